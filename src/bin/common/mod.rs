@@ -0,0 +1,101 @@
+//! Helpers shared by the command line tools.
+
+#![allow(dead_code)]
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use getopts::{Matches, Options};
+
+use ssdp::{FieldMap, IpVersionMode};
+use ssdp::message::Config;
+
+/// Options shared by the commands that use the network.
+pub fn network_options() -> Options {
+    let mut opts = Options::new();
+
+    opts.optflag("h", "help", "print this help");
+    opts.optopt("i", "interface", "only use the local interface with the address", "ADDR");
+    opts.optopt("p", "port", "multicast port (default 1900)", "PORT");
+    opts.optopt("", "ttl", "multicast time to live (default 2)", "HOPS");
+    opts.optflag("4", "ipv4", "only use IPv4");
+    opts.optflag("6", "ipv6", "only use IPv6");
+    opts
+}
+
+pub fn network_config(matches: &Matches) -> Result<Config, String> {
+    let defaults = Config::new();
+    let mut config = Config::new()
+        .set_port(try!(parse_number(matches, "port", defaults.port)))
+        .set_ttl(try!(parse_number(matches, "ttl", defaults.ttl)));
+
+    config = match (matches.opt_present("ipv4"), matches.opt_present("ipv6")) {
+        (true, true) => return Err("--ipv4 and --ipv6 are exclusive".to_owned()),
+        (true, false) => config.set_mode(IpVersionMode::V4Only),
+        (false, true) => config.set_mode(IpVersionMode::V6Only),
+        (false, false) => config,
+    };
+
+    if let Some(interface) = matches.opt_str("interface") {
+        let addr: IpAddr = try!(interface.parse().map_err(|_| format!("invalid interface '{}'", interface)));
+        config = config.set_interface(addr);
+    }
+
+    Ok(config)
+}
+
+/// Parse the arguments of the command, returning None if help was printed.
+///
+/// The command is named in the usage, such as `ssdp search`.
+pub fn parse_options(opts: &Options, args: &[String], command: &str) -> Result<Option<Matches>, String> {
+    // Help is checked first so that it is printed even when required options are missing
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", opts.usage(&format!("Usage: {} [options]", command)));
+        return Ok(None);
+    }
+
+    opts.parse(args).map(Some).map_err(|e| e.to_string())
+}
+
+pub fn parse_number<T: FromStr>(matches: &Matches, name: &str, default: T) -> Result<T, String> {
+    match matches.opt_str(name) {
+        Some(value) => value.parse().map_err(|_| format!("invalid --{} '{}'", name, value)),
+        None => Ok(default),
+    }
+}
+
+pub fn parse_field(value: &str) -> Result<FieldMap, String> {
+    FieldMap::new(value).ok_or_else(|| format!("invalid target '{}'", value))
+}
+
+/// Render the fields as a JSON object, with missing values as null.
+pub fn json_object(fields: &[(&str, Option<String>)]) -> String {
+    let members: Vec<String> = fields.iter()
+        .map(|&(name, ref value)| {
+            let value = value.as_ref().map_or("null".to_owned(), |value| json_string(value));
+            format!("{}:{}", json_string(&name.to_lowercase()), value)
+        })
+        .collect();
+
+    format!("{{{}}}", members.join(","))
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}
@@ -0,0 +1,365 @@
+//! Command line tool for searching, listening for, announcing and decoding
+//! SSDP messages.
+//!
+//! Built with the `cli` feature: `cargo run --features cli -- search --st ssdp:all`.
+
+extern crate getopts;
+extern crate ssdp;
+
+mod common;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use getopts::Options;
+
+use ssdp::{FieldMap, FromRawSSDP, SSDPResult};
+use ssdp::discovery::{self, DiscoverOptions};
+use ssdp::header::{CacheControl, CacheDirective, HeaderMut, HeaderRef, Location, MX, NT, NTS, ST, Server,
+                   USN};
+use ssdp::message::{Listen, Multicast, NotifyMessage, SearchRequest, SearchResponse};
+
+use common::{json_object, network_config, network_options, parse_field, parse_number, parse_options};
+
+const USAGE: &'static str = "Usage: ssdp <command> [options]
+
+Commands:
+    search      Search for devices and services
+    listen      Print messages sent to the multicast group
+    announce    Advertise a device or service until stopped
+    decode      Parse a raw message read from a file or stdin
+
+Run 'ssdp <command> --help' for the options of a command.";
+
+const DEFAULT_SEARCH_MX: u8 = 2;
+const DEFAULT_SEARCH_TIMEOUT_SECS: u64 = 3;
+const DEFAULT_MAX_AGE_SECS: u32 = 1800;
+
+/// Any message that can be received on the multicast group.
+#[derive(Debug)]
+enum Message {
+    Notify(NotifyMessage),
+    Search(SearchRequest),
+    Response(SearchResponse),
+}
+
+impl Message {
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Message::Notify(_) => "notify",
+            Message::Search(_) => "search",
+            Message::Response(_) => "response",
+        }
+    }
+
+    /// Value of the header as sent, if present.
+    fn header(&self, name: &str) -> Option<String> {
+        match *self {
+            Message::Notify(ref message) => raw_header(message, name),
+            Message::Search(ref message) => raw_header(message, name),
+            Message::Response(ref message) => raw_header(message, name),
+        }
+    }
+
+    /// Whether the notification type of a notify message, or the search target
+    /// of a search, is the target.
+    fn matches_target(&self, target: &FieldMap) -> bool {
+        match *self {
+            Message::Notify(ref message) => message.get::<NT>().map_or(false, |nt| nt.0 == *target),
+            Message::Search(ref message) => message.get::<ST>() == Some(&ST::Target(target.clone())),
+            Message::Response(ref message) => message.get::<ST>() == Some(&ST::Target(target.clone())),
+        }
+    }
+
+    fn to_json(&self, src: Option<SocketAddr>) -> String {
+        let mut fields = vec![("type", Some(self.type_name().to_owned()))];
+
+        fields.push(("source", src.map(|src| src.to_string())));
+        for &name in &["NTS", "NT", "ST", "USN", "LOCATION", "SERVER", "CACHE-CONTROL"] {
+            fields.push((name, self.header(name)));
+        }
+
+        json_object(&fields)
+    }
+}
+
+impl FromRawSSDP for Message {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<Message> {
+        NotifyMessage::raw_ssdp(bytes)
+            .map(Message::Notify)
+            .or_else(|_| SearchRequest::raw_ssdp(bytes).map(Message::Search))
+            .or_else(|_| SearchResponse::raw_ssdp(bytes).map(Message::Response))
+    }
+}
+
+/// Listener for every message sent to the multicast group.
+struct MessageListener;
+
+impl Listen for MessageListener {
+    type Message = Message;
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(|command| &command[..]) {
+        Some("search") => search(&args[1..]),
+        Some("listen") => listen(&args[1..]),
+        Some("announce") => announce(&args[1..]),
+        Some("decode") => decode(&args[1..]),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command '{}'\n\n{}", command, USAGE)),
+        None => Err(USAGE.to_owned()),
+    };
+
+    if let Err(msg) = result {
+        let _ = writeln!(io::stderr(), "ssdp: {}", msg);
+        process::exit(1);
+    }
+}
+
+fn search(args: &[String]) -> Result<(), String> {
+    let mut opts = network_options();
+    opts.optopt("s", "st", "search target (default ssdp:all)", "TARGET");
+    opts.optopt("m",
+                "mx",
+                &format!("seconds devices may delay responses (default {})", DEFAULT_SEARCH_MX),
+                "SECS");
+    opts.optopt("t",
+                "timeout",
+                &format!("seconds to wait for responses (default {})", DEFAULT_SEARCH_TIMEOUT_SECS),
+                "SECS");
+    opts.optflag("j", "json", "print responses as JSON");
+    let matches = match try!(parse_options(&opts, args, "ssdp search")) {
+        Some(matches) => matches,
+        None => return Ok(()),
+    };
+
+    let target = match matches.opt_str("st") {
+        Some(ref target) if target != "ssdp:all" => ST::Target(try!(parse_field(target))),
+        _ => ST::All,
+    };
+    let mx = try!(parse_number(&matches, "mx", DEFAULT_SEARCH_MX));
+    let timeout = try!(parse_number(&matches, "timeout", DEFAULT_SEARCH_TIMEOUT_SECS));
+    let options = DiscoverOptions::new()
+        .set_config(try!(network_config(&matches)))
+        .set_mx(MX(mx))
+        .set_timeout(Duration::new(timeout, 0));
+
+    let responses = try!(discovery::search(target, &options).map_err(|e| e.to_string()));
+
+    if matches.opt_present("json") {
+        let records: Vec<String> = responses.iter()
+            .map(|unique| {
+                let message = Message::Response(unique.message().clone());
+                message.to_json(unique.sources().first().cloned())
+            })
+            .collect();
+        println!("[{}]", records.join(",\n "));
+    } else {
+        let rows: Vec<Vec<String>> = responses.iter()
+            .map(|unique| {
+                let message = unique.message();
+                let source = unique.sources().first().map(|src| src.ip().to_string());
+
+                vec![source.unwrap_or_default(),
+                     raw_header(message, "ST").unwrap_or_default(),
+                     raw_header(message, "USN").unwrap_or_default(),
+                     raw_header(message, "LOCATION").unwrap_or_default()]
+            })
+            .collect();
+        print_table(&["SOURCE", "ST", "USN", "LOCATION"], &rows);
+    }
+
+    Ok(())
+}
+
+fn listen(args: &[String]) -> Result<(), String> {
+    let mut opts = network_options();
+    opts.optopt("T", "type", "only print messages of the type", "notify|search");
+    opts.optopt("n", "nt", "only print messages for the notification type or search target", "TARGET");
+    opts.optflag("v", "verbose", "print each message in full");
+    opts.optflag("j", "json", "print each message as a line of JSON");
+    let matches = match try!(parse_options(&opts, args, "ssdp listen")) {
+        Some(matches) => matches,
+        None => return Ok(()),
+    };
+
+    let message_type = match matches.opt_str("type") {
+        Some(ref kind) if kind == "notify" || kind == "search" => Some(kind.clone()),
+        Some(kind) => return Err(format!("unknown message type '{}'", kind)),
+        None => None,
+    };
+    let target = match matches.opt_str("nt") {
+        Some(ref target) => Some(try!(parse_field(target))),
+        None => None,
+    };
+
+    let config = try!(network_config(&matches));
+    let receiver = try!(MessageListener::listen_with_config(&config).map_err(|e| e.to_string()));
+
+    for (message, src) in receiver {
+        if message_type.as_ref().map_or(false, |kind| kind != message.type_name()) ||
+           target.as_ref().map_or(false, |target| !message.matches_target(target)) {
+            continue;
+        }
+
+        if matches.opt_present("json") {
+            println!("{}", message.to_json(Some(src)));
+        } else if matches.opt_present("verbose") {
+            println!("{} {}", src, message.type_name());
+            match message {
+                Message::Notify(ref message) => println!("{}", message),
+                Message::Search(ref message) => println!("{}", message),
+                Message::Response(ref message) => println!("{}", message),
+            }
+        } else {
+            let kind = message.header("NTS").unwrap_or_else(|| message.type_name().to_owned());
+            let target = message.header("NT").or_else(|| message.header("ST")).unwrap_or_default();
+
+            println!("{} {} {} {}",
+                     src,
+                     kind,
+                     target,
+                     message.header("USN").unwrap_or_default());
+        }
+    }
+
+    Ok(())
+}
+
+fn announce(args: &[String]) -> Result<(), String> {
+    let mut opts = network_options();
+    opts.reqopt("n", "nt", "notification type", "TARGET");
+    opts.reqopt("u", "usn", "unique service name", "USN");
+    opts.optopt("l", "location", "url of the device description", "URL");
+    opts.optopt("a",
+                "max-age",
+                &format!("seconds the advertisement is valid for (default {})", DEFAULT_MAX_AGE_SECS),
+                "SECS");
+    opts.optopt("S", "server", "value of the SERVER header", "SERVER");
+    opts.optflag("b", "byebye", "send a single byebye instead of advertising");
+    let matches = match try!(parse_options(&opts, args, "ssdp announce")) {
+        Some(matches) => matches,
+        None => return Ok(()),
+    };
+
+    let config = try!(network_config(&matches));
+    let max_age = try!(parse_number(&matches, "max-age", DEFAULT_MAX_AGE_SECS));
+
+    let mut message = NotifyMessage::new();
+    message.set_raw("NT", vec![matches.opt_str("nt").unwrap().into_bytes()]);
+    message.set_raw("USN", vec![matches.opt_str("usn").unwrap().into_bytes()]);
+    if message.get::<NT>().is_none() {
+        return Err("invalid notification type".to_owned());
+    }
+    if message.get::<USN>().is_none() {
+        return Err("invalid unique service name".to_owned());
+    }
+
+    if matches.opt_present("byebye") {
+        message.set(NTS::ByeBye);
+        return message.multicast_with_config(&config).map_err(|e| e.to_string());
+    }
+
+    let location = match matches.opt_str("location") {
+        Some(location) => location,
+        None => return Err("an alive announcement requires --location".to_owned()),
+    };
+    let server = matches.opt_str("server").unwrap_or_else(|| {
+        format!("{}/1.0 UPnP/1.1 ssdp/{}", env::consts::OS, env!("CARGO_PKG_VERSION"))
+    });
+
+    let mut alive = message.clone();
+    alive.set(NTS::Alive);
+    alive.set(Location(location));
+    alive.set(CacheControl(vec![CacheDirective::MaxAge(max_age)]));
+    alive.set(Server(server));
+
+    // Advertisements are repeated well within the max age until stdin is closed or a line is read
+    let (stop, stopped) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = io::stdin().read(&mut [0u8]);
+        let _ = stop.send(());
+    });
+    println!("Announcing, press Enter to send byebye and exit...");
+
+    let interval = Duration::new(max_age as u64 / 3 + 1, 0);
+    loop {
+        try!(alive.multicast_with_config(&config).map_err(|e| e.to_string()));
+
+        match stopped.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => (),
+            _ => break,
+        }
+    }
+
+    message.set(NTS::ByeBye);
+    message.multicast_with_config(&config).map_err(|e| e.to_string())
+}
+
+fn decode(args: &[String]) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help");
+    opts.optflag("j", "json", "print the message as JSON");
+    let matches = match try!(parse_options(&opts, args, "ssdp decode [FILE]")) {
+        Some(matches) => matches,
+        None => return Ok(()),
+    };
+
+    let mut bytes = Vec::new();
+    let read = match matches.free.first() {
+        Some(path) => File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)),
+        None => io::stdin().read_to_end(&mut bytes),
+    };
+    try!(read.map_err(|e| e.to_string()));
+
+    let message = try!(Message::raw_ssdp(&bytes).map_err(|e| format!("invalid message: {}", e)));
+    if matches.opt_present("json") {
+        println!("{}", message.to_json(None));
+    } else {
+        println!("Type: {}\n", message.type_name());
+        match message {
+            Message::Notify(ref message) => print!("{}", message),
+            Message::Search(ref message) => print!("{}", message),
+            Message::Response(ref message) => print!("{}", message),
+        }
+    }
+
+    Ok(())
+}
+
+fn raw_header<T: HeaderRef>(message: &T, name: &str) -> Option<String> {
+    message.get_raw(name)
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+fn print_table(columns: &[&str], rows: &[Vec<String>]) {
+    let widths: Vec<usize> = columns.iter()
+        .enumerate()
+        .map(|(index, column)| rows.iter().map(|row| row[index].len()).fold(column.len(), std::cmp::max))
+        .collect();
+
+    let format_row = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells.iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:1$}", cell, width))
+            .collect();
+        padded.join("  ").trim_end().to_owned()
+    };
+
+    println!("{}", format_row(columns.to_vec()));
+    for row in rows {
+        println!("{}", format_row(row.iter().map(|cell| &cell[..]).collect()));
+    }
+}
@@ -0,0 +1,450 @@
+//! Discovery daemon keeping a single cache of the devices and services on the
+//! network, for other processes to query over HTTP on the local host.
+//!
+//! The cache is fed by notify messages and by periodic searches. It is served as
+//! JSON on the following paths:
+//!
+//! * `GET /devices?st=TARGET`: devices and services matching the optional target.
+//! * `GET /events`: stream of changes to the cache, one JSON object per line.
+//!   Only a few streams are served at once, further ones being refused with
+//!   `503 Service Unavailable`.
+//! * `POST /search?st=TARGET&mx=SECS&timeout=SECS`: run a search, adding its
+//!   responses to the cache, and return the devices and services that answered.
+//!   Searches lasting longer than 30 seconds are refused with `400 Bad Request`.
+//! * `GET /metrics`: metrics on discovery and announcement activity in the
+//!   Prometheus text format, when built with the `metrics` feature.
+//!
+//! With `--minissdpd`, the daemon also stands in for minissdpd on its Unix
+//! socket: queries are answered from the cache, and services submitted on the
+//! socket are announced and answer searches until the daemon exits.
+//!
+//! Built with the `cli` feature: `cargo run --features cli --bin ssdpd`.
+
+extern crate getopts;
+extern crate hyper;
+extern crate ssdp;
+
+mod common;
+
+use std::env;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use hyper::Url;
+use hyper::header::ContentType;
+use hyper::method::Method;
+use hyper::net::Fresh;
+use hyper::server::{Handler, Request, Response, Server};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+
+use ssdp::cache::{CacheEvent, DeviceCache};
+use ssdp::discovery::{self, Device, DiscoverOptions};
+use ssdp::header::{HeaderRef, MX, NTS, ST, USN};
+use ssdp::message::{Config, Listen, Multicast, NotifyListener, ResponseScheduler, SearchRequest};
+use ssdp::minissdpd::{self, MiniSSDPDServer, Submission};
+#[cfg(feature = "metrics")]
+use ssdp::{Metrics, PrometheusExporter, PROMETHEUS_CONTENT_TYPE};
+
+use common::{json_string, network_config, network_options, parse_field, parse_number, parse_options};
+
+const DEFAULT_API_ADDR: &'static str = "127.0.0.1:1902";
+const DEFAULT_SEARCH_INTERVAL_SECS: u64 = 300;
+const DEFAULT_SEARCH_MX: u8 = 2;
+const DEFAULT_SEARCH_TIMEOUT_SECS: u64 = 3;
+/// Longest search, and largest MX, clients may ask for, each search holding an API thread.
+const MAX_SEARCH_SECS: u64 = 30;
+const API_THREADS: usize = 16;
+/// Number of event streams served at once, each holding an API thread for as
+/// long as it is open, leaving the rest of the threads for the other requests.
+const MAX_EVENT_STREAMS: usize = API_THREADS / 2;
+
+/// Time between checks for expired advertisements.
+const EXPIRE_INTERVAL_MS: u64 = 1000;
+/// Number of seconds the advertisements of submitted services are valid for.
+const SERVICE_MAX_AGE_SECS: u32 = 1800;
+
+/// Time between blank lines written to idle event streams to notice closed connections.
+const HEARTBEAT_INTERVAL_SECS: u64 = 30;
+
+/// Cache of the daemon, along with the event streams watching it.
+struct State {
+    cache: DeviceCache,
+    watchers: Vec<Sender<String>>,
+}
+
+impl State {
+    fn observe<T: HeaderRef>(&mut self, message: &T, src: SocketAddr) {
+        if let Some(event) = self.cache.observe(message, src) {
+            self.publish(&event);
+        }
+    }
+
+    fn publish(&mut self, event: &CacheEvent) {
+        let line = event_json(event);
+
+        // Watchers whose stream was closed are dropped
+        self.watchers.retain(|watcher| watcher.send(line.clone()).is_ok());
+    }
+}
+
+impl AsRef<DeviceCache> for State {
+    fn as_ref(&self) -> &DeviceCache {
+        &self.cache
+    }
+}
+
+type Shared = Arc<Mutex<State>>;
+
+/// Listener for the searches sent to the multicast group.
+struct SearchRequestListener;
+
+impl Listen for SearchRequestListener {
+    type Message = SearchRequest;
+}
+
+/// Handler of the requests made on the query API.
+struct Api {
+    state: Shared,
+    options: DiscoverOptions,
+    /// Number of event streams currently open.
+    streams: AtomicUsize,
+}
+
+/// Counts an event stream as open until dropped.
+struct StreamGuard<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for StreamGuard<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Handler for Api {
+    fn handle(&self, request: Request, mut response: Response<Fresh>) {
+        let url = match request.uri {
+            RequestUri::AbsolutePath(ref path) => Url::parse(&format!("http://localhost{}", path)).ok(),
+            _ => None,
+        };
+        let url = match url {
+            Some(url) => url,
+            None => return respond(response, StatusCode::BadRequest, String::new()),
+        };
+
+        match (&request.method, url.path()) {
+            (&Method::Get, "/devices") => {
+                let target = match search_target(&url) {
+                    Ok(target) => target,
+                    Err(msg) => return respond(response, StatusCode::BadRequest, json_error(&msg)),
+                };
+                let state = self.state.lock().unwrap();
+
+                respond(response, StatusCode::Ok, devices_json(state.cache.find(&target)));
+            }
+            (&Method::Get, "/events") => {
+                let _guard = StreamGuard(&self.streams);
+                if self.streams.fetch_add(1, Ordering::SeqCst) >= MAX_EVENT_STREAMS {
+                    let msg = "too many event streams are open";
+                    return respond(response, StatusCode::ServiceUnavailable, json_error(msg));
+                }
+
+                let (watcher, events) = mpsc::channel();
+                self.state.lock().unwrap().watchers.push(watcher);
+
+                response.headers_mut().set(ContentType::json());
+                let mut stream = match response.start() {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+
+                loop {
+                    let line = match events.recv_timeout(Duration::new(HEARTBEAT_INTERVAL_SECS, 0)) {
+                        Ok(line) => line,
+                        Err(RecvTimeoutError::Timeout) => String::new(),
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+
+                    if writeln!(stream, "{}", line).and_then(|_| stream.flush()).is_err() {
+                        break;
+                    }
+                }
+            }
+            (&Method::Post, "/search") => {
+                match self.search(&url) {
+                    Ok(devices) => respond(response, StatusCode::Ok, devices_json(devices.iter().collect())),
+                    Err(msg) => respond(response, StatusCode::BadRequest, json_error(&msg)),
+                }
+            }
+            #[cfg(feature = "metrics")]
+            (&Method::Get, "/metrics") => {
+                let mut exporter = PrometheusExporter::new(Vec::new());
+                if Metrics::export(&mut exporter).is_err() {
+                    return respond(response, StatusCode::InternalServerError, String::new());
+                }
+
+                response.headers_mut().set(ContentType(PROMETHEUS_CONTENT_TYPE.parse().unwrap()));
+                let _ = response.send(&exporter.into_inner());
+            }
+            (_, "/devices") | (_, "/events") | (_, "/search") | (_, "/metrics") => {
+                respond(response, StatusCode::MethodNotAllowed, String::new())
+            }
+            _ => respond(response, StatusCode::NotFound, String::new()),
+        }
+    }
+}
+
+impl Api {
+    /// Run a search on behalf of a client, adding the responses to the cache.
+    fn search(&self, url: &Url) -> Result<Vec<Device>, String> {
+        let target = try!(search_target(url));
+        let mut options = self.options.clone();
+
+        for (name, value) in url.query_pairs() {
+            match &name[..] {
+                "mx" => {
+                    let mx = match value.parse::<u8>().map(MX::new) {
+                        Ok(Ok(mx)) if mx.0 as u64 <= MAX_SEARCH_SECS => mx,
+                        _ => return Err(format!("invalid mx '{}', between 1 and {}", value, MAX_SEARCH_SECS)),
+                    };
+                    options = options.set_mx(mx);
+                }
+                "timeout" => {
+                    let secs = match value.parse::<u64>() {
+                        Ok(secs) if secs <= MAX_SEARCH_SECS => secs,
+                        _ => return Err(format!("invalid timeout '{}', at most {}", value, MAX_SEARCH_SECS)),
+                    };
+                    options = options.set_timeout(Duration::new(secs, 0));
+                }
+                _ => (),
+            }
+        }
+
+        let responses = try!(discovery::search(target, &options).map_err(|e| e.to_string()));
+        let mut state = self.state.lock().unwrap();
+
+        Ok(responses.iter()
+            .filter_map(|unique| {
+                for src in unique.sources() {
+                    state.observe(unique.message(), *src);
+                }
+
+                let interfaces: Vec<IpAddr> = unique.local_addrs().iter().map(SocketAddr::ip).collect();
+                Device::from_message(unique.message(), unique.sources(), &interfaces)
+            })
+            .collect())
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if let Err(msg) = run(&args) {
+        let _ = writeln!(io::stderr(), "ssdpd: {}", msg);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut opts = network_options();
+    opts.optopt("a",
+                "api",
+                &format!("local address to serve the query API on (default {})", DEFAULT_API_ADDR),
+                "ADDR");
+    opts.optopt("s",
+                "search-interval",
+                &format!("seconds between searches for all devices, 0 to disable (default {})",
+                         DEFAULT_SEARCH_INTERVAL_SECS),
+                "SECS");
+    opts.optflagopt("m",
+                    "minissdpd",
+                    &format!("serve the minissdpd protocol on the socket (default {})",
+                             minissdpd::DEFAULT_SOCKET_PATH),
+                    "PATH");
+    let matches = match try!(parse_options(&opts, args, "ssdpd")) {
+        Some(matches) => matches,
+        None => return Ok(()),
+    };
+
+    let config = try!(network_config(&matches));
+    let api_addr: SocketAddr = try!(parse_number(&matches, "api", DEFAULT_API_ADDR.parse().unwrap()));
+    let search_interval = try!(parse_number(&matches, "search-interval", DEFAULT_SEARCH_INTERVAL_SECS));
+    let options = DiscoverOptions::new()
+        .set_config(config.clone())
+        .set_mx(MX(DEFAULT_SEARCH_MX))
+        .set_timeout(Duration::new(DEFAULT_SEARCH_TIMEOUT_SECS, 0));
+
+    let state = Arc::new(Mutex::new(State {
+        cache: DeviceCache::new(),
+        watchers: Vec::new(),
+    }));
+
+    let notifications = try!(NotifyListener::listen_with_config(&config).map_err(|e| e.to_string()));
+    let listened = state.clone();
+    thread::spawn(move || for (message, src) in notifications {
+        listened.lock().unwrap().observe(&message, src);
+    });
+
+    let expiring = state.clone();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(EXPIRE_INTERVAL_MS));
+
+        let mut state = expiring.lock().unwrap();
+        for event in state.cache.expire() {
+            state.publish(&event);
+        }
+    });
+
+    if search_interval > 0 {
+        let api = Api {
+            state: state.clone(),
+            options: options.clone(),
+            streams: AtomicUsize::new(0),
+        };
+        let all = Url::parse("http://localhost/search").unwrap();
+
+        thread::spawn(move || loop {
+            if let Err(msg) = api.search(&all) {
+                let _ = writeln!(io::stderr(), "ssdpd: periodic search failed: {}", msg);
+            }
+            thread::sleep(Duration::new(search_interval, 0));
+        });
+    }
+
+    // Kept until the daemon exits, the socket is removed once the server is dropped
+    let _minissdpd = if matches.opt_present("minissdpd") {
+        let path = matches.opt_str("minissdpd").unwrap_or_else(|| minissdpd::DEFAULT_SOCKET_PATH.to_owned());
+        Some(try!(serve_minissdpd(&path, state.clone(), &config)))
+    } else {
+        None
+    };
+
+    let server = try!(Server::http(api_addr).map_err(|e| e.to_string()));
+    let api = Api {
+        state: state,
+        options: options,
+        streams: AtomicUsize::new(0),
+    };
+    try!(server.handle_threads(api, API_THREADS).map_err(|e| e.to_string()));
+
+    Ok(())
+}
+
+/// Stand in for minissdpd on the socket path, announcing the services submitted
+/// to it and answering searches for them.
+fn serve_minissdpd(path: &str, state: Shared, config: &Config) -> Result<Arc<MiniSSDPDServer>, String> {
+    let announcing = config.clone();
+    let server = try!(MiniSSDPDServer::bind(path,
+                                            state,
+                                            move |submission: &Submission| announce(submission, &announcing))
+        .map_err(|e| format!("failed to serve minissdpd on {}: {}", path, e)));
+    let server = Arc::new(server);
+
+    let searches = try!(SearchRequestListener::listen_with_config(config).map_err(|e| e.to_string()));
+    let answering = server.clone();
+    thread::spawn(move || for (request, src) in searches {
+        let target = match request.get::<ST>() {
+            Some(target) => target.clone(),
+            None => continue,
+        };
+        let responses: Vec<_> = answering.services()
+            .iter()
+            .filter(|service| service.matches(&target))
+            .map(|service| service.search_response(SERVICE_MAX_AGE_SECS))
+            .collect();
+        if responses.is_empty() {
+            continue;
+        }
+
+        if let Err(e) = ResponseScheduler::default().respond(&request, src, responses) {
+            let _ = writeln!(io::stderr(), "ssdpd: failed to answer search from {}: {}", src, e);
+        }
+    });
+
+    // Advertisements are renewed well before they expire
+    let renewing = server.clone();
+    let config = config.clone();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::new(SERVICE_MAX_AGE_SECS as u64 / 3, 0));
+
+        for service in renewing.services() {
+            announce(&service, &config);
+        }
+    });
+
+    Ok(server)
+}
+
+fn announce(service: &Submission, config: &Config) {
+    if let Err(e) = service.notify(NTS::Alive, SERVICE_MAX_AGE_SECS).multicast_with_config(config) {
+        let _ = writeln!(io::stderr(), "ssdpd: failed to announce {}: {}", service.location, e);
+    }
+}
+
+fn respond(mut response: Response<Fresh>, status: StatusCode, body: String) {
+    *response.status_mut() = status;
+    if !body.is_empty() {
+        response.headers_mut().set(ContentType::json());
+    }
+
+    let _ = response.send(body.as_bytes());
+}
+
+/// Search target given by the `st` parameter of the url, all devices if missing.
+fn search_target(url: &Url) -> Result<ST, String> {
+    match url.query_pairs().find(|&(ref name, _)| name == "st") {
+        Some((_, ref target)) if target != "ssdp:all" => parse_field(target).map(ST::Target),
+        _ => Ok(ST::All),
+    }
+}
+
+fn event_json(event: &CacheEvent) -> String {
+    let kind = match *event {
+        CacheEvent::Added(_) => "added",
+        CacheEvent::Updated(_) => "updated",
+        CacheEvent::Removed(_) => "removed",
+        CacheEvent::Expired(_) => "expired",
+    };
+
+    format!("{{\"event\":{},\"device\":{}}}", json_string(kind), device_json(event.device()))
+}
+
+fn devices_json(devices: Vec<&Device>) -> String {
+    let devices: Vec<String> = devices.into_iter().map(device_json).collect();
+
+    format!("[{}]", devices.join(","))
+}
+
+fn device_json(device: &Device) -> String {
+    let usn = match device.usn {
+        USN(ref uuid, Some(ref kind)) => format!("{}::{}", uuid, kind),
+        USN(ref uuid, None) => uuid.to_string(),
+    };
+    let sources: Vec<String> = device.sources.iter().map(|src| json_string(&src.to_string())).collect();
+
+    format!("{{\"usn\":{},\"uuid\":{},\"device_type\":{},\"service_type\":{},\"location\":{},\"server\":{},\
+             \"max_age\":{},\"boot_id\":{},\"sources\":[{}]}}",
+            json_string(&usn),
+            json_optional(device.uuid.as_ref()),
+            json_optional(device.device_type.as_ref()),
+            json_optional(device.service_type.as_ref()),
+            json_optional(device.location.as_ref()),
+            json_optional(device.server.as_ref()),
+            device.max_age.map_or("null".to_owned(), |max_age| max_age.to_string()),
+            device.boot_id.map_or("null".to_owned(), |boot_id| boot_id.0.to_string()),
+            sources.join(","))
+}
+
+fn json_optional<T: Display>(value: Option<T>) -> String {
+    value.map_or("null".to_owned(), |value| json_string(&value.to_string()))
+}
+
+fn json_error(msg: &str) -> String {
+    format!("{{\"error\":{}}}", json_string(msg))
+}
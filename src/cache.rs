@@ -0,0 +1,281 @@
+//! Cache of the devices and services advertised on the network.
+//!
+//! A `DeviceCache` is fed notify messages and search responses, keeping one
+//! `Device` for every unique service name until it says byebye or its
+//! advertisement expires. Changes to the cache are returned as `CacheEvent`s so
+//! that they can be passed on to anyone watching the cache.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use FieldMap;
+use discovery::Device;
+use header::{HeaderRef, NTS, ST, USN};
+#[cfg(feature = "metrics")]
+use metrics;
+
+/// Number of seconds an advertisement without a max age is kept for.
+const DEFAULT_MAX_AGE_SECS: u32 = 1800;
+
+/// Enumerates the changes made to a `DeviceCache`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CacheEvent {
+    /// A device or service was seen for the first time.
+    Added(Device),
+    /// The location, server or boot instance of a device or service changed.
+    Updated(Device),
+    /// A device or service said byebye.
+    Removed(Device),
+    /// The advertisement of a device or service was not renewed in time.
+    Expired(Device),
+}
+
+impl CacheEvent {
+    /// Device or service the event is about.
+    pub fn device(&self) -> &Device {
+        match *self {
+            CacheEvent::Added(ref device) |
+            CacheEvent::Updated(ref device) |
+            CacheEvent::Removed(ref device) |
+            CacheEvent::Expired(ref device) => device,
+        }
+    }
+}
+
+/// Devices and services currently advertised on the network, keyed by their
+/// unique service name.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceCache {
+    entries: HashMap<USN, Entry>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    device: Device,
+    expires: Instant,
+}
+
+impl DeviceCache {
+    /// Construct an empty DeviceCache.
+    pub fn new() -> DeviceCache {
+        DeviceCache { entries: HashMap::new() }
+    }
+
+    /// Record a notify message or search response received from the source address.
+    pub fn observe<T: HeaderRef>(&mut self, message: &T, src: SocketAddr) -> Option<CacheEvent> {
+        self.observe_at(message, src, Instant::now())
+    }
+
+    /// Record a message as if it was received at the given point in time.
+    ///
+    /// Useful when the messages come from a recording rather than the network.
+    pub fn observe_at<T>(&mut self, message: &T, src: SocketAddr, now: Instant) -> Option<CacheEvent>
+        where T: HeaderRef
+    {
+        let event = self.update(message, src, now);
+        #[cfg(feature = "metrics")]
+        metrics::cache_changed(self.len(), 0);
+
+        event
+    }
+
+    /// Remove every device and service whose advertisement has expired.
+    pub fn expire(&mut self) -> Vec<CacheEvent> {
+        self.expire_at(Instant::now())
+    }
+
+    /// Remove every device and service whose advertisement expired before the
+    /// given point in time.
+    pub fn expire_at(&mut self, now: Instant) -> Vec<CacheEvent> {
+        let expired: Vec<USN> = self.entries
+            .iter()
+            .filter(|&(_, entry)| entry.expires <= now)
+            .map(|(usn, _)| usn.clone())
+            .collect();
+
+        let events: Vec<CacheEvent> = expired.into_iter()
+            .filter_map(|usn| self.entries.remove(&usn))
+            .map(|entry| CacheEvent::Expired(entry.device))
+            .collect();
+        #[cfg(feature = "metrics")]
+        metrics::cache_changed(self.len(), events.len());
+
+        events
+    }
+
+    /// Device or service with the unique service name.
+    pub fn get(&self, usn: &USN) -> Option<&Device> {
+        self.entries.get(usn).map(|entry| &entry.device)
+    }
+
+    /// Point in time the advertisement of the device or service expires.
+    pub fn expires(&self, usn: &USN) -> Option<Instant> {
+        self.entries.get(usn).map(|entry| entry.expires)
+    }
+
+    /// Every device and service in the cache, in no particular order.
+    pub fn devices(&self) -> Vec<&Device> {
+        self.entries.values().map(|entry| &entry.device).collect()
+    }
+
+    /// Devices and services that would answer a search for the target.
+    pub fn find(&self, target: &ST) -> Vec<&Device> {
+        self.entries
+            .values()
+            .map(|entry| &entry.device)
+            .filter(|device| match *target {
+                ST::All => true,
+                ST::Target(ref field) => matches_target(&device.usn, field),
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Record the message, returning the change made to the cache.
+    fn update<T: HeaderRef>(&mut self, message: &T, src: SocketAddr, now: Instant) -> Option<CacheEvent> {
+        let mut device = match Device::from_message(message, &[src], &[]) {
+            Some(device) => device,
+            None => return None,
+        };
+
+        if message.get::<NTS>() == Some(&NTS::ByeBye) {
+            return self.entries.remove(&device.usn).map(|entry| CacheEvent::Removed(entry.device));
+        }
+
+        let max_age = device.max_age.unwrap_or(DEFAULT_MAX_AGE_SECS);
+        let expires = now + Duration::new(max_age as u64, 0);
+
+        if let Some(entry) = self.entries.get_mut(&device.usn) {
+            let changed = entry.device.location != device.location ||
+                          entry.device.server != device.server ||
+                          entry.device.boot_id != device.boot_id;
+
+            // Sources the device was seen from before are kept
+            for source in entry.device.sources.drain(..) {
+                if !device.sources.contains(&source) {
+                    device.sources.push(source);
+                }
+            }
+            entry.device = device;
+            entry.expires = expires;
+
+            return if changed {
+                Some(CacheEvent::Updated(entry.device.clone()))
+            } else {
+                None
+            };
+        }
+
+        self.entries.insert(device.usn.clone(),
+                            Entry {
+                                device: device.clone(),
+                                expires: expires,
+                            });
+        Some(CacheEvent::Added(device))
+    }
+}
+
+impl AsRef<DeviceCache> for DeviceCache {
+    fn as_ref(&self) -> &DeviceCache {
+        self
+    }
+}
+
+/// Whether the advertisement with the unique service name is for the target.
+fn matches_target(usn: &USN, target: &FieldMap) -> bool {
+    match usn.1 {
+        Some(ref kind) => kind == target,
+        // Advertisements of the device itself only carry its uuid
+        None => usn.0 == *target,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    use FieldMap;
+    use header::{CacheControl, CacheDirective, HeaderMut, Location, NT, NTS, ST, USN};
+    use message::NotifyMessage;
+    use super::{CacheEvent, DeviceCache};
+
+    fn notify(nts: NTS, location: &str) -> NotifyMessage {
+        let mut message = NotifyMessage::new();
+
+        message.set(nts);
+        message.set(NT(FieldMap::upnp("rootdevice")));
+        message.set(USN(FieldMap::uuid("device-1"), Some(FieldMap::upnp("rootdevice"))));
+        message.set(Location(location.to_owned()));
+        message.set(CacheControl(vec![CacheDirective::MaxAge(60)]));
+        message
+    }
+
+    fn source() -> SocketAddr {
+        "192.168.1.10:1900".parse().unwrap()
+    }
+
+    #[test]
+    fn positive_add_update_remove() {
+        let mut cache = DeviceCache::new();
+        let alive = notify(NTS::Alive, "http://192.168.1.10/description.xml");
+
+        match cache.observe(&alive, source()) {
+            Some(CacheEvent::Added(ref device)) => assert_eq!(device.max_age, Some(60)),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert!(cache.observe(&alive, source()).is_none());
+
+        let moved = notify(NTS::Alive, "http://192.168.1.10/other.xml");
+        match cache.observe(&moved, source()) {
+            Some(CacheEvent::Updated(ref device)) => {
+                assert_eq!(device.location, Some("http://192.168.1.10/other.xml".to_owned()))
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        match cache.observe(&notify(NTS::ByeBye, ""), source()) {
+            Some(CacheEvent::Removed(_)) => (),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn positive_expire() {
+        let mut cache = DeviceCache::new();
+        let now = Instant::now();
+        cache.observe_at(&notify(NTS::Alive, "http://192.168.1.10/"), source(), now);
+
+        assert!(cache.expire_at(now + Duration::new(59, 0)).is_empty());
+        assert_eq!(cache.expire_at(now + Duration::new(60, 0)).len(), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn positive_find() {
+        let mut cache = DeviceCache::new();
+        cache.observe(&notify(NTS::Alive, "http://192.168.1.10/"), source());
+
+        assert_eq!(cache.find(&ST::All).len(), 1);
+        assert_eq!(cache.find(&ST::Target(FieldMap::upnp("rootdevice"))).len(), 1);
+        assert!(cache.find(&ST::Target(FieldMap::uuid("device-1"))).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_byebye_unknown_device() {
+        let mut cache = DeviceCache::new();
+
+        cache.observe(&notify(NTS::ByeBye, ""), source()).unwrap();
+    }
+}
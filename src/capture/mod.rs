@@ -0,0 +1,93 @@
+//! Reading and writing packet captures of SSDP traffic.
+//!
+//! A `CaptureReader` reads pcap and pcapng files, such as those written by
+//! tcpdump or Wireshark, and parses the UDP datagrams sent to or from the SSDP
+//! port with the same parser used for messages received from the network.
+//!
+//! A `CaptureWriter` writes messages to a pcapng file, either one at a time or
+//! by passing on the messages yielded from a receiver with `CaptureWriter::tee`.
+//!
+//! A `Replay` plays the datagrams from a capture, or from a recording in the
+//! line based format read by a `TextReader`, back into a receiver or device
+//! cache, paced in real time or faster.
+
+use std::fmt::{self, Display, Formatter};
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+use hyper::header::Headers;
+
+use {SSDPErrorKind, SSDPResult};
+use message::{MessageType, NotifyMessage, SearchRequest, SearchResponse};
+use receiver::FromRawSSDP;
+
+mod packet;
+mod reader;
+mod replay;
+mod text;
+mod writer;
+
+pub use self::reader::{CaptureReader, Datagrams};
+pub use self::replay::{Replay, ReplayClock, Replayed};
+pub use self::text::TextReader;
+pub use self::writer::{CaptureWriter, Tee};
+
+/// Enumerates the messages found in a capture.
+#[derive(Clone, Debug)]
+pub enum CapturedMessage {
+    Notify(NotifyMessage),
+    Search(SearchRequest),
+    Response(SearchResponse),
+}
+
+impl FromRawSSDP for CapturedMessage {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<CapturedMessage> {
+        // The start line tells the messages apart without parsing them more than once
+        if bytes.starts_with(b"NOTIFY ") {
+            NotifyMessage::raw_ssdp(bytes).map(CapturedMessage::Notify)
+        } else if bytes.starts_with(b"M-SEARCH ") {
+            SearchRequest::raw_ssdp(bytes).map(CapturedMessage::Search)
+        } else if bytes.starts_with(b"HTTP/") {
+            SearchResponse::raw_ssdp(bytes).map(CapturedMessage::Response)
+        } else {
+            Err(SSDPErrorKind::InvalidHttp(bytes.to_vec()).into())
+        }
+    }
+
+    fn message_headers(&self) -> Option<(MessageType, &Headers)> {
+        match *self {
+            CapturedMessage::Notify(ref message) => message.message_headers(),
+            CapturedMessage::Search(ref message) => message.message_headers(),
+            CapturedMessage::Response(ref message) => message.message_headers(),
+        }
+    }
+}
+
+impl Display for CapturedMessage {
+    /// Render the message as it would be sent.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            CapturedMessage::Notify(ref message) => Display::fmt(message, f),
+            CapturedMessage::Search(ref message) => Display::fmt(message, f),
+            CapturedMessage::Response(ref message) => Display::fmt(message, f),
+        }
+    }
+}
+
+/// A message found in a capture, along with when and between whom it was sent.
+#[derive(Clone, Debug)]
+pub struct Captured {
+    pub timestamp: SystemTime,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub message: CapturedMessage,
+}
+
+/// A UDP datagram found in a capture, before it is parsed as a message.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CapturedDatagram {
+    pub timestamp: SystemTime,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub payload: Vec<u8>,
+}
@@ -0,0 +1,345 @@
+//! Decoding and encoding of the link, network and transport layers wrapping
+//! the datagrams found in a capture.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// BSD loopback encapsulation, a host order address family followed by the packet.
+pub const LINKTYPE_NULL: u16 = 0;
+/// Ethernet, possibly with 802.1Q tags.
+pub const LINKTYPE_ETHERNET: u16 = 1;
+/// Raw IPv4 or IPv6 packets.
+pub const LINKTYPE_RAW: u16 = 101;
+/// Linux cooked capture, as captured on the `any` interface.
+pub const LINKTYPE_LINUX_SLL: u16 = 113;
+/// Raw IPv4 packets.
+pub const LINKTYPE_IPV4: u16 = 228;
+/// Raw IPv6 packets.
+pub const LINKTYPE_IPV6: u16 = 229;
+/// Linux cooked capture, version 2.
+pub const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+/// IPv6 extension headers that can be skipped to find the transport header,
+/// fragments are not among them.
+const IPV6_EXTENSION_HEADERS: [u8; 4] = [0, 43, 60, 135];
+
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const UDP_HEADER_LEN: usize = 8;
+
+/// Hop limit given to the packets written to a capture.
+const DEFAULT_TTL: u8 = 4;
+
+/// A UDP datagram found in a captured packet.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Datagram<'a> {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub payload: &'a [u8],
+}
+
+/// Find the UDP datagram in a packet of the link type.
+///
+/// Returns None for packets that do not carry a whole UDP datagram, such as
+/// other protocols or fragments.
+pub fn decode<'a>(link_type: u16, packet: &'a [u8]) -> Option<Datagram<'a>> {
+    match link_type {
+        LINKTYPE_NULL => {
+            // The address family is in the byte order of the capturing machine, and small
+            // enough to fit in either the first or the last byte
+            let family = match packet.get(..4) {
+                Some(family) => family[0] | family[3],
+                None => return None,
+            };
+            match family {
+                2 => decode_ipv4(&packet[4..]),
+                24 | 28 | 30 => decode_ipv6(&packet[4..]),
+                _ => None,
+            }
+        }
+        LINKTYPE_ETHERNET => decode_ethernet(packet),
+        LINKTYPE_RAW => decode_ip(packet),
+        LINKTYPE_IPV4 => decode_ipv4(packet),
+        LINKTYPE_IPV6 => decode_ipv6(packet),
+        LINKTYPE_LINUX_SLL if packet.len() >= 16 => decode_ethertype(read_u16(packet, 14), &packet[16..]),
+        LINKTYPE_LINUX_SLL2 if packet.len() >= 20 => decode_ethertype(read_u16(packet, 0), &packet[20..]),
+        _ => None,
+    }
+}
+
+/// Wrap the payload in raw IP and UDP headers, as captured with `LINKTYPE_RAW`.
+///
+/// Returns None if the addresses are not of the same version.
+pub fn encode_raw(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Option<Vec<u8>> {
+    let udp_len = UDP_HEADER_LEN + payload.len();
+    let mut packet = Vec::with_capacity(IPV6_HEADER_LEN + udp_len);
+
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => {
+            let total_len = (IPV4_HEADER_LEN + udp_len) as u16;
+
+            packet.extend_from_slice(&[0x45, 0]);
+            push_u16(&mut packet, total_len);
+            packet.extend_from_slice(&[0, 0, 0, 0, DEFAULT_TTL, IP_PROTOCOL_UDP, 0, 0]);
+            packet.extend_from_slice(&src_ip.octets());
+            packet.extend_from_slice(&dst_ip.octets());
+
+            let checksum = !fold(sum(&packet));
+            packet[10] = (checksum >> 8) as u8;
+            packet[11] = checksum as u8;
+        }
+        (IpAddr::V6(src_ip), IpAddr::V6(dst_ip)) => {
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            push_u16(&mut packet, udp_len as u16);
+            packet.extend_from_slice(&[IP_PROTOCOL_UDP, DEFAULT_TTL]);
+            packet.extend_from_slice(&src_ip.octets());
+            packet.extend_from_slice(&dst_ip.octets());
+        }
+        _ => return None,
+    }
+
+    let udp_start = packet.len();
+    push_u16(&mut packet, src.port());
+    push_u16(&mut packet, dst.port());
+    push_u16(&mut packet, udp_len as u16);
+    push_u16(&mut packet, 0);
+    packet.extend_from_slice(payload);
+
+    let checksum = udp_checksum(src.ip(), dst.ip(), &packet[udp_start..]);
+    packet[udp_start + 6] = (checksum >> 8) as u8;
+    packet[udp_start + 7] = checksum as u8;
+
+    Some(packet)
+}
+
+fn decode_ethernet<'a>(frame: &'a [u8]) -> Option<Datagram<'a>> {
+    if frame.len() < 14 {
+        return None;
+    }
+
+    let mut offset = 12;
+    let mut ethertype = read_u16(frame, offset);
+    // Skip any number of 802.1Q tags
+    while (ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ) && frame.len() >= offset + 6 {
+        offset += 4;
+        ethertype = read_u16(frame, offset);
+    }
+
+    decode_ethertype(ethertype, &frame[offset + 2..])
+}
+
+fn decode_ethertype<'a>(ethertype: u16, packet: &'a [u8]) -> Option<Datagram<'a>> {
+    match ethertype {
+        ETHERTYPE_IPV4 => decode_ipv4(packet),
+        ETHERTYPE_IPV6 => decode_ipv6(packet),
+        _ => None,
+    }
+}
+
+fn decode_ip<'a>(packet: &'a [u8]) -> Option<Datagram<'a>> {
+    match packet.first().map(|byte| byte >> 4) {
+        Some(4) => decode_ipv4(packet),
+        Some(6) => decode_ipv6(packet),
+        _ => None,
+    }
+}
+
+fn decode_ipv4<'a>(packet: &'a [u8]) -> Option<Datagram<'a>> {
+    if packet.len() < IPV4_HEADER_LEN || packet[0] >> 4 != 4 {
+        return None;
+    }
+
+    let header_len = (packet[0] & 0x0f) as usize * 4;
+    let total_len = read_u16(packet, 2) as usize;
+    let fragmented = read_u16(packet, 6) & 0x3fff != 0;
+    if packet[9] != IP_PROTOCOL_UDP || fragmented || header_len < IPV4_HEADER_LEN || total_len < header_len ||
+       packet.len() < header_len {
+        return None;
+    }
+
+    let src = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let dst = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+    // Captures may be padded past the end of the packet, or truncated before it
+    let end = total_len.min(packet.len());
+
+    decode_udp(IpAddr::V4(src), IpAddr::V4(dst), &packet[header_len..end])
+}
+
+fn decode_ipv6<'a>(packet: &'a [u8]) -> Option<Datagram<'a>> {
+    if packet.len() < IPV6_HEADER_LEN || packet[0] >> 4 != 6 {
+        return None;
+    }
+
+    let mut addrs = [0u8; 32];
+    addrs.copy_from_slice(&packet[8..40]);
+    let end = (IPV6_HEADER_LEN + read_u16(packet, 4) as usize).min(packet.len());
+
+    let mut next_header = packet[6];
+    let mut offset = IPV6_HEADER_LEN;
+    while IPV6_EXTENSION_HEADERS.contains(&next_header) {
+        if end < offset + 8 {
+            return None;
+        }
+
+        next_header = packet[offset];
+        offset += (packet[offset + 1] as usize + 1) * 8;
+    }
+    if next_header != IP_PROTOCOL_UDP || end < offset {
+        return None;
+    }
+
+    decode_udp(IpAddr::V6(ipv6_from(&addrs[..16])),
+               IpAddr::V6(ipv6_from(&addrs[16..])),
+               &packet[offset..end])
+}
+
+fn decode_udp<'a>(src: IpAddr, dst: IpAddr, segment: &'a [u8]) -> Option<Datagram<'a>> {
+    if segment.len() < UDP_HEADER_LEN {
+        return None;
+    }
+
+    let len = read_u16(segment, 4) as usize;
+    if len < UDP_HEADER_LEN || len > segment.len() {
+        return None;
+    }
+
+    Some(Datagram {
+        src: SocketAddr::new(src, read_u16(segment, 0)),
+        dst: SocketAddr::new(dst, read_u16(segment, 2)),
+        payload: &segment[UDP_HEADER_LEN..len],
+    })
+}
+
+fn udp_checksum(src: IpAddr, dst: IpAddr, segment: &[u8]) -> u16 {
+    let mut pseudo = Vec::with_capacity(40);
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            pseudo.extend_from_slice(&src.octets());
+            pseudo.extend_from_slice(&dst.octets());
+            pseudo.extend_from_slice(&[0, IP_PROTOCOL_UDP]);
+            push_u16(&mut pseudo, segment.len() as u16);
+        }
+        (src, dst) => {
+            pseudo.extend_from_slice(&ipv6_octets(src));
+            pseudo.extend_from_slice(&ipv6_octets(dst));
+            push_u16(&mut pseudo, 0);
+            push_u16(&mut pseudo, segment.len() as u16);
+            pseudo.extend_from_slice(&[0, 0, 0, IP_PROTOCOL_UDP]);
+        }
+    }
+
+    // A checksum of zero means no checksum, so it is sent as all ones instead
+    match !fold(sum(&pseudo) + sum(segment)) {
+        0 => 0xffff,
+        checksum => checksum,
+    }
+}
+
+/// Sum of the big endian 16 bit words in the data, padded with a zero byte.
+fn sum(data: &[u8]) -> u32 {
+    data.chunks(2)
+        .map(|word| ((word[0] as u32) << 8) | word.get(1).cloned().unwrap_or(0) as u32)
+        .sum()
+}
+
+/// Fold a sum into a 16 bit ones' complement sum.
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    sum as u16
+}
+
+fn ipv6_from(octets: &[u8]) -> Ipv6Addr {
+    let mut segments = [0u16; 8];
+    for (index, segment) in segments.iter_mut().enumerate() {
+        *segment = read_u16(octets, index * 2);
+    }
+
+    Ipv6Addr::new(segments[0],
+                  segments[1],
+                  segments[2],
+                  segments[3],
+                  segments[4],
+                  segments[5],
+                  segments[6],
+                  segments[7])
+}
+
+fn ipv6_octets(addr: IpAddr) -> [u8; 16] {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped().octets(),
+        IpAddr::V6(addr) => addr.octets(),
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    ((data[offset] as u16) << 8) | data[offset + 1] as u16
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.push((value >> 8) as u8);
+    buffer.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::{decode, encode_raw, LINKTYPE_ETHERNET, LINKTYPE_RAW};
+
+    #[test]
+    fn positive_raw_round_trip() {
+        let addrs = [("192.168.1.10:1900", "239.255.255.250:1900"), ("[fe80::1]:50000", "[ff02::c]:1900")];
+
+        for &(src, dst) in &addrs {
+            let src: SocketAddr = src.parse().unwrap();
+            let dst: SocketAddr = dst.parse().unwrap();
+            let packet = encode_raw(src, dst, b"payload").unwrap();
+
+            let datagram = decode(LINKTYPE_RAW, &packet).unwrap();
+            assert_eq!((datagram.src, datagram.dst, datagram.payload), (src, dst, &b"payload"[..]));
+        }
+    }
+
+    #[test]
+    fn positive_ipv4_checksum() {
+        let packet = encode_raw("192.168.1.10:1900".parse().unwrap(),
+                                "239.255.255.250:1900".parse().unwrap(),
+                                b"")
+            .unwrap();
+
+        // The checksum of a header with its checksum filled in folds to all ones
+        assert_eq!(super::fold(super::sum(&packet[..20])), 0xffff);
+    }
+
+    #[test]
+    fn positive_ethernet_vlan() {
+        let packet = encode_raw("192.168.1.10:1900".parse().unwrap(),
+                                "239.255.255.250:1900".parse().unwrap(),
+                                b"payload")
+            .unwrap();
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x81, 0x00, 0x00, 0x0a, 0x08, 0x00]);
+        frame.extend_from_slice(&packet);
+
+        assert_eq!(decode(LINKTYPE_ETHERNET, &frame).unwrap().payload, b"payload");
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_truncated_udp() {
+        let packet = encode_raw("192.168.1.10:1900".parse().unwrap(),
+                                "239.255.255.250:1900".parse().unwrap(),
+                                b"payload")
+            .unwrap();
+
+        decode(LINKTYPE_RAW, &packet[..30]).unwrap();
+    }
+}
@@ -0,0 +1,558 @@
+//! Reading of pcap and pcapng files.
+
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use {SSDPErrorKind, SSDPResult};
+use message::UPNP_MULTICAST_PORT;
+use receiver::FromRawSSDP;
+use super::{Captured, CapturedDatagram, CapturedMessage};
+use super::packet;
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+const PCAP_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+pub const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+pub const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+pub const BLOCK_INTERFACE_DESCRIPTION: u32 = 1;
+const BLOCK_PACKET: u32 = 2;
+const BLOCK_SIMPLE_PACKET: u32 = 3;
+pub const BLOCK_ENHANCED_PACKET: u32 = 6;
+
+pub const OPTION_END: u16 = 0;
+pub const OPTION_IF_TSRESOL: u16 = 9;
+
+/// Timestamp units per second when an interface does not give its resolution.
+const DEFAULT_UNITS: u64 = 1_000_000;
+/// Largest record or block read, well past the largest packet that can be captured.
+const MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+/// Byte order of the fields in a capture, set by the writer of the file.
+#[derive(Copy, Clone, Debug)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn u16(&self, data: &[u8]) -> u16 {
+        match *self {
+            ByteOrder::Little => data[0] as u16 | (data[1] as u16) << 8,
+            ByteOrder::Big => (data[0] as u16) << 8 | data[1] as u16,
+        }
+    }
+
+    fn u32(&self, data: &[u8]) -> u32 {
+        match *self {
+            ByteOrder::Little => (self.u16(&data[2..]) as u32) << 16 | self.u16(data) as u32,
+            ByteOrder::Big => (self.u16(data) as u32) << 16 | self.u16(&data[2..]) as u32,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Interface {
+    link_type: u16,
+    /// Timestamp units per second.
+    units: u64,
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap { order: ByteOrder, interface: Interface },
+    Pcapng { order: ByteOrder, interfaces: Vec<Interface> },
+}
+
+/// A packet as captured on an interface.
+struct Record {
+    timestamp: SystemTime,
+    link_type: u16,
+    data: Vec<u8>,
+}
+
+/// Iterator over the SSDP messages found in a pcap or pcapng capture.
+///
+/// UDP datagrams sent to or from the configured ports are parsed as messages,
+/// yielding an error for those that do not parse and going on with the next
+/// packet. Errors in the capture itself, such as a truncated file, are yielded
+/// once and end the iteration.
+pub struct CaptureReader<R> {
+    reader: R,
+    format: Format,
+    ports: Vec<u16>,
+    last_timestamp: SystemTime,
+    done: bool,
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Open the capture file at the path.
+    pub fn open<P: AsRef<Path>>(path: P) -> SSDPResult<CaptureReader<BufReader<File>>> {
+        let file = try!(File::open(path));
+
+        CaptureReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Read a capture, telling pcap and pcapng files apart by their header.
+    pub fn new(mut reader: R) -> SSDPResult<CaptureReader<R>> {
+        let mut magic = [0u8; 4];
+        try!(reader.read_exact(&mut magic));
+
+        let mut capture = CaptureReader {
+            reader: reader,
+            format: Format::Pcapng {
+                order: ByteOrder::Little,
+                interfaces: Vec::new(),
+            },
+            ports: vec![UPNP_MULTICAST_PORT],
+            last_timestamp: UNIX_EPOCH,
+            done: false,
+        };
+
+        if ByteOrder::Big.u32(&magic) == PCAPNG_SECTION_HEADER {
+            try!(capture.read_block_body(PCAPNG_SECTION_HEADER));
+            return Ok(capture);
+        }
+
+        let (order, units) = match (ByteOrder::Little.u32(&magic), ByteOrder::Big.u32(&magic)) {
+            (PCAP_MAGIC_MICROS, _) => (ByteOrder::Little, 1_000_000),
+            (PCAP_MAGIC_NANOS, _) => (ByteOrder::Little, 1_000_000_000),
+            (_, PCAP_MAGIC_MICROS) => (ByteOrder::Big, 1_000_000),
+            (_, PCAP_MAGIC_NANOS) => (ByteOrder::Big, 1_000_000_000),
+            _ => return Err(invalid("not a pcap or pcapng file")),
+        };
+
+        let header = try!(capture.read_exact(PCAP_HEADER_LEN - magic.len()));
+        capture.format = Format::Pcap {
+            order: order,
+            interface: Interface {
+                // The upper bits may hold the length of a frame check sequence
+                link_type: (order.u32(&header[16..]) & 0x0fff_ffff) as u16,
+                units: units,
+            },
+        };
+
+        Ok(capture)
+    }
+
+    /// Set the UDP ports whose datagrams are parsed as messages, by default
+    /// only the SSDP port.
+    ///
+    /// Search responses are often sent from another port than the SSDP port to
+    /// the port the search was sent from, and are only found if either port is
+    /// among these.
+    pub fn set_ports(mut self, value: Vec<u16>) -> Self {
+        self.ports = value;
+        self
+    }
+
+    /// Read the next packet in the capture, returning None at the end of the file.
+    fn next_record(&mut self) -> SSDPResult<Option<Record>> {
+        let (order, interface) = match self.format {
+            Format::Pcap { order, interface } => (order, interface),
+            Format::Pcapng { .. } => return self.next_block_record(),
+        };
+
+        let mut header = [0u8; PCAP_RECORD_HEADER_LEN];
+        if !try!(self.read_start(&mut header)) {
+            return Ok(None);
+        }
+
+        let ticks = order.u32(&header[0..]) as u64 * interface.units + order.u32(&header[4..]) as u64;
+        let len = order.u32(&header[8..]) as usize;
+        if len > MAX_RECORD_LEN {
+            return Err(invalid("packet record is too long"));
+        }
+
+        Ok(Some(Record {
+            timestamp: try!(timestamp(ticks, interface.units)),
+            link_type: interface.link_type,
+            data: try!(self.read_exact(len)),
+        }))
+    }
+
+    fn next_block_record(&mut self) -> SSDPResult<Option<Record>> {
+        loop {
+            let mut block_type = [0u8; 4];
+            if !try!(self.read_start(&mut block_type)) {
+                return Ok(None);
+            }
+
+            let block_type = match self.format {
+                // The section header reads the same in either byte order
+                _ if ByteOrder::Big.u32(&block_type) == PCAPNG_SECTION_HEADER => PCAPNG_SECTION_HEADER,
+                Format::Pcapng { order, .. } => order.u32(&block_type),
+                Format::Pcap { .. } => unreachable!(),
+            };
+
+            let body = try!(self.read_block_body(block_type));
+            let (order, interfaces) = match self.format {
+                Format::Pcapng { order, ref mut interfaces } => (order, interfaces),
+                Format::Pcap { .. } => unreachable!(),
+            };
+
+            let (interface, ticks, data) = match block_type {
+                BLOCK_INTERFACE_DESCRIPTION => {
+                    interfaces.push(try!(parse_interface(order, &body)));
+                    continue;
+                }
+                BLOCK_ENHANCED_PACKET | BLOCK_PACKET if body.len() >= 20 => {
+                    let interface = match block_type {
+                        BLOCK_PACKET => order.u16(&body[0..]) as usize,
+                        _ => order.u32(&body[0..]) as usize,
+                    };
+                    let ticks = (order.u32(&body[4..]) as u64) << 32 | order.u32(&body[8..]) as u64;
+                    let len = order.u32(&body[12..]) as usize;
+                    if body.len() - 20 < len {
+                        return Err(invalid("packet is longer than its block"));
+                    }
+
+                    (interface, Some(ticks), &body[20..20 + len])
+                }
+                BLOCK_SIMPLE_PACKET if body.len() >= 4 => {
+                    // Simple packets hold as much of the packet as fits in the block
+                    let len = (order.u32(&body[0..]) as usize).min(body.len() - 4);
+
+                    (0, None, &body[4..4 + len])
+                }
+                BLOCK_ENHANCED_PACKET | BLOCK_PACKET | BLOCK_SIMPLE_PACKET => {
+                    return Err(invalid("packet block is too short"))
+                }
+                _ => continue,
+            };
+
+            let interface = match interfaces.get(interface) {
+                Some(interface) => *interface,
+                None => return Err(invalid("packet refers to an undescribed interface")),
+            };
+
+            // Simple packets have no timestamp of their own
+            let timestamp = match ticks {
+                Some(ticks) => try!(timestamp(ticks, interface.units)),
+                None => self.last_timestamp,
+            };
+            return Ok(Some(Record {
+                timestamp: timestamp,
+                link_type: interface.link_type,
+                data: data.to_vec(),
+            }));
+        }
+    }
+
+    /// Read the rest of a pcapng block after its type, returning its body.
+    ///
+    /// A section header sets the byte order of the blocks following it.
+    fn read_block_body(&mut self, block_type: u32) -> SSDPResult<Vec<u8>> {
+        let mut len = [0u8; 4];
+        try!(self.read_exact_into(&mut len));
+
+        let mut body = Vec::new();
+        if block_type == PCAPNG_SECTION_HEADER {
+            body = try!(self.read_exact(4));
+            let order = match (ByteOrder::Little.u32(&body), ByteOrder::Big.u32(&body)) {
+                (PCAPNG_BYTE_ORDER_MAGIC, _) => ByteOrder::Little,
+                (_, PCAPNG_BYTE_ORDER_MAGIC) => ByteOrder::Big,
+                _ => return Err(invalid("section header has no byte order magic")),
+            };
+
+            // Interfaces are numbered from zero in each section
+            self.format = Format::Pcapng {
+                order: order,
+                interfaces: Vec::new(),
+            };
+        }
+
+        let order = match self.format {
+            Format::Pcapng { order, .. } => order,
+            Format::Pcap { .. } => unreachable!(),
+        };
+        let len = order.u32(&len) as usize;
+        // The block length counts the type and the length, given before and after the body
+        if len < 12 + body.len() || len % 4 != 0 || len > MAX_RECORD_LEN {
+            return Err(invalid("block has an invalid length"));
+        }
+
+        body.extend(try!(self.read_exact(len - 12 - body.len())));
+        let mut trailer = [0u8; 4];
+        try!(self.read_exact_into(&mut trailer));
+        if order.u32(&trailer) as usize != len {
+            return Err(invalid("block lengths do not match"));
+        }
+
+        if block_type == PCAPNG_SECTION_HEADER && (body.len() < 8 || order.u16(&body[4..]) != 1) {
+            return Err(invalid("unsupported pcapng version"));
+        }
+
+        Ok(body)
+    }
+
+    /// Fill the buffer, returning false if the reader is at its end.
+    fn read_start(&mut self, buffer: &mut [u8]) -> SSDPResult<bool> {
+        let mut read = 0;
+        while read < buffer.len() {
+            match self.reader.read(&mut buffer[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(invalid("capture is truncated")),
+                Ok(count) => read += count,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn read_exact(&mut self, len: usize) -> SSDPResult<Vec<u8>> {
+        let mut buffer = vec![0u8; len];
+        try!(self.read_exact_into(&mut buffer));
+
+        Ok(buffer)
+    }
+
+    fn read_exact_into(&mut self, buffer: &mut [u8]) -> SSDPResult<()> {
+        self.reader.read_exact(buffer).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => invalid("capture is truncated"),
+            _ => e.into(),
+        })
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Read the next UDP datagram sent to or from the configured ports,
+    /// without parsing it as a message.
+    pub fn next_datagram(&mut self) -> Option<SSDPResult<CapturedDatagram>> {
+        while !self.done {
+            let record = match self.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => break,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            self.last_timestamp = record.timestamp;
+
+            let datagram = match packet::decode(record.link_type, &record.data) {
+                Some(datagram) => datagram,
+                None => continue,
+            };
+            if !self.ports.contains(&datagram.src.port()) && !self.ports.contains(&datagram.dst.port()) {
+                continue;
+            }
+
+            return Some(Ok(CapturedDatagram {
+                timestamp: record.timestamp,
+                src: datagram.src,
+                dst: datagram.dst,
+                payload: datagram.payload.to_vec(),
+            }));
+        }
+
+        self.done = true;
+        None
+    }
+
+    /// Iterate over the datagrams in the capture rather than the messages.
+    pub fn datagrams(self) -> Datagrams<R> {
+        Datagrams { reader: self }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = SSDPResult<Captured>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_datagram().map(|datagram| {
+            datagram.and_then(|datagram| {
+                CapturedMessage::raw_ssdp(&datagram.payload).map(|message| {
+                    Captured {
+                        timestamp: datagram.timestamp,
+                        src: datagram.src,
+                        dst: datagram.dst,
+                        message: message,
+                    }
+                })
+            })
+        })
+    }
+}
+
+/// Iterator over the datagrams in a capture, returned from `CaptureReader::datagrams`.
+pub struct Datagrams<R> {
+    reader: CaptureReader<R>,
+}
+
+impl<R: Read> Iterator for Datagrams<R> {
+    type Item = SSDPResult<CapturedDatagram>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.next_datagram()
+    }
+}
+
+fn parse_interface(order: ByteOrder, body: &[u8]) -> SSDPResult<Interface> {
+    if body.len() < 8 {
+        return Err(invalid("interface block is too short"));
+    }
+
+    let mut interface = Interface {
+        link_type: order.u16(body),
+        units: DEFAULT_UNITS,
+    };
+
+    let mut offset = 8;
+    while body.len() >= offset + 4 {
+        let code = order.u16(&body[offset..]);
+        let len = order.u16(&body[offset + 2..]) as usize;
+        let value = match body.get(offset + 4..offset + 4 + len) {
+            Some(value) => value,
+            None => return Err(invalid("interface option is longer than its block")),
+        };
+
+        match code {
+            OPTION_END => break,
+            OPTION_IF_TSRESOL if len >= 1 => interface.units = try!(resolution(value[0])),
+            _ => (),
+        }
+
+        // Options are padded to 32 bits
+        offset += 4 + (len + 3) / 4 * 4;
+    }
+
+    Ok(interface)
+}
+
+/// Timestamp units per second given by an `if_tsresol` option, either a power
+/// of two or of ten.
+fn resolution(option: u8) -> SSDPResult<u64> {
+    let exponent = (option & 0x7f) as u32;
+    let units = match option & 0x80 {
+        0 => 10u64.checked_pow(exponent),
+        _ => 1u64.checked_shl(exponent),
+    };
+
+    units.ok_or_else(|| invalid("unsupported timestamp resolution"))
+}
+
+fn timestamp(ticks: u64, units: u64) -> SSDPResult<SystemTime> {
+    let nanos = (ticks % units) as u128 * 1_000_000_000 / units as u128;
+
+    UNIX_EPOCH.checked_add(Duration::new(ticks / units, nanos as u32))
+        .ok_or_else(|| invalid("timestamp is out of range"))
+}
+
+fn invalid(msg: &str) -> ::SSDPError {
+    SSDPErrorKind::InvalidCapture(msg.to_owned()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{resolution, timestamp, CaptureReader};
+    use capture::CapturedMessage;
+    use capture::packet::{encode_raw, LINKTYPE_RAW};
+
+    const NOTIFY: &'static [u8] = b"NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\
+                                    NT: upnp:rootdevice\r\nNTS: ssdp:byebye\r\n\
+                                    USN: uuid:device-1::upnp:rootdevice\r\n\r\n";
+
+    /// A little endian pcap file with microsecond timestamps holding the packets.
+    fn pcap(packets: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut file = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0];
+        file.extend_from_slice(&[LINKTYPE_RAW as u8, 0, 0, 0]);
+
+        for &(seconds, ref packet) in packets {
+            for &field in &[seconds, 250_000, packet.len() as u32, packet.len() as u32] {
+                file.extend_from_slice(&[field as u8, (field >> 8) as u8, (field >> 16) as u8,
+                                         (field >> 24) as u8]);
+            }
+            file.extend_from_slice(packet);
+        }
+
+        file
+    }
+
+    #[test]
+    fn positive_read_pcap() {
+        let notify = encode_raw("192.168.1.10:1900".parse().unwrap(),
+                                "239.255.255.250:1900".parse().unwrap(),
+                                NOTIFY)
+            .unwrap();
+        let other = encode_raw("192.168.1.10:5353".parse().unwrap(),
+                               "224.0.0.251:5353".parse().unwrap(),
+                               b"mdns")
+            .unwrap();
+        let file = pcap(&[(10, other), (20, notify)]);
+
+        let captured: Vec<_> = CaptureReader::new(&file[..]).unwrap().collect();
+        assert_eq!(captured.len(), 1);
+
+        let captured = captured[0].as_ref().unwrap();
+        assert_eq!(captured.timestamp, UNIX_EPOCH + Duration::from_millis(20_250));
+        assert_eq!(captured.src, "192.168.1.10:1900".parse().unwrap());
+        match captured.message {
+            CapturedMessage::Notify(_) => (),
+            ref message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn positive_timestamp_resolution() {
+        assert_eq!(resolution(6).unwrap(), 1_000_000);
+        assert_eq!(resolution(0x80 | 10).unwrap(), 1024);
+        assert_eq!(timestamp(1536, 1024).unwrap(), UNIX_EPOCH + Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn positive_read_big_endian_pcap() {
+        let notify = encode_raw("192.168.1.10:1900".parse().unwrap(),
+                                "239.255.255.250:1900".parse().unwrap(),
+                                NOTIFY)
+            .unwrap();
+
+        let mut file = vec![0xa1, 0xb2, 0xc3, 0xd4, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff];
+        file.extend_from_slice(&[0, 0, 0, LINKTYPE_RAW as u8]);
+        for &field in &[20, 250_000, notify.len() as u32, notify.len() as u32] {
+            file.extend_from_slice(&[(field >> 24) as u8, (field >> 16) as u8, (field >> 8) as u8,
+                                     field as u8]);
+        }
+        file.extend_from_slice(&notify);
+
+        let captured: Vec<_> = CaptureReader::new(&file[..]).unwrap().map(Result::unwrap).collect();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].timestamp, UNIX_EPOCH + Duration::from_millis(20_250));
+    }
+
+    #[test]
+    fn positive_truncated_capture() {
+        let notify = encode_raw("192.168.1.10:1900".parse().unwrap(),
+                                "239.255.255.250:1900".parse().unwrap(),
+                                NOTIFY)
+            .unwrap();
+        let file = pcap(&[(10, notify.clone()), (20, notify)]);
+
+        let captured: Vec<_> = CaptureReader::new(&file[..file.len() - 10]).unwrap().collect();
+        assert_eq!(captured.len(), 2);
+        assert!(captured[0].is_ok());
+        assert!(captured[1].is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_timestamp_out_of_range() {
+        // A resolution of one unit per second leaves the ticks as seconds
+        timestamp(u64::max_value(), resolution(0).unwrap()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_unknown_format() {
+        CaptureReader::new(&b"GIF89a\x01\x00\x01\x00"[..]).unwrap();
+    }
+}
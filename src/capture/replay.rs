@@ -0,0 +1,310 @@
+//! Replay of recorded traffic, paced as it was recorded.
+
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use {SSDPErrorKind, SSDPResult};
+use cache::{CacheEvent, DeviceCache};
+use receiver::{self, FromRawSSDP, Received, SSDPReceiver};
+use super::{CapturedDatagram, CapturedMessage};
+
+/// Enumerates the paces at which recorded traffic is replayed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ReplayClock {
+    /// Wait as long between datagrams as passed between them when recorded.
+    RealTime,
+    /// Wait as long between datagrams as when recorded, divided by the factor,
+    /// which must be a positive finite number.
+    Accelerated(f64),
+    /// Replay the datagrams without waiting between them.
+    Unpaced,
+}
+
+/// A recorded datagram along with the point in time it is replayed at.
+#[derive(Clone, Debug)]
+pub struct Replayed {
+    pub datagram: CapturedDatagram,
+    /// Point in time the datagram was sent at, as far from the start of the
+    /// replay as it was from the first datagram when recorded, whatever the
+    /// clock. Messages observed and expired at this point in time behave as
+    /// they did when recorded, even if the replay is accelerated.
+    pub instant: Instant,
+}
+
+impl Replayed {
+    /// Parse the datagram as a message.
+    pub fn message(&self) -> SSDPResult<CapturedMessage> {
+        CapturedMessage::raw_ssdp(&self.datagram.payload)
+    }
+
+    /// Expire the cache at the point in time the datagram is replayed at, and
+    /// record the datagram in it if it is a notify message or search response.
+    pub fn observe(&self, cache: &mut DeviceCache) -> SSDPResult<Vec<CacheEvent>> {
+        let message = try!(self.message());
+        let mut events = cache.expire_at(self.instant);
+
+        let src = self.datagram.src;
+        let observed = match message {
+            CapturedMessage::Notify(ref message) => cache.observe_at(message, src, self.instant),
+            CapturedMessage::Response(ref message) => cache.observe_at(message, src, self.instant),
+            CapturedMessage::Search(_) => None,
+        };
+        events.extend(observed);
+
+        Ok(events)
+    }
+}
+
+/// Iterator replaying recorded datagrams, such as those from
+/// `CaptureReader::datagrams` or a `TextReader`, waiting between them as the
+/// clock says.
+///
+/// Datagrams are expected in the order they were recorded. Errors reading the
+/// recording are passed through.
+pub struct Replay<I> {
+    datagrams: I,
+    clock: ReplayClock,
+    start: Option<(SystemTime, Instant)>,
+}
+
+impl<I> Replay<I>
+    where I: Iterator<Item = SSDPResult<CapturedDatagram>>
+{
+    /// Construct a replay of the datagrams in real time.
+    pub fn new<D>(datagrams: D) -> Replay<I>
+        where D: IntoIterator<Item = SSDPResult<CapturedDatagram>, IntoIter = I>
+    {
+        Replay {
+            datagrams: datagrams.into_iter(),
+            clock: ReplayClock::RealTime,
+            start: None,
+        }
+    }
+
+    /// Set the clock pacing the replay.
+    ///
+    /// Returns an error if the clock is accelerated by a factor that is not a
+    /// positive finite number.
+    pub fn set_clock(mut self, value: ReplayClock) -> SSDPResult<Self> {
+        if let ReplayClock::Accelerated(factor) = value {
+            if !(factor.is_finite() && factor > 0.0) {
+                let msg = "replay factor is not a positive finite number".to_owned();
+                return Err(SSDPErrorKind::InvalidCapture(msg).into());
+            }
+        }
+
+        self.clock = value;
+        Ok(self)
+    }
+
+    /// Replay the datagrams into a receiver as messages of type T, in a thread
+    /// of their own, as if they were received on their destination address.
+    ///
+    /// Datagrams that do not parse are dropped, as they are by a receiver on a
+    /// socket, and the receiver ends with the recording.
+    pub fn into_receiver<T>(self) -> SSDPReceiver<T>
+        where T: FromRawSSDP + Send + 'static,
+              I: Send + 'static
+    {
+        let (send, recv) = receiver::channel();
+
+        thread::spawn(move || {
+            for replayed in self {
+                let datagram = match replayed {
+                    Ok(replayed) => replayed.datagram,
+                    Err(e) => {
+                        warn!("Failed to read recording: {}", e);
+                        continue;
+                    }
+                };
+
+                let message = match T::raw_ssdp(&datagram.payload) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+
+                let received = Received {
+                    message: message,
+                    src: datagram.src,
+                    local: datagram.dst,
+                };
+                if send.send(received).is_err() {
+                    // The receiver hung up
+                    return;
+                }
+            }
+        });
+
+        recv
+    }
+
+    /// Wait until the datagram recorded at the timestamp is due, returning the
+    /// point in time it was sent at on the replay clock.
+    ///
+    /// Datagrams recorded too long after the first for the point in time to be
+    /// represented are an error, and are not waited for.
+    fn wait_for(&mut self, timestamp: SystemTime) -> SSDPResult<Instant> {
+        let (first, start) = *self.start.get_or_insert((timestamp, Instant::now()));
+        // Datagrams recorded out of order are replayed right away
+        let recorded = timestamp.duration_since(first).unwrap_or_default();
+        let instant = match start.checked_add(recorded) {
+            Some(instant) => instant,
+            None => {
+                let msg = "datagram was recorded too long after the first".to_owned();
+                return Err(SSDPErrorKind::InvalidCapture(msg).into());
+            }
+        };
+
+        let wait = match self.clock {
+            ReplayClock::RealTime => Some(recorded),
+            // Waits stretched by tiny factors past what a duration holds are skipped rather than overflowing
+            ReplayClock::Accelerated(factor) => {
+                Duration::try_from_secs_f64(recorded.as_secs_f64() / factor).ok()
+            }
+            ReplayClock::Unpaced => None,
+        };
+        let due = wait.and_then(|wait| start.checked_add(wait));
+        if let Some(remaining) = due.and_then(|due| due.checked_duration_since(Instant::now())) {
+            thread::sleep(remaining);
+        }
+
+        Ok(instant)
+    }
+}
+
+impl<I> Iterator for Replay<I>
+    where I: Iterator<Item = SSDPResult<CapturedDatagram>>
+{
+    type Item = SSDPResult<Replayed>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let datagram = match self.datagrams.next() {
+            Some(Ok(datagram)) => datagram,
+            Some(Err(e)) => return Some(Err(e)),
+            None => return None,
+        };
+
+        let instant = match self.wait_for(datagram.timestamp) {
+            Ok(instant) => instant,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(Ok(Replayed {
+            datagram: datagram,
+            instant: instant,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    use super::{Replay, ReplayClock};
+    use SSDPResult;
+    use cache::{CacheEvent, DeviceCache};
+    use capture::CapturedDatagram;
+    use message::NotifyMessage;
+
+    fn alive(usn: &str, max_age: u32) -> Vec<u8> {
+        format!("NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nCACHE-CONTROL: max-age={}\r\n\
+                 LOCATION: http://192.168.1.10/description.xml\r\nNT: upnp:rootdevice\r\nNTS: ssdp:alive\r\n\
+                 USN: uuid:{}::upnp:rootdevice\r\n\r\n",
+                max_age,
+                usn)
+            .into_bytes()
+    }
+
+    fn recording(datagrams: Vec<(u64, Vec<u8>)>) -> Vec<SSDPResult<CapturedDatagram>> {
+        datagrams.into_iter()
+            .map(|(seconds, payload)| {
+                Ok(CapturedDatagram {
+                    timestamp: UNIX_EPOCH + Duration::new(1_500_000_000 + seconds, 0),
+                    src: "192.168.1.10:1900".parse().unwrap(),
+                    dst: "239.255.255.250:1900".parse().unwrap(),
+                    payload: payload,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn positive_unpaced_expiry() {
+        // The second device outlives the first, which expires by the time the third is announced
+        let datagrams = recording(vec![(0, alive("device-1", 60)),
+                                       (30, alive("device-2", 600)),
+                                       (90, alive("device-3", 60))]);
+        let mut cache = DeviceCache::new();
+
+        let started = Instant::now();
+        let mut events = Vec::new();
+        for replayed in Replay::new(datagrams).set_clock(ReplayClock::Unpaced).unwrap() {
+            events.extend(replayed.unwrap().observe(&mut cache).unwrap());
+        }
+
+        assert!(started.elapsed() < Duration::new(1, 0));
+        assert_eq!(events.len(), 4);
+        match events[2] {
+            CacheEvent::Expired(ref device) => assert_eq!(device.usn.0.to_string(), "uuid:device-1"),
+            ref event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn positive_accelerated_receiver() {
+        let datagrams = recording(vec![(0, alive("device-1", 60)), (10, alive("device-2", 60))]);
+
+        let started = Instant::now();
+        let recv = Replay::new(datagrams)
+            .set_clock(ReplayClock::Accelerated(100.0))
+            .unwrap()
+            .into_receiver::<NotifyMessage>();
+        let received: Vec<_> = recv.into_iter().collect();
+
+        assert_eq!(received.len(), 2);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(received[0].1, "192.168.1.10:1900".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_observe_invalid_message() {
+        let datagrams = recording(vec![(0, b"HELLO".to_vec())]);
+
+        Replay::new(datagrams).next().unwrap().unwrap().observe(&mut DeviceCache::new()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_zero_replay_factor() {
+        Replay::new(recording(Vec::new())).set_clock(ReplayClock::Accelerated(0.0)).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_nan_replay_factor() {
+        Replay::new(recording(Vec::new())).set_clock(ReplayClock::Accelerated(::std::f64::NAN)).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_replay_past_instant_range() {
+        // Further apart than an instant can reach, though within the range of the system time
+        let span = Duration::new(1 << 62, 0);
+        let datagrams = vec![UNIX_EPOCH - span, UNIX_EPOCH + span]
+            .into_iter()
+            .map(|timestamp| {
+                Ok(CapturedDatagram {
+                    timestamp: timestamp,
+                    src: "192.168.1.10:1900".parse().unwrap(),
+                    dst: "239.255.255.250:1900".parse().unwrap(),
+                    payload: alive("device-1", 60),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut replay = Replay::new(datagrams).set_clock(ReplayClock::Unpaced).unwrap();
+
+        assert!(replay.next().unwrap().is_ok());
+        replay.next().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+//! A line based format for recorded traffic, which can be read and edited by hand.
+//!
+//! Each line holds one datagram, given as the seconds since the epoch at which
+//! it was sent, its source and destination addresses and its payload, separated
+//! by single spaces:
+//!
+//! ```text
+//! 1500000000.250000000 192.168.1.10:1900 239.255.255.250:1900 NOTIFY * HTTP/1.1\r\nHOST: ...\r\n\r\n
+//! ```
+//!
+//! Carriage returns, line feeds, tabs and backslashes in the payload are escaped
+//! as `\r`, `\n`, `\t` and `\\`, and other bytes outside of printable ASCII as
+//! `\xNN`. Empty lines and lines starting with `#` are skipped.
+//!
+//! A `CapturedDatagram` displays as a line in this format.
+
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use {SSDPErrorKind, SSDPResult};
+use super::CapturedDatagram;
+
+/// Iterator over the datagrams in a recording in the line based format.
+///
+/// Lines that do not parse yield an error and are skipped.
+pub struct TextReader<R> {
+    lines: Lines<R>,
+    line: usize,
+    done: bool,
+}
+
+impl TextReader<BufReader<File>> {
+    /// Open the recording at the path.
+    pub fn open<P: AsRef<Path>>(path: P) -> SSDPResult<TextReader<BufReader<File>>> {
+        let file = try!(File::open(path));
+
+        Ok(TextReader::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> TextReader<R> {
+    /// Read a recording from the reader.
+    pub fn new(reader: R) -> TextReader<R> {
+        TextReader {
+            lines: reader.lines(),
+            line: 0,
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TextReader<R> {
+    type Item = SSDPResult<CapturedDatagram>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+                None => break,
+            };
+            self.line += 1;
+
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            return Some(parse_line(line).map_err(|msg| {
+                SSDPErrorKind::InvalidCapture(format!("line {}: {}", self.line, msg)).into()
+            }));
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+impl Display for CapturedDatagram {
+    /// Render the datagram as a line of a recording, without the line ending.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Times before the epoch can not be written, and are clamped to it
+        let since_epoch = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        try!(write!(f,
+                    "{}.{:09} {} {} ",
+                    since_epoch.as_secs(),
+                    since_epoch.subsec_nanos(),
+                    self.src,
+                    self.dst));
+
+        for &byte in &self.payload {
+            try!(match byte {
+                b'\r' => f.write_str("\\r"),
+                b'\n' => f.write_str("\\n"),
+                b'\t' => f.write_str("\\t"),
+                b'\\' => f.write_str("\\\\"),
+                b' '..=b'~' => write!(f, "{}", byte as char),
+                _ => write!(f, "\\x{:02x}", byte),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Result<CapturedDatagram, String> {
+    let mut fields = line.splitn(4, ' ');
+    let timestamp = try!(parse_timestamp(fields.next().unwrap_or("")));
+    let src = try!(parse_addr(fields.next()));
+    let dst = try!(parse_addr(fields.next()));
+    let payload = try!(unescape(fields.next().unwrap_or("")));
+
+    Ok(CapturedDatagram {
+        timestamp: timestamp,
+        src: src,
+        dst: dst,
+        payload: payload,
+    })
+}
+
+fn parse_timestamp(field: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("invalid timestamp '{}'", field);
+
+    let mut parts = field.splitn(2, '.');
+    let secs: u64 = try!(parts.next().unwrap_or("").parse().map_err(|_| invalid()));
+    let fraction = parts.next().unwrap_or("");
+    if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    // Digits past nanoseconds are dropped
+    let nanos = fraction.bytes()
+        .chain(::std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0, |nanos, digit| nanos * 10 + (digit - b'0') as u32);
+
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos)).ok_or_else(invalid)
+}
+
+fn parse_addr(field: Option<&str>) -> Result<SocketAddr, String> {
+    match field {
+        Some(field) => field.parse().map_err(|_| format!("invalid address '{}'", field)),
+        None => Err("missing address".to_owned()),
+    }
+}
+
+fn unescape(field: &str) -> Result<Vec<u8>, String> {
+    let mut payload = Vec::with_capacity(field.len());
+    let mut bytes = field.bytes();
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            payload.push(byte);
+            continue;
+        }
+
+        payload.push(match bytes.next() {
+            Some(b'r') => b'\r',
+            Some(b'n') => b'\n',
+            Some(b't') => b'\t',
+            Some(b'\\') => b'\\',
+            Some(b'x') => {
+                let digits: Vec<u8> = bytes.by_ref().take(2).collect();
+                let digits = String::from_utf8_lossy(&digits).into_owned();
+
+                try!(u8::from_str_radix(&digits, 16).map_err(|_| format!("invalid escape '\\x{}'", digits)))
+            }
+            Some(other) => return Err(format!("invalid escape '\\{}'", other as char)),
+            None => return Err("payload ends with a backslash".to_owned()),
+        });
+    }
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::TextReader;
+    use capture::CapturedDatagram;
+
+    #[test]
+    fn positive_display_parse_round_trip() {
+        let datagram = CapturedDatagram {
+            timestamp: UNIX_EPOCH + Duration::new(1_500_000_000, 250_000_000),
+            src: "192.168.1.10:1900".parse().unwrap(),
+            dst: "239.255.255.250:1900".parse().unwrap(),
+            payload: b"NOTIFY * HTTP/1.1\r\nSERVER: a\\b\xff\r\n\r\n".to_vec(),
+        };
+
+        let line = datagram.to_string();
+        assert_eq!(line,
+                   "1500000000.250000000 192.168.1.10:1900 239.255.255.250:1900 \
+                    NOTIFY * HTTP/1.1\\r\\nSERVER: a\\\\b\\xff\\r\\n\\r\\n");
+
+        let parsed: Vec<_> = TextReader::new(line.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(parsed, vec![datagram]);
+    }
+
+    #[test]
+    fn positive_skip_comments() {
+        let recording = "# recorded in the field\n\n1.5 [fe80::1]:1900 [ff02::c]:1900 M-SEARCH\r\n";
+
+        let parsed: Vec<_> = TextReader::new(recording.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].timestamp, UNIX_EPOCH + Duration::from_millis(1500));
+        assert_eq!(parsed[0].payload, b"M-SEARCH");
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_invalid_address() {
+        TextReader::new(&b"1.5 192.168.1.10 239.255.255.250:1900 NOTIFY"[..]).next().unwrap().unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_timestamp_out_of_range() {
+        let line = b"18446744073709551615.0 192.168.1.10:1900 239.255.255.250:1900 NOTIFY";
+
+        TextReader::new(&line[..]).next().unwrap().unwrap();
+    }
+}
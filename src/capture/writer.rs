@@ -0,0 +1,271 @@
+//! Writing of pcapng files.
+
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use {SSDPErrorKind, SSDPResult};
+use super::packet::{self, LINKTYPE_RAW};
+use super::reader::{BLOCK_ENHANCED_PACKET, BLOCK_INTERFACE_DESCRIPTION, PCAPNG_BYTE_ORDER_MAGIC,
+                    PCAPNG_SECTION_HEADER};
+
+/// Writes SSDP messages to a pcapng file.
+///
+/// Messages are written as raw IP packets on a single interface, with
+/// microsecond timestamps, and the writer is flushed after each of them so
+/// that a capture of live traffic can be followed as it is written.
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    /// Create a capture file at the path, replacing any file already there.
+    pub fn create<P: AsRef<Path>>(path: P) -> SSDPResult<CaptureWriter<BufWriter<File>>> {
+        let file = try!(File::create(path));
+
+        CaptureWriter::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a capture on the writer, writing the headers of the file.
+    pub fn new(writer: W) -> SSDPResult<CaptureWriter<W>> {
+        let mut capture = CaptureWriter { writer: writer };
+
+        let mut section = Vec::new();
+        push_u32(&mut section, PCAPNG_BYTE_ORDER_MAGIC);
+        push_u16(&mut section, 1);
+        push_u16(&mut section, 0);
+        // The length of the section is not known up front
+        section.extend_from_slice(&[0xff; 8]);
+        try!(capture.write_block(PCAPNG_SECTION_HEADER, section));
+
+        let mut interface = Vec::new();
+        push_u16(&mut interface, LINKTYPE_RAW);
+        push_u16(&mut interface, 0);
+        push_u32(&mut interface, 0);
+        try!(capture.write_block(BLOCK_INTERFACE_DESCRIPTION, interface));
+
+        try!(capture.writer.flush());
+        Ok(capture)
+    }
+
+    /// Write a message as sent from the source to the destination at the time given.
+    pub fn write<T: Display>(&mut self,
+                             message: &T,
+                             src: SocketAddr,
+                             dst: SocketAddr,
+                             timestamp: SystemTime)
+                             -> SSDPResult<()> {
+        self.write_datagram(message.to_string().as_bytes(), src, dst, timestamp)
+    }
+
+    /// Write a UDP datagram as sent from the source to the destination at the time given.
+    pub fn write_datagram(&mut self,
+                          payload: &[u8],
+                          src: SocketAddr,
+                          dst: SocketAddr,
+                          timestamp: SystemTime)
+                          -> SSDPResult<()> {
+        let packet = match packet::encode_raw(src, dst, payload) {
+            Some(packet) => packet,
+            None => {
+                return Err(SSDPErrorKind::InvalidCapture(format!("{} and {} are not of the same IP version",
+                                                                 src,
+                                                                 dst))
+                    .into())
+            }
+        };
+
+        // Times before the epoch can not be written, and are clamped to it
+        let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let micros = since_epoch.as_secs() * 1_000_000 + since_epoch.subsec_micros() as u64;
+
+        let mut block = Vec::with_capacity(20 + packet.len() + 3);
+        push_u32(&mut block, 0);
+        push_u32(&mut block, (micros >> 32) as u32);
+        push_u32(&mut block, micros as u32);
+        push_u32(&mut block, packet.len() as u32);
+        push_u32(&mut block, packet.len() as u32);
+        block.extend_from_slice(&packet);
+
+        try!(self.write_block(BLOCK_ENHANCED_PACKET, block));
+        try!(self.writer.flush());
+        Ok(())
+    }
+
+    /// Write the messages yielded from the iterator, such as an `SSDPReceiver`,
+    /// as they pass through to the returned iterator.
+    ///
+    /// Each message is written as sent from the address it came with to the
+    /// destination, such as the multicast address the receiver listens on,
+    /// and timestamped when it is yielded. Errors writing the capture are
+    /// logged and do not interrupt the messages.
+    pub fn tee<I, T>(self, messages: I, dst: SocketAddr) -> Tee<I::IntoIter, W>
+        where I: IntoIterator<Item = (T, SocketAddr)>,
+              T: Display
+    {
+        Tee {
+            messages: messages.into_iter(),
+            writer: self,
+            dst: dst,
+        }
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_block(&mut self, block_type: u32, mut body: Vec<u8>) -> SSDPResult<()> {
+        // Blocks are padded to 32 bits
+        let padded_len = (body.len() + 3) / 4 * 4;
+        body.resize(padded_len, 0);
+        let len = 12 + body.len() as u32;
+
+        let mut header = Vec::with_capacity(8);
+        push_u32(&mut header, block_type);
+        push_u32(&mut header, len);
+
+        try!(self.writer.write_all(&header));
+        try!(self.writer.write_all(&body));
+        try!(self.writer.write_all(&header[4..]));
+        Ok(())
+    }
+}
+
+/// Iterator writing the messages passing through it to a capture.
+pub struct Tee<I, W: Write> {
+    messages: I,
+    writer: CaptureWriter<W>,
+    dst: SocketAddr,
+}
+
+impl<I, W: Write> Tee<I, W> {
+    /// Stop writing to the capture, returning it.
+    pub fn into_writer(self) -> CaptureWriter<W> {
+        self.writer
+    }
+}
+
+impl<I, T, W> Iterator for Tee<I, W>
+    where I: Iterator<Item = (T, SocketAddr)>,
+          T: Display,
+          W: Write
+{
+    type Item = (T, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (message, src) = match self.messages.next() {
+            Some(item) => item,
+            None => return None,
+        };
+
+        if let Err(e) = self.writer.write(&message, src, self.dst, SystemTime::now()) {
+            warn!("Failed to write message from {} to capture: {}", src, e);
+        }
+
+        Some((message, src))
+    }
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.push(value as u8);
+    buffer.push((value >> 8) as u8);
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    push_u16(buffer, value as u16);
+    push_u16(buffer, (value >> 16) as u16);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use hyper::header::Host;
+
+    use super::CaptureWriter;
+    use capture::{CaptureReader, CapturedMessage};
+    use header::{HeaderMut, HeaderRef, Man, MX, NT, NTS, ST, USN};
+    use message::{NotifyMessage, SearchRequest};
+    use FieldMap;
+
+    fn host() -> Host {
+        Host {
+            hostname: "239.255.255.250".to_owned(),
+            port: Some(1900),
+        }
+    }
+
+    fn notify() -> NotifyMessage {
+        let mut message = NotifyMessage::new();
+        message.set(host());
+        message.set(NT(FieldMap::upnp("rootdevice")));
+        message.set(NTS::ByeBye);
+        message.set(USN(FieldMap::uuid("device-1"), Some(FieldMap::upnp("rootdevice"))));
+
+        message
+    }
+
+    #[test]
+    fn positive_write_read_round_trip() {
+        let device: SocketAddr = "[fe80::1]:1900".parse().unwrap();
+        let multicast: SocketAddr = "[ff02::c]:1900".parse().unwrap();
+        let timestamp = UNIX_EPOCH + Duration::new(1_500_000_000, 123_456_000);
+        let mut search = SearchRequest::new();
+        search.set(host());
+        search.set(Man);
+        search.set(MX(2));
+        search.set(ST::All);
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer.write(&notify(), device, multicast, timestamp).unwrap();
+        writer.write(&search, device, multicast, timestamp).unwrap();
+        let file = writer.into_inner();
+
+        let captured: Vec<_> = CaptureReader::new(&file[..]).unwrap().map(Result::unwrap).collect();
+        assert_eq!(captured.len(), 2);
+        assert_eq!((captured[0].src, captured[0].dst, captured[0].timestamp),
+                   (device, multicast, timestamp));
+        match captured[0].message {
+            CapturedMessage::Notify(ref message) => assert_eq!(message.get::<USN>(), notify().get::<USN>()),
+            ref message => panic!("unexpected message {:?}", message),
+        }
+        match captured[1].message {
+            CapturedMessage::Search(_) => (),
+            ref message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn positive_tee() {
+        let device: SocketAddr = "192.168.1.10:1900".parse().unwrap();
+        let multicast: SocketAddr = "239.255.255.250:1900".parse().unwrap();
+
+        let mut tee = CaptureWriter::new(Vec::new()).unwrap().tee(vec![(notify(), device)], multicast);
+        assert_eq!(tee.next().map(|(_, src)| src), Some(device));
+        assert!(tee.next().is_none());
+
+        let file = tee.into_writer().into_inner();
+        let captured: Vec<_> = CaptureReader::new(&file[..]).unwrap().map(Result::unwrap).collect();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].dst, multicast);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_mixed_ip_versions() {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+
+        writer.write(&notify(),
+                   "192.168.1.10:1900".parse().unwrap(),
+                   "[ff02::c]:1900".parse().unwrap(),
+                   UNIX_EPOCH)
+            .unwrap();
+    }
+}
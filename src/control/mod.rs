@@ -0,0 +1,237 @@
+//! Invocation of actions on the services of discovered devices.
+//!
+//! Actions are invoked by POSTing a SOAP envelope to the control URL of a
+//! service. Errors raised by the service are returned as
+//! `SSDPErrorKind::UPnPError` holding the `errorCode` and `errorDescription`.
+
+use std::io::Read;
+
+use hyper::Client;
+use hyper::Url;
+use hyper::header::ContentType;
+use hyper::status::StatusCode;
+
+use {SSDPError, SSDPResult, SSDPErrorKind};
+use description::{self, ServiceDescription, ServiceEntry};
+
+pub mod soap;
+mod value;
+
+pub use self::value::Value;
+
+/// Client invoking actions on a single service of a device.
+pub struct ServiceClient {
+    client: Client,
+    service_type: String,
+    control_url: Url,
+}
+
+impl ServiceClient {
+    /// Construct a client for the service entry of a device description.
+    pub fn new(service: &ServiceEntry) -> ServiceClient {
+        ServiceClient::with_client(description::default_client(),
+                                   &service.service_type,
+                                   service.control_url.clone())
+    }
+
+    /// Construct a client for the service type at the control URL using the client.
+    pub fn with_client(client: Client, service_type: &str, control_url: Url) -> ServiceClient {
+        ServiceClient {
+            client: client,
+            service_type: service_type.to_owned(),
+            control_url: control_url,
+        }
+    }
+
+    pub fn service_type(&self) -> &str {
+        &self.service_type
+    }
+
+    pub fn control_url(&self) -> &Url {
+        &self.control_url
+    }
+
+    /// Invoke the action with the arguments, returning the out arguments in the
+    /// order they were sent by the service.
+    pub fn invoke(&self, action: &str, args: &[(&str, Value)]) -> SSDPResult<Vec<(String, String)>> {
+        let args: Vec<(String, String)> = args.iter()
+            .map(|&(name, ref value)| (name.to_owned(), value.to_string()))
+            .collect();
+        let envelope = soap::encode_action(&self.service_type, action, &args);
+
+        debug!("Invoking {} on {}", action, self.control_url);
+        let mut response = try!(self.client
+            .post(self.control_url.clone())
+            .header(ContentType(soap::SOAP_CONTENT_TYPE.parse().unwrap()))
+            .header(soap::SoapAction(soap::soap_action(&self.service_type, action)))
+            .body(&envelope[..])
+            .send());
+
+        let mut body = Vec::new();
+        try!(response.read_to_end(&mut body));
+
+        match response.status {
+            StatusCode::Ok => soap::decode_action(&body[..], &format!("{}{}", action, soap::RESPONSE_SUFFIX)),
+            StatusCode::InternalServerError => {
+                match soap::decode_fault(&body[..]) {
+                    Ok((code, description)) => Err(SSDPErrorKind::UPnPError(code, description).into()),
+                    Err(_) => Err(SSDPErrorKind::ResponseCode(response.status.to_u16()).into()),
+                }
+            }
+            status => Err(SSDPErrorKind::ResponseCode(status.to_u16()).into()),
+        }
+    }
+
+    /// Invoke the action described in the service description.
+    ///
+    /// In arguments are checked against their related state variables and sent
+    /// in the order of the description. Out arguments are parsed into values of
+    /// the data type of their related state variables.
+    pub fn invoke_typed(&self,
+                        scpd: &ServiceDescription,
+                        action: &str,
+                        args: &[(&str, Value)])
+                        -> SSDPResult<Vec<(String, Value)>> {
+        let described = match scpd.action(action) {
+            Some(described) => described,
+            None => return Err(invalid_argument(action, "unknown action")),
+        };
+
+        let mut ordered = Vec::new();
+        for argument in described.in_arguments() {
+            let value = match args.iter().find(|&&(name, _)| name == argument.name) {
+                Some(&(_, ref value)) => value,
+                None => return Err(invalid_argument(&argument.name, "missing")),
+            };
+            if let Some(variable) = scpd.argument_variable(argument) {
+                try!(Value::parse_argument(&argument.name, variable, &value.to_string()));
+            }
+
+            ordered.push((&argument.name[..], value.clone()));
+        }
+        let unknown = args.iter().find(|&&(name, _)| !ordered.iter().any(|&(sent, _)| sent == name));
+        if let Some(&(name, _)) = unknown {
+            return Err(invalid_argument(name, "not an in argument"));
+        }
+
+        let results = try!(self.invoke(action, &ordered));
+
+        results.into_iter()
+            .map(|(name, text)| {
+                let variable = described.out_arguments()
+                    .into_iter()
+                    .find(|argument| argument.name == name)
+                    .and_then(|argument| scpd.argument_variable(argument));
+                let value = match variable {
+                    Some(variable) => {
+                        try!(Value::parse(&variable.data_type, &text)
+                            .map_err(|msg| SSDPErrorKind::InvalidArgument(name.clone(), msg)))
+                    }
+                    None => Value::String(text),
+                };
+
+                Ok((name, value))
+            })
+            .collect()
+    }
+}
+
+fn invalid_argument(argument: &str, msg: &str) -> SSDPError {
+    SSDPErrorKind::InvalidArgument(argument.to_owned(), msg.to_owned()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Url;
+    use hyper::status::StatusCode;
+
+    use SSDPErrorKind;
+    use description::{self, ServiceDescription, RENDERING_CONTROL_SCPD};
+    use description::mocks::{MockResponse, MockServer};
+    use super::{soap, ServiceClient, Value};
+
+    const SERVICE_TYPE: &'static str = "urn:schemas-upnp-org:service:RenderingControl:1";
+
+    fn client(server: &MockServer) -> ServiceClient {
+        ServiceClient::with_client(description::default_client(),
+                                   SERVICE_TYPE,
+                                   Url::parse(&server.url("/rc/control")).unwrap())
+    }
+
+    fn volume_response() -> MockResponse {
+        let args = vec![("CurrentVolume".to_owned(), "42".to_owned())];
+
+        MockResponse::ok(soap::encode_action(SERVICE_TYPE, "GetVolumeResponse", &args))
+    }
+
+    #[test]
+    fn positive_invoke() {
+        let server = MockServer::new(|_| volume_response());
+
+        let results = client(&server)
+            .invoke("GetVolume", &[("InstanceID", Value::from(0u32)), ("Channel", Value::from("Master"))])
+            .unwrap();
+        assert_eq!(results, vec![("CurrentVolume".to_owned(), "42".to_owned())]);
+
+        let requests = server.requests.lock().unwrap();
+        let soap_action = requests[0].headers.get_raw("SOAPACTION").unwrap();
+        assert_eq!(soap_action[0], b"\"urn:schemas-upnp-org:service:RenderingControl:1#GetVolume\"".to_vec());
+        assert_eq!(soap::decode_action(&requests[0].body[..], "GetVolume").unwrap(),
+                   vec![("InstanceID".to_owned(), "0".to_owned()),
+                        ("Channel".to_owned(), "Master".to_owned())]);
+    }
+
+    #[test]
+    fn positive_invoke_typed() {
+        let server = MockServer::new(|_| volume_response());
+        let scpd = ServiceDescription::parse(RENDERING_CONTROL_SCPD.as_bytes()).unwrap();
+
+        let results = client(&server)
+            .invoke_typed(&scpd,
+                          "GetVolume",
+                          &[("Channel", Value::from("Master")), ("InstanceID", Value::from(0u32))])
+            .unwrap();
+        assert_eq!(results, vec![("CurrentVolume".to_owned(), Value::Unsigned(42))]);
+
+        // Arguments are sent in the order of the description
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(soap::decode_action(&requests[0].body[..], "GetVolume").unwrap()[0].0, "InstanceID");
+    }
+
+    #[test]
+    fn positive_upnp_error() {
+        let server = MockServer::new(|_| {
+            MockResponse::with_status(StatusCode::InternalServerError,
+                                      r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><s:Fault>
+  <faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring>
+  <detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0">
+    <errorCode>402</errorCode><errorDescription>Invalid Args</errorDescription>
+  </UPnPError></detail>
+</s:Fault></s:Body></s:Envelope>"#)
+        });
+
+        match *client(&server).invoke("GetVolume", &[]).unwrap_err().kind() {
+            SSDPErrorKind::UPnPError(code, ref description) => {
+                assert_eq!(code, 402);
+                assert_eq!(description, "Invalid Args");
+            }
+            ref other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_invalid_typed_argument() {
+        let server = MockServer::new(|_| MockResponse::ok(""));
+        let scpd = ServiceDescription::parse(RENDERING_CONTROL_SCPD.as_bytes()).unwrap();
+
+        client(&server)
+            .invoke_typed(&scpd,
+                          "SetVolume",
+                          &[("InstanceID", Value::from(0u32)),
+                            ("Channel", Value::from("Master")),
+                            ("DesiredVolume", Value::from(101u32))])
+            .unwrap();
+    }
+}
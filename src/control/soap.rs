@@ -0,0 +1,206 @@
+//! Encoding and decoding of the SOAP envelopes used to invoke actions.
+
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+use {SSDPResult, SSDPErrorKind};
+use dom::{self, Element};
+
+/// Value of the `CONTENT-TYPE` header for SOAP requests and responses.
+pub const SOAP_CONTENT_TYPE: &'static str = "text/xml; charset=\"utf-8\"";
+
+/// Name of the header identifying the action being invoked.
+pub const SOAPACTION_HEADER_NAME: &'static str = "SOAPACTION";
+
+/// Suffix appended to the action name for the element holding the results.
+pub const RESPONSE_SUFFIX: &'static str = "Response";
+
+const ENVELOPE_START: &'static str = "<?xml version=\"1.0\"?>\
+    <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+    s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body>";
+const ENVELOPE_END: &'static str = "</s:Body></s:Envelope>";
+
+/// Represents a header naming the service type and action being invoked.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SoapAction(pub String);
+
+impl SoapAction {
+    /// Split the header value into the service type and action name.
+    pub fn parts(&self) -> Option<(&str, &str)> {
+        let value = self.0.trim().trim_matches('"');
+        let hash = match value.rfind('#') {
+            Some(hash) => hash,
+            None => return None,
+        };
+
+        Some((&value[..hash], &value[hash + 1..]))
+    }
+}
+
+impl Header for SoapAction {
+    fn header_name() -> &'static str {
+        SOAPACTION_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 {
+            return Err(Error::Header);
+        }
+
+        match String::from_utf8(raw[0].clone()) {
+            Ok(value) => Ok(SoapAction(value)),
+            Err(_) => Err(Error::Header),
+        }
+    }
+}
+
+impl HeaderFormat for SoapAction {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        fmt.write_str(&self.0)
+    }
+}
+
+/// Value of the `SOAPACTION` header for the action of the given service type.
+pub fn soap_action(service_type: &str, action: &str) -> String {
+    format!("\"{}#{}\"", service_type, action)
+}
+
+/// Encode an envelope with a body element, named for an action or its response,
+/// that holds the given arguments in order.
+pub fn encode_action(service_type: &str, element: &str, args: &[(String, String)]) -> String {
+    let mut envelope = String::from(ENVELOPE_START);
+
+    envelope.push_str(&format!("<u:{} xmlns:u=\"{}\">", element, dom::escape(service_type)));
+    for &(ref name, ref value) in args {
+        dom::write_text_element(&mut envelope, name, value);
+    }
+    envelope.push_str(&format!("</u:{}>", element));
+    envelope.push_str(ENVELOPE_END);
+
+    envelope
+}
+
+/// Decode the arguments, in order, from the body element with the given name.
+pub fn decode_action(envelope: &[u8], element: &str) -> SSDPResult<Vec<(String, String)>> {
+    let body = try!(envelope_body(envelope));
+
+    match body.child(element) {
+        Some(action) => {
+            Ok(action.children
+                .iter()
+                .map(|arg| (arg.name.clone(), arg.text.clone()))
+                .collect())
+        }
+        None => Err(SSDPErrorKind::InvalidDocument(format!("<Body> missing <{}>", element)).into()),
+    }
+}
+
+/// Encode a fault envelope holding the `UPnPError` code and description.
+pub fn encode_fault(code: u16, description: &str) -> String {
+    let mut envelope = String::from(ENVELOPE_START);
+
+    envelope.push_str("<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring>");
+    envelope.push_str("<detail><UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\">");
+    dom::write_text_element(&mut envelope, "errorCode", &code.to_string());
+    dom::write_text_element(&mut envelope, "errorDescription", description);
+    envelope.push_str("</UPnPError></detail></s:Fault>");
+    envelope.push_str(ENVELOPE_END);
+
+    envelope
+}
+
+/// Decode the `UPnPError` code and description from a fault envelope.
+pub fn decode_fault(envelope: &[u8]) -> SSDPResult<(u16, String)> {
+    let body = try!(envelope_body(envelope));
+
+    let error = body.child("Fault")
+        .and_then(|fault| fault.child("detail"))
+        .and_then(|detail| detail.child("UPnPError"));
+    let error = match error {
+        Some(error) => error,
+        None => return Err(SSDPErrorKind::InvalidDocument("fault missing <UPnPError>".to_owned()).into()),
+    };
+
+    let code = try!(error.required_text("errorCode"));
+    let code = try!(code.parse()
+        .map_err(|_| SSDPErrorKind::InvalidDocument(format!("invalid errorCode '{}'", code))));
+
+    Ok((code, error.child_text("errorDescription").unwrap_or("").to_owned()))
+}
+
+fn envelope_body(envelope: &[u8]) -> SSDPResult<Element> {
+    let mut root = try!(Element::parse(envelope));
+    if root.name != "Envelope" {
+        return Err(SSDPErrorKind::InvalidDocument(format!("unexpected root element <{}>", root.name)).into());
+    }
+
+    match root.children.iter().position(|child| child.name == "Body") {
+        Some(index) => Ok(root.children.swap_remove(index)),
+        None => Err(SSDPErrorKind::InvalidDocument("<Envelope> missing <Body>".to_owned()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::{decode_action, decode_fault, encode_action, encode_fault, soap_action, SoapAction};
+
+    const SERVICE_TYPE: &'static str = "urn:schemas-upnp-org:service:RenderingControl:1";
+
+    #[test]
+    fn positive_soap_action() {
+        assert_eq!(soap_action(SERVICE_TYPE, "GetVolume"),
+                   "\"urn:schemas-upnp-org:service:RenderingControl:1#GetVolume\"");
+    }
+
+    #[test]
+    fn positive_soap_action_header() {
+        let raw = soap_action(SERVICE_TYPE, "GetVolume").into_bytes();
+        let header = SoapAction::parse_header(&[raw]).unwrap();
+
+        assert_eq!(header.parts(), Some((SERVICE_TYPE, "GetVolume")));
+    }
+
+    #[test]
+    fn positive_action_round_trip() {
+        let args = vec![("InstanceID".to_owned(), "0".to_owned()),
+                        ("Channel".to_owned(), "<Master>".to_owned())];
+        let envelope = encode_action(SERVICE_TYPE, "GetVolume", &args);
+
+        assert_eq!(decode_action(envelope.as_bytes(), "GetVolume").unwrap(), args);
+    }
+
+    #[test]
+    fn positive_decode_fault() {
+        let envelope = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/">
+  <s:Body><s:Fault>
+    <faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring>
+    <detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0">
+      <errorCode>718</errorCode><errorDescription>ConflictInMappingEntry</errorDescription>
+    </UPnPError></detail>
+  </s:Fault></s:Body>
+</s:Envelope>"#;
+
+        assert_eq!(decode_fault(envelope.as_bytes()).unwrap(),
+                   (718, "ConflictInMappingEntry".to_owned()));
+    }
+
+    #[test]
+    fn positive_fault_round_trip() {
+        let envelope = encode_fault(402, "Invalid Args");
+
+        assert_eq!(decode_fault(envelope.as_bytes()).unwrap(), (402, "Invalid Args".to_owned()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_missing_response_element() {
+        let envelope = encode_action(SERVICE_TYPE, "GetVolume", &[]);
+
+        decode_action(envelope.as_bytes(), "GetVolumeResponse").unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use std::fmt::{self, Display, Formatter};
+
+use {SSDPResult, SSDPErrorKind};
+use description::{DataType, StateVariable};
+
+/// A typed value of an action argument or state variable.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Boolean(bool),
+    Unsigned(u64),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Value {
+    /// Parse a value of the given data type from its text form.
+    pub fn parse(data_type: &DataType, text: &str) -> Result<Value, String> {
+        let text = text.trim();
+
+        match *data_type {
+            DataType::UI1 => parse_unsigned(text, u8::max_value() as u64),
+            DataType::UI2 => parse_unsigned(text, u16::max_value() as u64),
+            DataType::UI4 => parse_unsigned(text, u32::max_value() as u64),
+            DataType::UI8 => parse_unsigned(text, u64::max_value()),
+            DataType::I1 => parse_integer(text, i8::min_value() as i64, i8::max_value() as i64),
+            DataType::I2 => parse_integer(text, i16::min_value() as i64, i16::max_value() as i64),
+            DataType::I4 |
+            DataType::Int => parse_integer(text, i32::min_value() as i64, i32::max_value() as i64),
+            DataType::I8 => parse_integer(text, i64::min_value(), i64::max_value()),
+            DataType::R4 | DataType::R8 | DataType::Number | DataType::Fixed14_4 | DataType::Float => {
+                text.parse().map(Value::Float).map_err(|_| format!("'{}' is not a number", text))
+            }
+            DataType::Boolean => {
+                match &text.to_lowercase()[..] {
+                    "1" | "true" | "yes" => Ok(Value::Boolean(true)),
+                    "0" | "false" | "no" => Ok(Value::Boolean(false)),
+                    _ => Err(format!("'{}' is not a boolean", text)),
+                }
+            }
+            _ => Ok(Value::String(text.to_owned())),
+        }
+    }
+
+    /// Parse a value for the state variable, checking its allowed values and range.
+    pub fn parse_for(variable: &StateVariable, text: &str) -> Result<Value, String> {
+        let value = try!(Value::parse(&variable.data_type, text));
+
+        // Allowed values are compared against the text as parsed, without surrounding whitespace
+        let text = text.trim();
+        let allowed = &variable.allowed_values;
+        if !allowed.is_empty() && !allowed.iter().any(|value| value == text) {
+            return Err(format!("'{}' is not an allowed value", text));
+        }
+
+        if let Some(ref range) = variable.allowed_range {
+            let number = match value {
+                Value::Unsigned(n) => n as f64,
+                Value::Integer(n) => n as f64,
+                Value::Float(n) => n,
+                _ => return Ok(value),
+            };
+
+            if number < range.minimum || number > range.maximum {
+                return Err(format!("{} is outside of the range {} to {}",
+                                   number,
+                                   range.minimum,
+                                   range.maximum));
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Parse a value for the state variable, naming the argument in the error.
+    pub fn parse_argument(argument: &str, variable: &StateVariable, text: &str) -> SSDPResult<Value> {
+        Value::parse_for(variable, text)
+            .map_err(|msg| SSDPErrorKind::InvalidArgument(argument.to_owned(), msg).into())
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Unsigned(value) => Some(value),
+            Value::Integer(value) if value >= 0 => Some(value as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(value) => Some(value),
+            Value::Unsigned(value) if value <= i64::max_value() as u64 => Some(value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Float(value) => Some(value),
+            Value::Unsigned(value) => Some(value as f64),
+            Value::Integer(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Value::Boolean(value) => f.write_str(if value { "1" } else { "0" }),
+            Value::Unsigned(value) => Display::fmt(&value, f),
+            Value::Integer(value) => Display::fmt(&value, f),
+            Value::Float(value) => Display::fmt(&value, f),
+            Value::String(ref value) => f.write_str(value),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Boolean(value)
+    }
+}
+
+impl From<u8> for Value {
+    fn from(value: u8) -> Value {
+        Value::Unsigned(value as u64)
+    }
+}
+
+impl From<u16> for Value {
+    fn from(value: u16) -> Value {
+        Value::Unsigned(value as u64)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Value {
+        Value::Unsigned(value as u64)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Value {
+        Value::Unsigned(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Float(value)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(value: &'a str) -> Value {
+        Value::String(value.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::String(value)
+    }
+}
+
+fn parse_unsigned(text: &str, max: u64) -> Result<Value, String> {
+    match text.parse::<u64>() {
+        Ok(value) if value <= max => Ok(Value::Unsigned(value)),
+        Ok(value) => Err(format!("{} is larger than {}", value, max)),
+        Err(_) => Err(format!("'{}' is not an unsigned integer", text)),
+    }
+}
+
+fn parse_integer(text: &str, min: i64, max: i64) -> Result<Value, String> {
+    match text.parse::<i64>() {
+        Ok(value) if value >= min && value <= max => Ok(Value::Integer(value)),
+        Ok(value) => Err(format!("{} is outside of the range {} to {}", value, min, max)),
+        Err(_) => Err(format!("'{}' is not an integer", text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use description::{AllowedRange, DataType, StateVariable};
+    use super::Value;
+
+    fn volume() -> StateVariable {
+        StateVariable {
+            name: "Volume".to_owned(),
+            data_type: DataType::UI2,
+            send_events: true,
+            multicast: false,
+            default_value: None,
+            allowed_values: Vec::new(),
+            allowed_range: Some(AllowedRange {
+                minimum: 0.0,
+                maximum: 100.0,
+                step: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn positive_parse_types() {
+        assert_eq!(Value::parse(&DataType::UI1, "255"), Ok(Value::Unsigned(255)));
+        assert_eq!(Value::parse(&DataType::I2, "-5"), Ok(Value::Integer(-5)));
+        assert_eq!(Value::parse(&DataType::Boolean, "true"), Ok(Value::Boolean(true)));
+        assert_eq!(Value::parse(&DataType::R8, "1.5"), Ok(Value::Float(1.5)));
+        assert_eq!(Value::parse(&DataType::String, "text"), Ok(Value::String("text".to_owned())));
+    }
+
+    #[test]
+    fn positive_boolean_text() {
+        assert_eq!(Value::from(true).to_string(), "1");
+        assert_eq!(Value::from(false).to_string(), "0");
+    }
+
+    #[test]
+    fn positive_within_range() {
+        assert_eq!(Value::parse_for(&volume(), "50"), Ok(Value::Unsigned(50)));
+    }
+
+    #[test]
+    fn positive_allowed_value_trimmed() {
+        let mut channel = volume();
+        channel.data_type = DataType::String;
+        channel.allowed_values = vec!["Master".to_owned()];
+
+        assert_eq!(Value::parse_for(&channel, " Master\n"), Ok(Value::String("Master".to_owned())));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_outside_range() {
+        Value::parse_for(&volume(), "101").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_unsigned_overflow() {
+        Value::parse(&DataType::UI1, "256").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_not_allowed_value() {
+        let mut channel = volume();
+        channel.data_type = DataType::String;
+        channel.allowed_values = vec!["Master".to_owned()];
+
+        Value::parse_for(&channel, "LF").unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+use hyper::{Client, Url};
+
+use {SSDPResult, SSDPErrorKind};
+use description::{self, resolve_url, ServiceDescription};
+use description::scpd::parse_value;
+use dom::Element;
+
+/// Name of the root element of a device description document.
+const ROOT_ELEMENT: &'static str = "root";
+
+/// Version of the `UPnP` Device Architecture a document conforms to.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub struct SpecVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+/// A parsed device description document.
+#[derive(Clone, Debug)]
+pub struct Description {
+    /// Version of the `UPnP` Device Architecture the document conforms to.
+    pub spec_version: SpecVersion,
+    /// Base that relative URLs within the document were resolved against.
+    pub url_base: Url,
+    /// The root device.
+    pub device: DeviceDescription,
+}
+
+/// A device, along with its services and embedded devices.
+#[derive(Clone, Debug)]
+pub struct DeviceDescription {
+    pub device_type: String,
+    pub friendly_name: String,
+    pub manufacturer: String,
+    pub manufacturer_url: Option<Url>,
+    pub model_description: Option<String>,
+    pub model_name: String,
+    pub model_number: Option<String>,
+    pub model_url: Option<Url>,
+    pub serial_number: Option<String>,
+    /// Unique device name, usually `uuid:` followed by the UUID of the device.
+    pub udn: String,
+    pub upc: Option<String>,
+    pub presentation_url: Option<Url>,
+    pub icons: Vec<Icon>,
+    pub services: Vec<ServiceEntry>,
+    /// Devices embedded within this device.
+    pub devices: Vec<DeviceDescription>,
+}
+
+/// An icon advertised by a device.
+#[derive(Clone, Debug)]
+pub struct Icon {
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub url: Url,
+}
+
+/// A service listed in the `serviceList` of a device.
+#[derive(Clone, Debug)]
+pub struct ServiceEntry {
+    pub service_type: String,
+    pub service_id: String,
+    /// URL of the service description, also known as the SCPD.
+    pub scpd_url: Url,
+    /// URL that actions are invoked on.
+    pub control_url: Url,
+    /// URL that event subscriptions are made on.
+    pub event_sub_url: Url,
+}
+
+impl Description {
+    /// Parse a description document that was retrieved from the given location.
+    pub fn parse(location: &Url, document: &[u8]) -> SSDPResult<Description> {
+        let root = try!(Element::parse(document));
+        if root.name != ROOT_ELEMENT {
+            let msg = format!("unexpected root element <{}>", root.name);
+            return Err(SSDPErrorKind::InvalidDocument(msg).into());
+        }
+
+        let spec_version = match root.child("specVersion") {
+            Some(spec) => {
+                SpecVersion {
+                    major: try!(parse_value(spec, "major")),
+                    minor: try!(parse_value(spec, "minor")),
+                }
+            }
+            None => {
+                return Err(SSDPErrorKind::InvalidDocument("<root> missing <specVersion>".to_owned()).into())
+            }
+        };
+
+        // URLBase was deprecated in UPnP 1.1, but older devices still send it
+        let url_base = match root.child_text("URLBase") {
+            Some(base) if !base.is_empty() => try!(resolve_url(location, base)),
+            _ => location.clone(),
+        };
+
+        let device = match root.child("device") {
+            Some(device) => try!(DeviceDescription::from_element(device, &url_base)),
+            None => return Err(SSDPErrorKind::InvalidDocument("<root> missing <device>".to_owned()).into()),
+        };
+
+        Ok(Description {
+            spec_version: spec_version,
+            url_base: url_base,
+            device: device,
+        })
+    }
+}
+
+impl DeviceDescription {
+    fn from_element(device: &Element, base: &Url) -> SSDPResult<DeviceDescription> {
+        let icons = match device.child("iconList") {
+            Some(list) => {
+                try!(list.children_named("icon")
+                    .into_iter()
+                    .map(|icon| Icon::from_element(icon, base))
+                    .collect())
+            }
+            None => Vec::new(),
+        };
+        let services = match device.child("serviceList") {
+            Some(list) => {
+                try!(list.children_named("service")
+                    .into_iter()
+                    .map(|service| ServiceEntry::from_element(service, base))
+                    .collect())
+            }
+            None => Vec::new(),
+        };
+        let devices = match device.child("deviceList") {
+            Some(list) => {
+                try!(list.children_named("device")
+                    .into_iter()
+                    .map(|embedded| DeviceDescription::from_element(embedded, base))
+                    .collect())
+            }
+            None => Vec::new(),
+        };
+
+        Ok(DeviceDescription {
+            device_type: try!(device.required_text("deviceType")).to_owned(),
+            friendly_name: try!(device.required_text("friendlyName")).to_owned(),
+            manufacturer: try!(device.required_text("manufacturer")).to_owned(),
+            manufacturer_url: try!(optional_url(device, "manufacturerURL", base)),
+            model_description: optional_text(device, "modelDescription"),
+            model_name: try!(device.required_text("modelName")).to_owned(),
+            model_number: optional_text(device, "modelNumber"),
+            model_url: try!(optional_url(device, "modelURL", base)),
+            serial_number: optional_text(device, "serialNumber"),
+            udn: try!(device.required_text("UDN")).to_owned(),
+            upc: optional_text(device, "UPC"),
+            presentation_url: try!(optional_url(device, "presentationURL", base)),
+            icons: icons,
+            services: services,
+            devices: devices,
+        })
+    }
+
+    /// Iterate over this device and all of its embedded devices, depth first.
+    pub fn all_devices(&self) -> Vec<&DeviceDescription> {
+        let mut devices = vec![self];
+
+        for embedded in &self.devices {
+            devices.extend(embedded.all_devices());
+        }
+
+        devices
+    }
+
+    /// Find the first service of the given type on this device or any embedded device.
+    pub fn find_service(&self, service_type: &str) -> Option<&ServiceEntry> {
+        self.all_devices()
+            .into_iter()
+            .flat_map(|device| device.services.iter())
+            .find(|service| service.service_type == service_type)
+    }
+}
+
+impl Icon {
+    fn from_element(icon: &Element, base: &Url) -> SSDPResult<Icon> {
+        Ok(Icon {
+            mime_type: try!(icon.required_text("mimetype")).to_owned(),
+            width: try!(parse_value(icon, "width")),
+            height: try!(parse_value(icon, "height")),
+            depth: try!(parse_value(icon, "depth")),
+            url: try!(resolve_url(base, try!(icon.required_text("url")))),
+        })
+    }
+}
+
+impl ServiceEntry {
+    fn from_element(service: &Element, base: &Url) -> SSDPResult<ServiceEntry> {
+        Ok(ServiceEntry {
+            service_type: try!(service.required_text("serviceType")).to_owned(),
+            service_id: try!(service.required_text("serviceId")).to_owned(),
+            scpd_url: try!(resolve_url(base, try!(service.required_text("SCPDURL")))),
+            control_url: try!(resolve_url(base, try!(service.required_text("controlURL")))),
+            event_sub_url: try!(resolve_url(base, try!(service.required_text("eventSubURL")))),
+        })
+    }
+
+    /// Fetch and parse the service description of this service.
+    pub fn fetch_scpd(&self) -> SSDPResult<ServiceDescription> {
+        self.fetch_scpd_with_client(&description::default_client())
+    }
+
+    /// Fetch and parse the service description of this service using the client.
+    pub fn fetch_scpd_with_client(&self, client: &Client) -> SSDPResult<ServiceDescription> {
+        let document = try!(description::fetch_document(client, &self.scpd_url));
+
+        ServiceDescription::parse(&document[..])
+    }
+}
+
+fn optional_text(element: &Element, name: &str) -> Option<String> {
+    element.child_text(name).map(str::to_owned)
+}
+
+fn optional_url(element: &Element, name: &str, base: &Url) -> SSDPResult<Option<Url>> {
+    match element.child_text(name) {
+        Some(url) if !url.is_empty() => resolve_url(base, url).map(Some),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use hyper::Url;
+
+    use description::{self, mocks::MockServer};
+    use super::Description;
+
+    const RENDERING_CONTROL: &'static str = "urn:schemas-upnp-org:service:RenderingControl:1";
+
+    pub const MEDIA_RENDERER_DESCRIPTION: &'static str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <specVersion><major>1</major><minor>1</minor></specVersion>
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+    <friendlyName>Living Room</friendlyName>
+    <manufacturer>Example &amp; Co</manufacturer>
+    <manufacturerURL>http://example.com/</manufacturerURL>
+    <modelName>Renderer</modelName>
+    <modelNumber>1.0</modelNumber>
+    <UDN>uuid:1234</UDN>
+    <presentationURL>/</presentationURL>
+    <iconList>
+      <icon>
+        <mimetype>image/png</mimetype><width>48</width><height>48</height><depth>24</depth>
+        <url>/icon.png</url>
+      </icon>
+    </iconList>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType>
+        <serviceId>urn:upnp-org:serviceId:AVTransport</serviceId>
+        <SCPDURL>/avt/scpd.xml</SCPDURL>
+        <controlURL>/avt/control</controlURL>
+        <eventSubURL>/avt/event</eventSubURL>
+      </service>
+    </serviceList>
+    <deviceList>
+      <device>
+        <deviceType>urn:schemas-upnp-org:device:Embedded:1</deviceType>
+        <friendlyName>Embedded</friendlyName>
+        <manufacturer>Example</manufacturer>
+        <modelName>Embedded</modelName>
+        <UDN>uuid:5678</UDN>
+        <serviceList>
+          <service>
+            <serviceType>urn:schemas-upnp-org:service:RenderingControl:1</serviceType>
+            <serviceId>urn:upnp-org:serviceId:RenderingControl</serviceId>
+            <SCPDURL>rc/scpd.xml</SCPDURL>
+            <controlURL>rc/control</controlURL>
+            <eventSubURL>rc/event</eventSubURL>
+          </service>
+        </serviceList>
+      </device>
+    </deviceList>
+  </device>
+</root>"#;
+
+    fn location() -> Url {
+        Url::parse("http://192.168.1.2:8080/dev/description.xml").unwrap()
+    }
+
+    #[test]
+    fn positive_parse_device() {
+        let description = Description::parse(&location(), MEDIA_RENDERER_DESCRIPTION.as_bytes()).unwrap();
+        let device = &description.device;
+
+        assert_eq!(description.spec_version.minor, 1);
+        assert_eq!(device.friendly_name, "Living Room");
+        assert_eq!(device.manufacturer, "Example & Co");
+        assert_eq!(device.udn, "uuid:1234");
+        assert_eq!(device.icons[0].url.as_str(), "http://192.168.1.2:8080/icon.png");
+        assert_eq!(device.services[0].control_url.as_str(), "http://192.168.1.2:8080/avt/control");
+        assert_eq!(device.devices[0].udn, "uuid:5678");
+    }
+
+    #[test]
+    fn positive_resolve_relative_to_location() {
+        let description = Description::parse(&location(), MEDIA_RENDERER_DESCRIPTION.as_bytes()).unwrap();
+        let service = description.device.find_service(RENDERING_CONTROL).unwrap();
+
+        assert_eq!(service.scpd_url.as_str(), "http://192.168.1.2:8080/dev/rc/scpd.xml");
+    }
+
+    #[test]
+    fn positive_resolve_relative_to_url_base() {
+        let root_device = "<device>\n    <deviceType>urn:schemas-upnp-org:device:MediaRenderer";
+        let with_base = format!("<URLBase>http://10.0.0.1:49152/base/</URLBase>\n  {}", root_device);
+        let document = MEDIA_RENDERER_DESCRIPTION.replace(root_device, &with_base);
+        let description = Description::parse(&location(), document.as_bytes()).unwrap();
+        let service = description.device.find_service(RENDERING_CONTROL).unwrap();
+
+        assert_eq!(service.control_url.as_str(), "http://10.0.0.1:49152/base/rc/control");
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_missing_udn() {
+        let document = MEDIA_RENDERER_DESCRIPTION.replace("<UDN>uuid:1234</UDN>", "");
+
+        Description::parse(&location(), document.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn positive_fetch_from_server() {
+        let server = MockServer::documents(vec![("/description.xml", MEDIA_RENDERER_DESCRIPTION.to_owned())]);
+
+        let description = description::fetch(&server.url("/description.xml")).unwrap();
+
+        assert_eq!(description.device.services[0].scpd_url.as_str(), server.url("/avt/scpd.xml"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_fetch_not_found() {
+        let server = MockServer::documents(vec![]);
+
+        description::fetch(&server.url("/description.xml")).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use hyper::header::Headers;
+use hyper::method::Method;
+use hyper::server::{Listening, Request, Response, Server};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+
+/// A request received by a `MockServer`.
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    pub method: Method,
+    pub path: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+/// A response for a `MockServer` to send back.
+pub struct MockResponse {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn ok<B: Into<Vec<u8>>>(body: B) -> MockResponse {
+        MockResponse::with_status(StatusCode::Ok, body)
+    }
+
+    pub fn with_status<B: Into<Vec<u8>>>(status: StatusCode, body: B) -> MockResponse {
+        MockResponse {
+            status: status,
+            headers: Headers::new(),
+            body: body.into(),
+        }
+    }
+}
+
+/// Local HTTP server standing in for a device during tests.
+pub struct MockServer {
+    listening: Listening,
+    pub requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    /// Serve requests by passing them to the handler.
+    pub fn new<F>(handler: F) -> MockServer
+        where F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static
+    {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        let listening = Server::http("127.0.0.1:0")
+            .unwrap()
+            .handle(move |mut req: Request, mut res: Response| {
+                let mut body = Vec::new();
+                req.read_to_end(&mut body).unwrap();
+
+                let path = match req.uri {
+                    RequestUri::AbsolutePath(ref path) => path.clone(),
+                    ref other => other.to_string(),
+                };
+                let request = MockRequest {
+                    method: req.method.clone(),
+                    path: path,
+                    headers: req.headers.clone(),
+                    body: body,
+                };
+                let response = handler(&request);
+                recorded.lock().unwrap().push(request);
+
+                *res.status_mut() = response.status;
+                res.headers_mut().extend(response.headers.iter());
+                res.start().unwrap().write_all(&response.body).unwrap();
+            })
+            .unwrap();
+
+        MockServer {
+            listening: listening,
+            requests: requests,
+        }
+    }
+
+    /// Serve the given documents by path, answering anything else with a 404.
+    pub fn documents(documents: Vec<(&'static str, String)>) -> MockServer {
+        MockServer::new(move |request| {
+            match documents.iter().find(|&&(path, _)| path == request.path) {
+                Some(&(_, ref body)) => MockResponse::ok(body.clone()),
+                None => MockResponse::with_status(StatusCode::NotFound, ""),
+            }
+        })
+    }
+
+    /// Absolute URL for the given path on this server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.listening.socket, path)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        // Hyper can not stop the listener, detach it instead of blocking on it
+        self.listening.close().unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+//! Retrieval and parsing of the description documents advertised by devices.
+//!
+//! Discovery messages carry a `LOCATION` (or `SECURELOCATION.UPNP.ORG`) header
+//! pointing at the description document of the root device. This module fetches
+//! that document over HTTP and parses it into typed structures, resolving every
+//! URL within it against the `URLBase` of the document or the location itself.
+
+use std::io::Read;
+use std::time::Duration;
+
+use hyper::Client;
+use hyper::header::Header;
+use hyper::Url;
+use hyper::status::StatusCode;
+
+use {SSDPResult, SSDPErrorKind};
+use header::{HeaderRef, Location, SecureLocation};
+
+mod device;
+mod scpd;
+#[cfg(test)]
+pub mod mocks;
+
+pub use self::device::{Description, DeviceDescription, Icon, ServiceEntry, SpecVersion};
+pub use self::scpd::{Action, AllowedRange, Argument, DataType, Direction, ServiceDescription, StateVariable};
+#[cfg(test)]
+pub use self::scpd::tests::RENDERING_CONTROL_SCPD;
+
+/// Default time to wait on a device when fetching a document.
+const DEFAULT_FETCH_TIMEOUT_SECS: u64 = 5;
+
+/// Construct an HTTP client with the default timeouts used for fetching documents.
+pub fn default_client() -> Client {
+    let mut client = Client::new();
+
+    client.set_read_timeout(Some(Duration::new(DEFAULT_FETCH_TIMEOUT_SECS, 0)));
+    client.set_write_timeout(Some(Duration::new(DEFAULT_FETCH_TIMEOUT_SECS, 0)));
+
+    client
+}
+
+/// Fetch and parse the description document at the given location.
+pub fn fetch(location: &str) -> SSDPResult<Description> {
+    fetch_with_client(&default_client(), location)
+}
+
+/// Fetch and parse the description document at the given location using the client.
+///
+/// Secure locations require a client that was constructed with a connector
+/// capable of HTTPS.
+pub fn fetch_with_client(client: &Client, location: &str) -> SSDPResult<Description> {
+    let url = try!(Url::parse(location));
+    let document = try!(fetch_document(client, &url));
+
+    Description::parse(&url, &document[..])
+}
+
+/// Fetch and parse the description document advertised by a search response or
+/// notify message.
+///
+/// The `LOCATION` header is preferred, falling back to `SECURELOCATION.UPNP.ORG`.
+pub fn fetch_for<T>(client: &Client, message: &T) -> SSDPResult<Description>
+    where T: HeaderRef
+{
+    match (message.get::<Location>(), message.get::<SecureLocation>()) {
+        (Some(&Location(ref location)), _) => fetch_with_client(client, location),
+        (None, Some(&SecureLocation(ref location))) => fetch_with_client(client, location),
+        (None, None) => Err(SSDPErrorKind::MissingHeader(Location::header_name()).into()),
+    }
+}
+
+/// Fetch the raw bytes of the document at the url.
+pub fn fetch_document(client: &Client, url: &Url) -> SSDPResult<Vec<u8>> {
+    debug!("Fetching document at {}", url);
+    let mut response = try!(client.get(url.clone()).send());
+
+    if response.status != StatusCode::Ok {
+        return Err(SSDPErrorKind::ResponseCode(response.status.to_u16()).into());
+    }
+
+    let mut document = Vec::new();
+    try!(response.read_to_end(&mut document));
+
+    Ok(document)
+}
+
+/// Resolve a possibly relative URL from a document against the base URL.
+pub fn resolve_url(base: &Url, url: &str) -> SSDPResult<Url> {
+    base.join(url.trim())
+        .map_err(|err| SSDPErrorKind::InvalidDocument(format!("invalid url '{}': {}", url, err)).into())
+}
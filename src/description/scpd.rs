@@ -0,0 +1,555 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use {SSDPResult, SSDPErrorKind};
+use description::SpecVersion;
+use dom::{self, Element};
+
+/// Name of the root element of a service description document.
+const ROOT_ELEMENT: &'static str = "scpd";
+/// Namespace of service description documents.
+const SERVICE_NAMESPACE: &'static str = "urn:schemas-upnp-org:service-1-0";
+
+/// Value of boolean attributes within a service description.
+const YES_VALUE: &'static str = "yes";
+/// Value of false boolean attributes within a service description.
+const NO_VALUE: &'static str = "no";
+
+/// A parsed service description document, also known as an SCPD.
+#[derive(Clone, Debug)]
+pub struct ServiceDescription {
+    /// Version of the `UPnP` Device Architecture the document conforms to.
+    pub spec_version: SpecVersion,
+    pub actions: Vec<Action>,
+    pub state_variables: Vec<StateVariable>,
+}
+
+/// An action that can be invoked on a service.
+#[derive(Clone, Debug)]
+pub struct Action {
+    pub name: String,
+    /// Arguments of the action, in the order they must be sent and are returned.
+    pub arguments: Vec<Argument>,
+}
+
+/// An argument to, or result of, an action.
+#[derive(Clone, Debug)]
+pub struct Argument {
+    pub name: String,
+    pub direction: Direction,
+    /// Name of the state variable that defines the type of the argument.
+    pub related_state_variable: String,
+    /// Whether the argument is the return value of the action.
+    pub retval: bool,
+}
+
+/// Enumerates directions an argument can be passed in.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum Direction {
+    /// Argument is sent to the service.
+    In,
+    /// Argument is returned from the service.
+    Out,
+}
+
+/// A state variable of a service.
+#[derive(Clone, Debug)]
+pub struct StateVariable {
+    pub name: String,
+    pub data_type: DataType,
+    /// Whether changes to the variable are sent to event subscribers.
+    pub send_events: bool,
+    /// Whether changes to the variable are sent as multicast events.
+    pub multicast: bool,
+    pub default_value: Option<String>,
+    /// Values the variable is restricted to, empty if it is not restricted.
+    pub allowed_values: Vec<String>,
+    pub allowed_range: Option<AllowedRange>,
+}
+
+/// Range that a numeric state variable is restricted to.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AllowedRange {
+    pub minimum: f64,
+    pub maximum: f64,
+    pub step: Option<f64>,
+}
+
+/// Enumerates data types of state variables defined by the `UPnP` standard.
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+pub enum DataType {
+    UI1,
+    UI2,
+    UI4,
+    UI8,
+    I1,
+    I2,
+    I4,
+    I8,
+    Int,
+    R4,
+    R8,
+    Number,
+    Fixed14_4,
+    Float,
+    Char,
+    String,
+    Date,
+    DateTime,
+    DateTimeTZ,
+    Time,
+    TimeTZ,
+    Boolean,
+    BinBase64,
+    BinHex,
+    URI,
+    UUID,
+    /// A vendor defined type, the name of the type is supplied.
+    Unknown(String),
+}
+
+/// Mapping between data types and their names within documents.
+const DATA_TYPE_NAMES: &'static [(&'static str, DataType)] = &[("ui1", DataType::UI1),
+                                                               ("ui2", DataType::UI2),
+                                                               ("ui4", DataType::UI4),
+                                                               ("ui8", DataType::UI8),
+                                                               ("i1", DataType::I1),
+                                                               ("i2", DataType::I2),
+                                                               ("i4", DataType::I4),
+                                                               ("i8", DataType::I8),
+                                                               ("int", DataType::Int),
+                                                               ("r4", DataType::R4),
+                                                               ("r8", DataType::R8),
+                                                               ("number", DataType::Number),
+                                                               ("fixed.14.4", DataType::Fixed14_4),
+                                                               ("float", DataType::Float),
+                                                               ("char", DataType::Char),
+                                                               ("string", DataType::String),
+                                                               ("date", DataType::Date),
+                                                               ("dateTime", DataType::DateTime),
+                                                               ("dateTime.tz", DataType::DateTimeTZ),
+                                                               ("time", DataType::Time),
+                                                               ("time.tz", DataType::TimeTZ),
+                                                               ("boolean", DataType::Boolean),
+                                                               ("bin.base64", DataType::BinBase64),
+                                                               ("bin.hex", DataType::BinHex),
+                                                               ("uri", DataType::URI),
+                                                               ("uuid", DataType::UUID)];
+
+impl DataType {
+    /// Whether values of this type are numbers.
+    pub fn is_numeric(&self) -> bool {
+        match *self {
+            DataType::UI1 | DataType::UI2 | DataType::UI4 | DataType::UI8 | DataType::I1 | DataType::I2 |
+            DataType::I4 | DataType::I8 | DataType::Int | DataType::R4 | DataType::R8 | DataType::Number |
+            DataType::Fixed14_4 | DataType::Float => true,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for DataType {
+    fn from(name: &'a str) -> DataType {
+        DATA_TYPE_NAMES.iter()
+            .find(|&&(known, _)| known == name)
+            .map(|&(_, ref data_type)| data_type.clone())
+            .unwrap_or_else(|| DataType::Unknown(name.to_owned()))
+    }
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let DataType::Unknown(ref name) = *self {
+            return f.write_str(name);
+        }
+
+        let name = DATA_TYPE_NAMES.iter()
+            .find(|&&(_, ref known)| known == self)
+            .map(|&(name, _)| name)
+            .expect("DataType Missing From DATA_TYPE_NAMES");
+
+        f.write_str(name)
+    }
+}
+
+impl ServiceDescription {
+    /// Parse a service description document.
+    pub fn parse(document: &[u8]) -> SSDPResult<ServiceDescription> {
+        let root = try!(Element::parse(document));
+        if root.name != ROOT_ELEMENT {
+            let msg = format!("unexpected root element <{}>", root.name);
+            return Err(SSDPErrorKind::InvalidDocument(msg).into());
+        }
+
+        let spec_version = match root.child("specVersion") {
+            Some(spec) => {
+                SpecVersion {
+                    major: try!(parse_value(spec, "major")),
+                    minor: try!(parse_value(spec, "minor")),
+                }
+            }
+            None => {
+                return Err(SSDPErrorKind::InvalidDocument("<scpd> missing <specVersion>".to_owned()).into())
+            }
+        };
+
+        let actions = match root.child("actionList") {
+            Some(list) => try!(list.children_named("action").into_iter().map(Action::from_element).collect()),
+            None => Vec::new(),
+        };
+        let state_variables = match root.child("serviceStateTable") {
+            Some(table) => {
+                try!(table.children_named("stateVariable")
+                    .into_iter()
+                    .map(StateVariable::from_element)
+                    .collect())
+            }
+            None => Vec::new(),
+        };
+
+        let description = ServiceDescription {
+            spec_version: spec_version,
+            actions: actions,
+            state_variables: state_variables,
+        };
+        description.check_arguments();
+
+        Ok(description)
+    }
+
+    /// Render the description as a service description document.
+    pub fn to_xml(&self) -> String {
+        let mut document = format!("<?xml version=\"1.0\"?>\n<{} xmlns=\"{}\">",
+                                   ROOT_ELEMENT,
+                                   SERVICE_NAMESPACE);
+
+        document.push_str("<specVersion>");
+        dom::write_text_element(&mut document, "major", &self.spec_version.major.to_string());
+        dom::write_text_element(&mut document, "minor", &self.spec_version.minor.to_string());
+        document.push_str("</specVersion>");
+
+        document.push_str("<actionList>");
+        for action in &self.actions {
+            action.write_xml(&mut document);
+        }
+        document.push_str("</actionList>");
+
+        document.push_str("<serviceStateTable>");
+        for variable in &self.state_variables {
+            variable.write_xml(&mut document);
+        }
+        document.push_str("</serviceStateTable>");
+
+        document.push_str(&format!("</{}>", ROOT_ELEMENT));
+        document
+    }
+
+    /// Find the action with the given name.
+    pub fn action(&self, name: &str) -> Option<&Action> {
+        self.actions.iter().find(|action| action.name == name)
+    }
+
+    /// Find the state variable with the given name.
+    pub fn state_variable(&self, name: &str) -> Option<&StateVariable> {
+        self.state_variables.iter().find(|variable| variable.name == name)
+    }
+
+    /// Find the state variable that defines the type of the argument.
+    pub fn argument_variable(&self, argument: &Argument) -> Option<&StateVariable> {
+        self.state_variable(&argument.related_state_variable)
+    }
+
+    /// Warn about arguments that refer to a state variable that does not exist.
+    ///
+    /// Devices get this wrong often enough that the rest of the description is
+    /// still worth using, values of such arguments are just not checked.
+    fn check_arguments(&self) {
+        for action in &self.actions {
+            for argument in &action.arguments {
+                if self.argument_variable(argument).is_none() {
+                    warn!("Argument '{}' of action '{}' refers to unknown state variable '{}'",
+                          argument.name,
+                          action.name,
+                          argument.related_state_variable);
+                }
+            }
+        }
+    }
+}
+
+impl Action {
+    fn from_element(action: &Element) -> SSDPResult<Action> {
+        let arguments = match action.child("argumentList") {
+            Some(list) => {
+                try!(list.children_named("argument")
+                    .into_iter()
+                    .map(Argument::from_element)
+                    .collect())
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Action {
+            name: try!(action.required_text("name")).to_owned(),
+            arguments: arguments,
+        })
+    }
+
+    fn write_xml(&self, buffer: &mut String) {
+        buffer.push_str("<action>");
+        dom::write_text_element(buffer, "name", &self.name);
+
+        if !self.arguments.is_empty() {
+            buffer.push_str("<argumentList>");
+            for argument in &self.arguments {
+                argument.write_xml(buffer);
+            }
+            buffer.push_str("</argumentList>");
+        }
+
+        buffer.push_str("</action>");
+    }
+
+    /// Arguments sent to the service, in order.
+    pub fn in_arguments(&self) -> Vec<&Argument> {
+        self.arguments.iter().filter(|argument| argument.direction == Direction::In).collect()
+    }
+
+    /// Arguments returned from the service, in order.
+    pub fn out_arguments(&self) -> Vec<&Argument> {
+        self.arguments.iter().filter(|argument| argument.direction == Direction::Out).collect()
+    }
+}
+
+impl Argument {
+    fn from_element(argument: &Element) -> SSDPResult<Argument> {
+        let direction = match try!(argument.required_text("direction")) {
+            "in" => Direction::In,
+            "out" => Direction::Out,
+            other => {
+                let msg = format!("invalid argument direction '{}'", other);
+                return Err(SSDPErrorKind::InvalidDocument(msg).into());
+            }
+        };
+
+        Ok(Argument {
+            name: try!(argument.required_text("name")).to_owned(),
+            direction: direction,
+            related_state_variable: try!(argument.required_text("relatedStateVariable")).to_owned(),
+            retval: argument.child("retval").is_some(),
+        })
+    }
+
+    fn write_xml(&self, buffer: &mut String) {
+        let direction = match self.direction {
+            Direction::In => "in",
+            Direction::Out => "out",
+        };
+
+        buffer.push_str("<argument>");
+        dom::write_text_element(buffer, "name", &self.name);
+        dom::write_text_element(buffer, "direction", direction);
+        if self.retval {
+            buffer.push_str("<retval/>");
+        }
+        dom::write_text_element(buffer, "relatedStateVariable", &self.related_state_variable);
+        buffer.push_str("</argument>");
+    }
+}
+
+impl StateVariable {
+    fn from_element(variable: &Element) -> SSDPResult<StateVariable> {
+        let allowed_values = match variable.child("allowedValueList") {
+            Some(list) => {
+                list.children_named("allowedValue")
+                    .into_iter()
+                    .map(|value| value.text.trim().to_owned())
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        let allowed_range = match variable.child("allowedValueRange") {
+            Some(range) => {
+                Some(AllowedRange {
+                    minimum: try!(parse_value(range, "minimum")),
+                    maximum: try!(parse_value(range, "maximum")),
+                    step: match range.child_text("step") {
+                        Some(_) => Some(try!(parse_value(range, "step"))),
+                        None => None,
+                    },
+                })
+            }
+            None => None,
+        };
+
+        Ok(StateVariable {
+            name: try!(variable.required_text("name")).to_owned(),
+            data_type: DataType::from(try!(variable.required_text("dataType"))),
+            // Events are sent unless a device explicitly opts out
+            send_events: variable.attribute("sendEvents").map_or(true, |value| value == YES_VALUE),
+            multicast: variable.attribute("multicast").map_or(false, |value| value == YES_VALUE),
+            default_value: variable.child_text("defaultValue").map(str::to_owned),
+            allowed_values: allowed_values,
+            allowed_range: allowed_range,
+        })
+    }
+
+    fn write_xml(&self, buffer: &mut String) {
+        let yes_no = |value| if value { YES_VALUE } else { NO_VALUE };
+
+        buffer.push_str(&format!("<stateVariable sendEvents=\"{}\" multicast=\"{}\">",
+                                 yes_no(self.send_events),
+                                 yes_no(self.multicast)));
+        dom::write_text_element(buffer, "name", &self.name);
+        dom::write_text_element(buffer, "dataType", &self.data_type.to_string());
+        if let Some(ref default_value) = self.default_value {
+            dom::write_text_element(buffer, "defaultValue", default_value);
+        }
+
+        if !self.allowed_values.is_empty() {
+            buffer.push_str("<allowedValueList>");
+            for value in &self.allowed_values {
+                dom::write_text_element(buffer, "allowedValue", value);
+            }
+            buffer.push_str("</allowedValueList>");
+        }
+
+        if let Some(ref range) = self.allowed_range {
+            buffer.push_str("<allowedValueRange>");
+            dom::write_text_element(buffer, "minimum", &range.minimum.to_string());
+            dom::write_text_element(buffer, "maximum", &range.maximum.to_string());
+            if let Some(step) = range.step {
+                dom::write_text_element(buffer, "step", &step.to_string());
+            }
+            buffer.push_str("</allowedValueRange>");
+        }
+
+        buffer.push_str("</stateVariable>");
+    }
+}
+
+/// Parse the text of the required child element.
+pub fn parse_value<T: FromStr>(element: &Element, name: &str) -> SSDPResult<T> {
+    let text = try!(element.required_text(name));
+
+    text.parse().map_err(|_| {
+        SSDPErrorKind::InvalidDocument(format!("<{}> has an invalid value: '{}'", name, text)).into()
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use description::{self, mocks::MockServer};
+    use description::device::tests::MEDIA_RENDERER_DESCRIPTION;
+    use super::{DataType, Direction, ServiceDescription};
+
+    pub const RENDERING_CONTROL_SCPD: &'static str = r#"<?xml version="1.0"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <actionList>
+    <action>
+      <name>GetVolume</name>
+      <argumentList>
+        <argument><name>InstanceID</name><direction>in</direction>
+          <relatedStateVariable>A_ARG_TYPE_InstanceID</relatedStateVariable></argument>
+        <argument><name>Channel</name><direction>in</direction>
+          <relatedStateVariable>A_ARG_TYPE_Channel</relatedStateVariable></argument>
+        <argument><name>CurrentVolume</name><direction>out</direction><retval/>
+          <relatedStateVariable>Volume</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>SetVolume</name>
+      <argumentList>
+        <argument><name>InstanceID</name><direction>in</direction>
+          <relatedStateVariable>A_ARG_TYPE_InstanceID</relatedStateVariable></argument>
+        <argument><name>Channel</name><direction>in</direction>
+          <relatedStateVariable>A_ARG_TYPE_Channel</relatedStateVariable></argument>
+        <argument><name>DesiredVolume</name><direction>in</direction>
+          <relatedStateVariable>Volume</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_InstanceID</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no">
+      <name>A_ARG_TYPE_Channel</name><dataType>string</dataType>
+      <allowedValueList><allowedValue>Master</allowedValue><allowedValue>LF</allowedValue></allowedValueList>
+    </stateVariable>
+    <stateVariable sendEvents="yes">
+      <name>Volume</name><dataType>ui2</dataType><defaultValue>20</defaultValue>
+      <allowedValueRange><minimum>0</minimum><maximum>100</maximum><step>1</step></allowedValueRange>
+    </stateVariable>
+  </serviceStateTable>
+</scpd>"#;
+
+    #[test]
+    fn positive_parse_actions() {
+        let scpd = ServiceDescription::parse(RENDERING_CONTROL_SCPD.as_bytes()).unwrap();
+        let get_volume = scpd.action("GetVolume").unwrap();
+
+        assert_eq!(scpd.actions.len(), 2);
+        assert_eq!(get_volume.in_arguments().len(), 2);
+        assert_eq!(get_volume.out_arguments()[0].name, "CurrentVolume");
+        assert_eq!(get_volume.out_arguments()[0].direction, Direction::Out);
+        assert!(get_volume.out_arguments()[0].retval);
+    }
+
+    #[test]
+    fn positive_parse_state_variables() {
+        let scpd = ServiceDescription::parse(RENDERING_CONTROL_SCPD.as_bytes()).unwrap();
+        let volume = scpd.state_variable("Volume").unwrap();
+        let channel = scpd.state_variable("A_ARG_TYPE_Channel").unwrap();
+
+        assert_eq!(volume.data_type, DataType::UI2);
+        assert!(volume.send_events);
+        assert_eq!(volume.default_value, Some("20".to_owned()));
+        assert_eq!(volume.allowed_range.as_ref().unwrap().maximum, 100.0);
+        assert!(!channel.send_events);
+        assert_eq!(channel.allowed_values, vec!["Master".to_owned(), "LF".to_owned()]);
+    }
+
+    #[test]
+    fn positive_render_round_trip() {
+        let scpd = ServiceDescription::parse(RENDERING_CONTROL_SCPD.as_bytes()).unwrap();
+        let rendered = ServiceDescription::parse(scpd.to_xml().as_bytes()).unwrap();
+
+        assert_eq!(rendered.actions.len(), 2);
+        assert!(rendered.action("GetVolume").unwrap().out_arguments()[0].retval);
+        assert_eq!(rendered.state_variable("Volume").unwrap().allowed_range,
+                   scpd.state_variable("Volume").unwrap().allowed_range);
+        assert!(!rendered.state_variable("A_ARG_TYPE_Channel").unwrap().send_events);
+    }
+
+    #[test]
+    fn positive_data_type_round_trip() {
+        assert_eq!(DataType::from("fixed.14.4"), DataType::Fixed14_4);
+        assert_eq!(DataType::Fixed14_4.to_string(), "fixed.14.4");
+        assert_eq!(DataType::from("vendor.type").to_string(), "vendor.type");
+    }
+
+    #[test]
+    fn positive_unknown_related_variable() {
+        let document = RENDERING_CONTROL_SCPD.replace("<name>A_ARG_TYPE_Channel</name>",
+                                                      "<name>Channel</name>");
+        let description = ServiceDescription::parse(document.as_bytes()).unwrap();
+
+        let argument = &description.action("GetVolume").unwrap().arguments[1];
+        assert_eq!(argument.name, "Channel");
+        assert!(description.argument_variable(argument).is_none());
+    }
+
+    #[test]
+    fn positive_fetch_from_device() {
+        let server = MockServer::documents(vec![("/description.xml", MEDIA_RENDERER_DESCRIPTION.to_owned()),
+                                                ("/rc/scpd.xml", RENDERING_CONTROL_SCPD.to_owned())]);
+        let description = description::fetch(&server.url("/description.xml")).unwrap();
+        let service = description.device
+            .find_service("urn:schemas-upnp-org:service:RenderingControl:1")
+            .unwrap();
+
+        let scpd = service.fetch_scpd().unwrap();
+
+        assert!(scpd.action("SetVolume").is_some());
+    }
+}
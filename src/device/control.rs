@@ -0,0 +1,268 @@
+use hyper::header::ContentType;
+use hyper::method::Method;
+use hyper::status::StatusCode;
+
+use control::Value;
+use control::soap::{self, SoapAction};
+use net::http::{HttpRequest, HttpResponse};
+use super::ServiceModel;
+
+/// Name of the empty header sent with every control response.
+const EXT_HEADER_NAME: &'static str = "EXT";
+
+/// An action invoked by a control point, with arguments checked against the SCPD.
+#[derive(Clone, Debug)]
+pub struct ActionRequest {
+    pub action: String,
+    /// In arguments, in the order of the service description.
+    pub args: Vec<(String, Value)>,
+}
+
+impl ActionRequest {
+    /// Value of the in argument with the given name.
+    pub fn arg(&self, name: &str) -> Option<&Value> {
+        self.args.iter().find(|&&(ref arg, _)| arg == name).map(|&(_, ref value)| value)
+    }
+}
+
+/// An error returned to a control point in place of the results of an action.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Fault {
+    pub code: u16,
+    pub description: String,
+}
+
+impl Fault {
+    pub fn new<S: Into<String>>(code: u16, description: S) -> Fault {
+        Fault {
+            code: code,
+            description: description.into(),
+        }
+    }
+
+    /// No action by that name at this service.
+    pub fn invalid_action() -> Fault {
+        Fault::new(401, "Invalid Action")
+    }
+
+    /// Not enough in arguments, arguments in the wrong order, or of the wrong type.
+    pub fn invalid_args() -> Fault {
+        Fault::new(402, "Invalid Args")
+    }
+
+    /// The action failed for a reason not covered by a more specific fault.
+    pub fn action_failed() -> Fault {
+        Fault::new(501, "Action Failed")
+    }
+
+    /// The value of an argument is not valid for its state variable.
+    pub fn argument_value_invalid() -> Fault {
+        Fault::new(600, "Argument Value Invalid")
+    }
+
+    /// The action is described by the service but was not implemented.
+    pub fn optional_action_not_implemented() -> Fault {
+        Fault::new(602, "Optional Action Not Implemented")
+    }
+}
+
+/// Out arguments of an action, or the fault to return in their place.
+pub type ActionResult = Result<Vec<(String, Value)>, Fault>;
+
+/// Trait for handling the invocation of an action on a service.
+///
+/// Implemented for closures taking an `ActionRequest`.
+pub trait ActionHandler: Send + Sync {
+    fn invoke(&self, request: &ActionRequest) -> ActionResult;
+}
+
+impl<F> ActionHandler for F
+    where F: Fn(&ActionRequest) -> ActionResult + Send + Sync
+{
+    fn invoke(&self, request: &ActionRequest) -> ActionResult {
+        self(request)
+    }
+}
+
+/// Handle a request made on the control url of the service.
+pub fn dispatch(service: &ServiceModel, request: &HttpRequest) -> HttpResponse {
+    if request.method != Method::Post {
+        return HttpResponse::empty(StatusCode::MethodNotAllowed);
+    }
+
+    let action = match request.headers.get::<SoapAction>().and_then(|header| header.parts()) {
+        Some((service_type, action)) if service_type == service.service_type => action.to_owned(),
+        _ => return fault_response(&Fault::invalid_action()),
+    };
+
+    let result = parse_request(service, &action, &request.body[..])
+        .and_then(|request| invoke(service, &request));
+
+    match result {
+        Ok(results) => {
+            let element = format!("{}{}", action, soap::RESPONSE_SUFFIX);
+            let envelope = soap::encode_action(&service.service_type, &element, &results);
+
+            soap_response(StatusCode::Ok, envelope)
+        }
+        Err(fault) => {
+            debug!("Action {} of {} failed: {:?}", action, service.service_type, fault);
+            fault_response(&fault)
+        }
+    }
+}
+
+/// Decode the in arguments of the action, checking them against the SCPD.
+fn parse_request(service: &ServiceModel, action: &str, body: &[u8]) -> Result<ActionRequest, Fault> {
+    let described = match service.scpd.action(action) {
+        Some(described) => described,
+        None => return Err(Fault::invalid_action()),
+    };
+    let sent = try!(soap::decode_action(body, action).map_err(|_| Fault::invalid_args()));
+
+    let in_arguments = described.in_arguments();
+    if sent.len() != in_arguments.len() {
+        return Err(Fault::invalid_args());
+    }
+
+    let mut args = Vec::with_capacity(in_arguments.len());
+    for (argument, &(ref name, ref text)) in in_arguments.into_iter().zip(sent.iter()) {
+        if *name != argument.name {
+            return Err(Fault::invalid_args());
+        }
+
+        let value = match service.scpd.argument_variable(argument) {
+            Some(variable) => {
+                try!(Value::parse_for(variable, text).map_err(|msg| {
+                    debug!("Invalid value for argument '{}' of action '{}': {}", name, action, msg);
+                    Fault::argument_value_invalid()
+                }))
+            }
+            None => Value::String(text.clone()),
+        };
+        args.push((name.clone(), value));
+    }
+
+    Ok(ActionRequest {
+        action: action.to_owned(),
+        args: args,
+    })
+}
+
+/// Call the handler of the action, returning its out arguments in the order of the SCPD.
+fn invoke(service: &ServiceModel, request: &ActionRequest) -> Result<Vec<(String, String)>, Fault> {
+    let handler = match service.handler(&request.action) {
+        Some(handler) => handler,
+        None => return Err(Fault::optional_action_not_implemented()),
+    };
+    let results = try!(handler.invoke(request));
+
+    // Both the action and its described out arguments were checked before invoking
+    let described = service.scpd.action(&request.action).expect("Action Missing From SCPD");
+    described.out_arguments()
+        .into_iter()
+        .map(|argument| match results.iter().find(|&&(ref name, _)| *name == argument.name) {
+            Some(&(ref name, ref value)) => Ok((name.clone(), value.to_string())),
+            None => {
+                warn!("Handler of {} did not return out argument {}", request.action, argument.name);
+                Err(Fault::action_failed())
+            }
+        })
+        .collect()
+}
+
+fn fault_response(fault: &Fault) -> HttpResponse {
+    soap_response(StatusCode::InternalServerError,
+                  soap::encode_fault(fault.code, &fault.description))
+}
+
+fn soap_response(status: StatusCode, envelope: String) -> HttpResponse {
+    let mut response = HttpResponse::with_status(status, envelope);
+
+    response.headers.set(ContentType(soap::SOAP_CONTENT_TYPE.parse().unwrap()));
+    response.headers.set_raw(EXT_HEADER_NAME, vec![Vec::new()]);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Headers;
+    use hyper::method::Method;
+    use hyper::status::StatusCode;
+
+    use control::Value;
+    use control::soap::{self, SoapAction};
+    use description::{ServiceDescription, RENDERING_CONTROL_SCPD};
+    use net::http::HttpRequest;
+    use super::{dispatch, ActionRequest, Fault};
+    use super::super::ServiceModel;
+
+    const SERVICE_TYPE: &'static str = "urn:schemas-upnp-org:service:RenderingControl:1";
+
+    fn rendering_control() -> ServiceModel {
+        let scpd = ServiceDescription::parse(RENDERING_CONTROL_SCPD.as_bytes()).unwrap();
+
+        ServiceModel::new(SERVICE_TYPE, "urn:upnp-org:serviceId:RenderingControl", scpd)
+            .set_action("GetVolume", |request: &ActionRequest| {
+                match request.arg("Channel").and_then(Value::as_str) {
+                    Some("Master") => Ok(vec![("CurrentVolume".to_owned(), Value::from(42u16))]),
+                    _ => Err(Fault::new(702, "Invalid Channel")),
+                }
+            })
+    }
+
+    fn control_request(action: &str, args: &[(&str, &str)]) -> HttpRequest {
+        let args: Vec<(String, String)> = args.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect();
+        let mut headers = Headers::new();
+        headers.set(SoapAction(soap::soap_action(SERVICE_TYPE, action)));
+
+        HttpRequest {
+            method: Method::Post,
+            path: "/service/0/control".to_owned(),
+            headers: headers,
+            body: soap::encode_action(SERVICE_TYPE, action, &args).into_bytes(),
+        }
+    }
+
+    fn fault_code(request: &HttpRequest) -> u16 {
+        let response = dispatch(&rendering_control(), request);
+        assert_eq!(response.status, StatusCode::InternalServerError);
+
+        soap::decode_fault(&response.body[..]).unwrap().0
+    }
+
+    #[test]
+    fn positive_dispatch() {
+        let response = dispatch(&rendering_control(),
+                                &control_request("GetVolume", &[("InstanceID", "0"), ("Channel", "Master")]));
+
+        assert_eq!(response.status, StatusCode::Ok);
+        assert_eq!(soap::decode_action(&response.body[..], "GetVolumeResponse").unwrap(),
+                   vec![("CurrentVolume".to_owned(), "42".to_owned())]);
+    }
+
+    #[test]
+    fn positive_faults() {
+        assert_eq!(fault_code(&control_request("GetMute", &[])), 401);
+        assert_eq!(fault_code(&control_request("GetVolume", &[("InstanceID", "0")])), 402);
+        assert_eq!(fault_code(&control_request("GetVolume", &[("InstanceID", "x"), ("Channel", "Master")])),
+                   600);
+        assert_eq!(fault_code(&control_request("GetVolume", &[("InstanceID", "0"), ("Channel", "LF")])),
+                   702);
+        assert_eq!(fault_code(&control_request("SetVolume",
+                                               &[("InstanceID", "0"),
+                                                 ("Channel", "Master"),
+                                                 ("DesiredVolume", "10")])),
+                   602);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_wrong_service_type() {
+        let mut request = control_request("GetVolume", &[("InstanceID", "0"), ("Channel", "Master")]);
+        request.headers.set(SoapAction(soap::soap_action("urn:schemas-upnp-org:service:AVTransport:1",
+                                                         "GetVolume")));
+
+        assert_eq!(dispatch(&rendering_control(), &request).status, StatusCode::Ok);
+    }
+}
@@ -0,0 +1,529 @@
+//! Serving a device advertised on the network.
+//!
+//! A `DeviceServer` renders the description of a `DeviceModel` and the SCPD of
+//! each of its services, serving them over HTTP on every interface the device is
+//! advertised on. Urls within the documents are relative, so the same documents
+//! are valid whichever interface the `LOCATION` of a message points at.
+//!
+//! Actions invoked on the control url of a service are checked against its SCPD
+//! and passed to the `ActionHandler` set for them on the `ServiceModel`.
+//!
+//! Control points subscribe to a service on its event subscription url. Changes
+//! to evented variables in the `ServiceState` of the service are sent to every
+//! subscriber, limited by the `Moderation` set for each variable.
+
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use get_if_addrs;
+use hyper::header::{CacheControl, CacheDirective, ContentType, Location};
+use hyper::method::Method;
+use hyper::status::StatusCode;
+
+use {FieldMap, SSDPResult};
+use header::{BootID, ConfigID, HeaderMut, HeaderRef, NT, ST, Server, USN};
+use message::{Config, NotifyMessage, SearchRequest, SearchResponse};
+use net;
+use net::http::{HttpRequest, HttpResponse, HttpServer};
+
+mod control;
+mod model;
+mod publisher;
+
+pub use self::control::{ActionHandler, ActionRequest, ActionResult, Fault};
+pub use self::model::{DeviceModel, ServiceModel};
+pub use self::publisher::{Moderation, ServiceState};
+
+/// Path the device description is served on.
+const DESCRIPTION_PATH: &'static str = "/description.xml";
+/// Prefix of the paths the documents of each service are served on.
+const SERVICE_PATH_PREFIX: &'static str = "/service/";
+
+const SCPD_DOCUMENT: &'static str = "scpd.xml";
+const CONTROL_DOCUMENT: &'static str = "control";
+const EVENT_DOCUMENT: &'static str = "event";
+
+const XML_CONTENT_TYPE: &'static str = "text/xml; charset=\"utf-8\"";
+
+/// Number of seconds search responses are valid for.
+const DEFAULT_MAX_AGE_SECS: u32 = 1800;
+
+/// Largest boot id, which is a 31 bit value.
+const MAX_BOOT_ID: u32 = 0x7fff_ffff;
+
+/// Configuration id of the first documents served by a device.
+const INITIAL_CONFIG_ID: u32 = 1;
+/// Largest configuration id devices should use, ids above it are reserved.
+const MAX_CONFIG_ID: u32 = 16777215;
+
+/// Documents rendered from a `DeviceModel`, along with the services that
+/// handle requests made on their urls.
+struct Documents {
+    config_id: u32,
+    description: String,
+    /// Service descriptions, in the order of `DeviceModel::all_services`.
+    scpds: Vec<String>,
+    services: Vec<Arc<ServiceModel>>,
+    advertisements: Vec<(NT, USN)>,
+}
+
+impl Documents {
+    fn render(model: &DeviceModel, config_id: u32) -> Documents {
+        Documents {
+            config_id: config_id,
+            description: model.description_xml(config_id),
+            scpds: model.all_services().into_iter().map(|service| service.scpd.to_xml()).collect(),
+            services: model.all_services().into_iter().map(|service| Arc::new(service.clone())).collect(),
+            advertisements: model.advertisements(),
+        }
+    }
+
+    /// Service whose control or event subscription url is the path, along with
+    /// the document requested.
+    fn service<'a>(&self, path: &'a str) -> Option<(Arc<ServiceModel>, &'a str)> {
+        match parse_service_path(path) {
+            Some((index, document)) if document == CONTROL_DOCUMENT || document == EVENT_DOCUMENT => {
+                self.services.get(index).map(|service| (service.clone(), document))
+            }
+            _ => None,
+        }
+    }
+
+    fn route(&self, request: &HttpRequest) -> HttpResponse {
+        if request.method != Method::Get && request.method != Method::Head {
+            return HttpResponse::empty(StatusCode::MethodNotAllowed);
+        }
+
+        let document = if request.path == DESCRIPTION_PATH {
+            Some(&self.description)
+        } else {
+            match parse_service_path(&request.path) {
+                Some((index, SCPD_DOCUMENT)) => self.scpds.get(index),
+                _ => None,
+            }
+        };
+
+        match document {
+            Some(document) => {
+                let mut response = HttpResponse::with_status(StatusCode::Ok, document.clone());
+                response.headers.set(ContentType(XML_CONTENT_TYPE.parse().unwrap()));
+                response.headers.set(ConfigID(self.config_id));
+                response
+            }
+            None => HttpResponse::empty(StatusCode::NotFound),
+        }
+    }
+}
+
+/// HTTP servers for the documents of a device, one for each interface.
+///
+/// Servers are stopped when dropped.
+pub struct DeviceServer {
+    documents: Arc<RwLock<Documents>>,
+    servers: Vec<HttpServer>,
+    server: String,
+    boot_id: BootID,
+}
+
+impl DeviceServer {
+    /// Serve the documents of the device on every local IPv4 interface.
+    pub fn new(model: DeviceModel) -> SSDPResult<DeviceServer> {
+        let addrs: Vec<IpAddr> = try!(get_if_addrs::get_if_addrs())
+            .into_iter()
+            .map(|iface| iface.ip())
+            .filter(IpAddr::is_ipv4)
+            .collect();
+
+        DeviceServer::bind(model, &addrs[..])
+    }
+
+    /// Serve the documents of the device on each of the addresses.
+    ///
+    /// Binding to an unspecified address serves every interface on a single port.
+    pub fn bind(model: DeviceModel, addrs: &[IpAddr]) -> SSDPResult<DeviceServer> {
+        let documents = Arc::new(RwLock::new(Documents::render(&model, INITIAL_CONFIG_ID)));
+
+        let mut servers = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            let shared = documents.clone();
+
+            let handler = move |request| handle(&shared, request);
+
+            servers.push(try!(HttpServer::bind(SocketAddr::new(*addr, 0), handler)));
+        }
+
+        Ok(DeviceServer {
+            documents: documents,
+            servers: servers,
+            server: format!("{}/1.0 UPnP/1.1 ssdp/{}", env::consts::OS, env!("CARGO_PKG_VERSION")),
+            boot_id: default_boot_id(),
+        })
+    }
+
+    /// Set the `SERVER` header of search responses, naming the operating
+    /// system, `UPnP` version and product of the device.
+    pub fn set_server<S: Into<String>>(mut self, value: S) -> Self {
+        self.server = value.into();
+        self
+    }
+
+    /// Set the `BOOTID.UPNP.ORG` header of search responses, which is to be
+    /// increased every time the device rejoins the network.
+    ///
+    /// Defaults to the seconds since the epoch at which the server was bound,
+    /// which increases between runs without being stored.
+    pub fn set_boot_id(mut self, value: BootID) -> Self {
+        self.boot_id = value;
+        self
+    }
+
+    /// Boot id given in search responses.
+    pub fn boot_id(&self) -> BootID {
+        self.boot_id
+    }
+
+    /// Configuration id of the documents currently being served.
+    pub fn config_id(&self) -> ConfigID {
+        ConfigID(self.documents.read().unwrap().config_id)
+    }
+
+    /// Url of the device description as reachable through the local interface,
+    /// or None if the device is not served on that interface.
+    pub fn location(&self, local: IpAddr) -> Option<String> {
+        let served = self.servers
+            .iter()
+            .map(HttpServer::local_addr)
+            .find(|addr| addr.ip() == local)
+            .or_else(|| {
+                self.servers
+                    .iter()
+                    .map(HttpServer::local_addr)
+                    .find(|addr| addr.ip().is_unspecified() && addr.is_ipv4() == local.is_ipv4())
+            });
+
+        served.map(|addr| format!("http://{}{}", SocketAddr::new(local, addr.port()), DESCRIPTION_PATH))
+    }
+
+    /// Set the `LOCATION` and `CONFIGID.UPNP.ORG` headers of a message sent
+    /// through the local interface.
+    ///
+    /// Returns false, leaving the message untouched, if the device is not
+    /// served on that interface.
+    pub fn set_headers<T: HeaderMut>(&self, message: &mut T, local: IpAddr) -> bool {
+        match self.location(local) {
+            Some(location) => {
+                message.set(Location(location));
+                message.set(self.config_id());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set the `LOCATION` and `CONFIGID.UPNP.ORG` headers of a message sent to
+    /// the remote address, such as a search response.
+    pub fn set_headers_towards<T: HeaderMut>(&self, message: &mut T, remote: SocketAddr) -> bool {
+        match net::local_ip_towards(remote) {
+            Ok(local) => self.set_headers(message, local),
+            Err(e) => {
+                debug!("Failed to find route towards {}: {}", remote, e);
+                false
+            }
+        }
+    }
+
+    /// Send the notify message via multicast on every interface the device is
+    /// served on, each copy pointing at the documents on its interface.
+    pub fn multicast_notify(&self, message: &NotifyMessage, config: &Config) -> SSDPResult<()> {
+        message.multicast_per_interface(config, |local, message| self.set_headers(message, local))
+    }
+
+    /// Build the responses to a search request received from the remote address,
+    /// one for each advertisement of the device matching the search target.
+    ///
+    /// Devices and services also answer searches for earlier versions of their
+    /// type, giving the version searched for as the `ST` of the response.
+    ///
+    /// The responses can be sent with a `ResponseScheduler`.
+    pub fn search_responses(&self, request: &SearchRequest, remote: SocketAddr) -> Vec<SearchResponse> {
+        let target = match request.get::<ST>() {
+            Some(target) => target.clone(),
+            None => return Vec::new(),
+        };
+        let advertisements = self.documents.read().unwrap().advertisements.clone();
+
+        advertisements.into_iter()
+            .filter_map(|(NT(kind), usn)| {
+                let st = match target {
+                    ST::All => kind,
+                    ST::Target(ref searched) if is_version_of(&kind, searched) => searched.clone(),
+                    ST::Target(_) => return None,
+                };
+                let mut response = SearchResponse::new();
+
+                response.set(CacheControl(vec![CacheDirective::MaxAge(DEFAULT_MAX_AGE_SECS)]));
+                response.set_raw("EXT", vec![Vec::new()]);
+                response.set(ST::Target(st));
+                response.set(usn);
+                response.set(Server(self.server.clone()));
+                response.set(self.boot_id);
+
+                if self.set_headers_towards(&mut response, remote) {
+                    Some(response)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Replace the model of the device, bumping the configuration id if any of
+    /// the served documents changed.
+    ///
+    /// The new action handlers and state stores are used from then on even if
+    /// the documents stay the same.
+    pub fn update(&self, model: DeviceModel) -> ConfigID {
+        let mut documents = self.documents.write().unwrap();
+
+        let mut rendered = Documents::render(&model, documents.config_id);
+        if rendered.description != documents.description || rendered.scpds != documents.scpds {
+            let config_id = if documents.config_id >= MAX_CONFIG_ID {
+                0
+            } else {
+                documents.config_id + 1
+            };
+
+            rendered = Documents::render(&model, config_id);
+        }
+        *documents = rendered;
+
+        ConfigID(documents.config_id)
+    }
+}
+
+fn handle(documents: &RwLock<Documents>, request: HttpRequest) -> HttpResponse {
+    // Handlers run without holding the lock so that they can update the device
+    let service = documents.read().unwrap().service(&request.path);
+
+    match service {
+        Some((service, CONTROL_DOCUMENT)) => control::dispatch(&service, &request),
+        Some((service, _)) => service.state().handle(&request),
+        None => documents.read().unwrap().route(&request),
+    }
+}
+
+/// Returns true if the advertised type is the searched type, or a later
+/// version of it.
+///
+/// Versions are the number after the last colon of device and service types.
+fn is_version_of(advertised: &FieldMap, searched: &FieldMap) -> bool {
+    match (advertised, searched) {
+        (&FieldMap::URN(ref advertised), &FieldMap::URN(ref searched)) => {
+            match (split_version(advertised), split_version(searched)) {
+                (Some((kind, version)), Some((searched_kind, searched_version))) => {
+                    kind == searched_kind && version >= searched_version
+                }
+                _ => advertised == searched,
+            }
+        }
+        _ => advertised == searched,
+    }
+}
+
+/// Split a device or service type into the type and its version.
+fn split_version(kind: &str) -> Option<(&str, u32)> {
+    let mut parts = kind.rsplitn(2, ':');
+    let version = parts.next().and_then(|version| version.parse().ok());
+
+    match (parts.next(), version) {
+        (Some(kind), Some(version)) => Some((kind, version)),
+        _ => None,
+    }
+}
+
+/// Boot id of a device starting now, the seconds since the epoch.
+fn default_boot_id() -> BootID {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+
+    BootID((secs & MAX_BOOT_ID as u64) as u32)
+}
+
+/// Path of a document of the service at the index within `DeviceModel::all_services`.
+fn service_path(index: usize, document: &str) -> String {
+    format!("{}{}/{}", SERVICE_PATH_PREFIX, index, document)
+}
+
+/// Split a service path into the index of the service and the document.
+fn parse_service_path(path: &str) -> Option<(usize, &str)> {
+    if !path.starts_with(SERVICE_PATH_PREFIX) {
+        return None;
+    }
+
+    let mut parts = path[SERVICE_PATH_PREFIX.len()..].splitn(2, '/');
+    match (parts.next().and_then(|index| index.parse().ok()), parts.next()) {
+        (Some(index), Some(document)) => Some((index, document)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use description::{self, ServiceDescription, RENDERING_CONTROL_SCPD};
+    use std::net::SocketAddr;
+
+    use FieldMap;
+    use control::{ServiceClient, Value};
+    use header::{BootID, ConfigID, HeaderMut, HeaderRef, Location, ST, Server, USN};
+    use message::{SearchRequest, SearchResponse};
+    use super::{ActionRequest, DeviceModel, DeviceServer, ServiceModel};
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+    fn media_renderer(friendly_name: &str) -> DeviceModel {
+        let scpd = ServiceDescription::parse(RENDERING_CONTROL_SCPD.as_bytes()).unwrap();
+        let mut model = DeviceModel::new("urn:schemas-upnp-org:device:MediaRenderer:1",
+                                         friendly_name,
+                                         "Example",
+                                         "Renderer",
+                                         "uuid:renderer-1");
+        model.services.push(ServiceModel::new("urn:schemas-upnp-org:service:RenderingControl:1",
+                                              "urn:upnp-org:serviceId:RenderingControl",
+                                              scpd));
+
+        model
+    }
+
+    #[test]
+    fn positive_serve_documents() {
+        let server = DeviceServer::bind(media_renderer("Living Room"), &[LOCALHOST]).unwrap();
+        let location = server.location(LOCALHOST).unwrap();
+
+        let description = description::fetch(&location).unwrap();
+        assert_eq!(description.device.friendly_name, "Living Room");
+
+        let service = description.device
+            .find_service("urn:schemas-upnp-org:service:RenderingControl:1")
+            .unwrap();
+        assert!(service.control_url.as_str().starts_with("http://127.0.0.1:"));
+        assert!(service.fetch_scpd().unwrap().action("GetVolume").is_some());
+    }
+
+    #[test]
+    fn positive_invoke_action() {
+        let mut model = media_renderer("Living Room");
+        model.services[0] = model.services[0].clone().set_action("GetVolume", |request: &ActionRequest| {
+            assert_eq!(request.arg("Channel"), Some(&Value::from("Master")));
+            Ok(vec![("CurrentVolume".to_owned(), Value::from(42u16))])
+        });
+        let server = DeviceServer::bind(model, &[LOCALHOST]).unwrap();
+
+        let description = description::fetch(&server.location(LOCALHOST).unwrap()).unwrap();
+        let client = ServiceClient::new(&description.device.services[0]);
+        let results = client
+            .invoke("GetVolume", &[("InstanceID", Value::from(0u32)), ("Channel", Value::from("Master"))])
+            .unwrap();
+
+        assert_eq!(results, vec![("CurrentVolume".to_owned(), "42".to_owned())]);
+    }
+
+    #[test]
+    fn positive_set_headers() {
+        let server = DeviceServer::bind(media_renderer("Living Room"), &[LOCALHOST]).unwrap();
+        let mut response = SearchResponse::new();
+
+        assert!(server.set_headers(&mut response, LOCALHOST));
+        assert_eq!(response.get::<Location>().map(|location| location.0.clone()),
+                   server.location(LOCALHOST));
+        assert_eq!(response.get::<ConfigID>(), Some(&server.config_id()));
+    }
+
+    #[test]
+    fn positive_search_responses() {
+        let server = DeviceServer::bind(media_renderer("Living Room"), &[LOCALHOST]).unwrap();
+        let remote = SocketAddr::new(LOCALHOST, 1900);
+
+        let mut request = SearchRequest::new();
+        request.set(ST::Target(FieldMap::upnp("rootdevice")));
+        let responses = server.search_responses(&request, remote);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].get::<USN>(),
+                   Some(&USN(FieldMap::uuid("renderer-1"), Some(FieldMap::upnp("rootdevice")))));
+        assert!(responses[0].get::<Location>().is_some());
+        assert!(responses[0].get::<Server>().is_some());
+        assert_eq!(responses[0].get::<BootID>(), Some(&server.boot_id()));
+
+        // Root device, uuid, device type and service type
+        request.set(ST::All);
+        assert_eq!(server.search_responses(&request, remote).len(), 4);
+    }
+
+    #[test]
+    fn positive_search_earlier_version() {
+        let mut model = media_renderer("Living Room");
+        model.device_type = "urn:schemas-upnp-org:device:MediaRenderer:2".to_owned();
+        let server = DeviceServer::bind(model, &[LOCALHOST])
+            .unwrap()
+            .set_server("Linux/4.0 UPnP/1.1 Renderer/2.0")
+            .set_boot_id(BootID(7));
+        let remote = SocketAddr::new(LOCALHOST, 1900);
+
+        let mut request = SearchRequest::new();
+        request.set(ST::Target(FieldMap::URN("schemas-upnp-org:device:MediaRenderer:1".to_owned())));
+        let responses = server.search_responses(&request, remote);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].get::<ST>(), request.get::<ST>());
+        assert_eq!(responses[0].get::<USN>(),
+                   Some(&USN(FieldMap::uuid("renderer-1"),
+                             Some(FieldMap::URN("schemas-upnp-org:device:MediaRenderer:2".to_owned())))));
+        assert_eq!(responses[0].get::<Server>(), Some(&Server("Linux/4.0 UPnP/1.1 Renderer/2.0".to_owned())));
+        assert_eq!(responses[0].get::<BootID>(), Some(&BootID(7)));
+
+        // Later versions than the device implements are not answered
+        request.set(ST::Target(FieldMap::URN("schemas-upnp-org:device:MediaRenderer:3".to_owned())));
+        assert!(server.search_responses(&request, remote).is_empty());
+    }
+
+    #[test]
+    fn positive_config_id_bumped_on_change() {
+        let server = DeviceServer::bind(media_renderer("Living Room"), &[LOCALHOST]).unwrap();
+        let ConfigID(initial) = server.config_id();
+
+        assert_eq!(server.update(media_renderer("Living Room")), ConfigID(initial));
+        assert_eq!(server.update(media_renderer("Kitchen")), ConfigID(initial + 1));
+
+        let description = description::fetch(&server.location(LOCALHOST).unwrap()).unwrap();
+        assert_eq!(description.device.friendly_name, "Kitchen");
+    }
+
+    #[test]
+    fn positive_update_handlers() {
+        let server = DeviceServer::bind(media_renderer("Living Room"), &[LOCALHOST]).unwrap();
+        let ConfigID(initial) = server.config_id();
+
+        let mut model = media_renderer("Living Room");
+        model.services[0] = model.services[0].clone().set_action("GetVolume", |_: &ActionRequest| {
+            Ok(vec![("CurrentVolume".to_owned(), Value::from(7u16))])
+        });
+        assert_eq!(server.update(model), ConfigID(initial));
+
+        let description = description::fetch(&server.location(LOCALHOST).unwrap()).unwrap();
+        let client = ServiceClient::new(&description.device.services[0]);
+        let results = client
+            .invoke("GetVolume", &[("InstanceID", Value::from(0u32)), ("Channel", Value::from("Master"))])
+            .unwrap();
+
+        assert_eq!(results, vec![("CurrentVolume".to_owned(), "7".to_owned())]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_unserved_interface() {
+        let server = DeviceServer::bind(media_renderer("Living Room"), &[LOCALHOST]).unwrap();
+
+        server.location(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use FieldMap;
+use description::ServiceDescription;
+use dom;
+use header::{NT, USN};
+use super::{service_path, ActionHandler, ServiceState, CONTROL_DOCUMENT, EVENT_DOCUMENT, SCPD_DOCUMENT};
+
+/// Namespace of device description documents.
+const DEVICE_NAMESPACE: &'static str = "urn:schemas-upnp-org:device-1-0";
+
+/// A device to be served, along with its services and embedded devices.
+#[derive(Clone, Debug)]
+pub struct DeviceModel {
+    pub device_type: String,
+    pub friendly_name: String,
+    pub manufacturer: String,
+    pub manufacturer_url: Option<String>,
+    pub model_description: Option<String>,
+    pub model_name: String,
+    pub model_number: Option<String>,
+    pub model_url: Option<String>,
+    pub serial_number: Option<String>,
+    /// Unique device name, usually `uuid:` followed by the UUID of the device.
+    pub udn: String,
+    pub upc: Option<String>,
+    pub presentation_url: Option<String>,
+    pub services: Vec<ServiceModel>,
+    /// Devices embedded within this device.
+    pub devices: Vec<DeviceModel>,
+}
+
+/// A service of a `DeviceModel`, described by its SCPD, along with the
+/// handlers of its actions and the values of its state variables.
+///
+/// Clones share the same state variables.
+#[derive(Clone)]
+pub struct ServiceModel {
+    pub service_type: String,
+    pub service_id: String,
+    pub scpd: ServiceDescription,
+    handlers: Vec<(String, Arc<ActionHandler>)>,
+    state: ServiceState,
+}
+
+impl DeviceModel {
+    /// Construct a DeviceModel with the required fields of a description and
+    /// no services or embedded devices.
+    pub fn new<S: Into<String>>(device_type: S,
+                                friendly_name: S,
+                                manufacturer: S,
+                                model_name: S,
+                                udn: S)
+                                -> DeviceModel {
+        DeviceModel {
+            device_type: device_type.into(),
+            friendly_name: friendly_name.into(),
+            manufacturer: manufacturer.into(),
+            manufacturer_url: None,
+            model_description: None,
+            model_name: model_name.into(),
+            model_number: None,
+            model_url: None,
+            serial_number: None,
+            udn: udn.into(),
+            upc: None,
+            presentation_url: None,
+            services: Vec::new(),
+            devices: Vec::new(),
+        }
+    }
+
+    /// Iterate over the services of this device and all of its embedded devices,
+    /// depth first.
+    ///
+    /// The position of a service in this list decides the urls it is served on.
+    pub fn all_services(&self) -> Vec<&ServiceModel> {
+        let mut services: Vec<&ServiceModel> = self.services.iter().collect();
+
+        for embedded in &self.devices {
+            services.extend(embedded.all_services());
+        }
+
+        services
+    }
+
+    /// Notification types the device is advertised with, each paired with the
+    /// unique service name sent along with it.
+    ///
+    /// Devices with a `udn` that is not a valid field are left out.
+    pub fn advertisements(&self) -> Vec<(NT, USN)> {
+        let mut advertisements = Vec::new();
+
+        if let Some(uuid) = FieldMap::new(&self.udn[..]) {
+            let root = FieldMap::upnp("rootdevice");
+            advertisements.push((NT(root.clone()), USN(uuid, Some(root))));
+        }
+        self.push_advertisements(&mut advertisements);
+
+        advertisements
+    }
+
+    fn push_advertisements(&self, advertisements: &mut Vec<(NT, USN)>) {
+        if let Some(uuid) = FieldMap::new(&self.udn[..]) {
+            advertisements.push((NT(uuid.clone()), USN(uuid.clone(), None)));
+
+            let types = Some(&self.device_type)
+                .into_iter()
+                .chain(self.services.iter().map(|service| &service.service_type));
+            for kind in types.filter_map(|kind| FieldMap::new(&kind[..])) {
+                let advertisement = (NT(kind.clone()), USN(uuid.clone(), Some(kind)));
+
+                // Services of the same type are only advertised once per device
+                if !advertisements.contains(&advertisement) {
+                    advertisements.push(advertisement);
+                }
+            }
+        }
+
+        for embedded in &self.devices {
+            embedded.push_advertisements(advertisements);
+        }
+    }
+
+    /// Render the device description document, identified by the configuration id.
+    ///
+    /// Urls of the services are relative to the location of the document.
+    pub fn description_xml(&self, config_id: u32) -> String {
+        let mut document = format!("<?xml version=\"1.0\"?>\n<root xmlns=\"{}\" configId=\"{}\">",
+                                   DEVICE_NAMESPACE,
+                                   config_id);
+
+        document.push_str("<specVersion><major>1</major><minor>1</minor></specVersion>");
+        self.write_xml(&mut document, &mut 0);
+        document.push_str("</root>");
+
+        document
+    }
+
+    fn write_xml(&self, buffer: &mut String, next_service: &mut usize) {
+        buffer.push_str("<device>");
+        dom::write_text_element(buffer, "deviceType", &self.device_type);
+        dom::write_text_element(buffer, "friendlyName", &self.friendly_name);
+        dom::write_text_element(buffer, "manufacturer", &self.manufacturer);
+        write_optional(buffer, "manufacturerURL", &self.manufacturer_url);
+        write_optional(buffer, "modelDescription", &self.model_description);
+        dom::write_text_element(buffer, "modelName", &self.model_name);
+        write_optional(buffer, "modelNumber", &self.model_number);
+        write_optional(buffer, "modelURL", &self.model_url);
+        write_optional(buffer, "serialNumber", &self.serial_number);
+        dom::write_text_element(buffer, "UDN", &self.udn);
+        write_optional(buffer, "UPC", &self.upc);
+
+        if !self.services.is_empty() {
+            buffer.push_str("<serviceList>");
+            for service in &self.services {
+                service.write_xml(buffer, *next_service);
+                *next_service += 1;
+            }
+            buffer.push_str("</serviceList>");
+        }
+
+        if !self.devices.is_empty() {
+            buffer.push_str("<deviceList>");
+            for embedded in &self.devices {
+                embedded.write_xml(buffer, next_service);
+            }
+            buffer.push_str("</deviceList>");
+        }
+
+        write_optional(buffer, "presentationURL", &self.presentation_url);
+        buffer.push_str("</device>");
+    }
+}
+
+impl ServiceModel {
+    pub fn new<S: Into<String>>(service_type: S, service_id: S, scpd: ServiceDescription) -> ServiceModel {
+        ServiceModel {
+            service_type: service_type.into(),
+            service_id: service_id.into(),
+            state: ServiceState::new(&scpd),
+            scpd: scpd,
+            handlers: Vec::new(),
+        }
+    }
+
+    /// State variables of the service, published to its event subscribers.
+    pub fn state(&self) -> &ServiceState {
+        &self.state
+    }
+
+    /// Set the handler invoked for the action, replacing any previous handler.
+    pub fn set_action<H>(mut self, name: &str, handler: H) -> Self
+        where H: ActionHandler + 'static
+    {
+        self.handlers.retain(|&(ref action, _)| action != name);
+        self.handlers.push((name.to_owned(), Arc::new(handler)));
+        self
+    }
+
+    /// Handler invoked for the action, if one was set.
+    pub fn handler(&self, name: &str) -> Option<&ActionHandler> {
+        self.handlers.iter().find(|&&(ref action, _)| action == name).map(|&(_, ref handler)| &**handler)
+    }
+
+    fn write_xml(&self, buffer: &mut String, index: usize) {
+        buffer.push_str("<service>");
+        dom::write_text_element(buffer, "serviceType", &self.service_type);
+        dom::write_text_element(buffer, "serviceId", &self.service_id);
+        dom::write_text_element(buffer, "SCPDURL", &service_path(index, SCPD_DOCUMENT));
+        dom::write_text_element(buffer, "controlURL", &service_path(index, CONTROL_DOCUMENT));
+        dom::write_text_element(buffer, "eventSubURL", &service_path(index, EVENT_DOCUMENT));
+        buffer.push_str("</service>");
+    }
+}
+
+impl Debug for ServiceModel {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let actions: Vec<&String> = self.handlers.iter().map(|&(ref action, _)| action).collect();
+
+        f.debug_struct("ServiceModel")
+            .field("service_type", &self.service_type)
+            .field("service_id", &self.service_id)
+            .field("scpd", &self.scpd)
+            .field("handlers", &actions)
+            .finish()
+    }
+}
+
+fn write_optional(buffer: &mut String, name: &str, text: &Option<String>) {
+    if let Some(ref text) = *text {
+        dom::write_text_element(buffer, name, text);
+    }
+}
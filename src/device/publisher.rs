@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hyper::Client;
+use hyper::Url;
+use hyper::header::{ContentType, Headers};
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use rand::{self, Rng};
+
+use {FieldMap, SSDPResult, SSDPErrorKind};
+use control::Value;
+use description::{self, DataType, ServiceDescription};
+use eventing::{self, encode_propertyset};
+use header::{Callback, HeaderRef, NT, NTS, SEQ, SID, Timeout};
+use net::http::{HttpRequest, HttpResponse};
+
+/// Longest subscription granted, also granted to requests for an infinite one.
+const MAX_SUBSCRIPTION_SECS: u32 = 1800;
+
+/// Time the initial event waits on the subscription response to be sent.
+const INITIAL_EVENT_WAIT_SECS: u64 = 5;
+
+const SUBSCRIBE_METHOD: &'static str = "SUBSCRIBE";
+const UNSUBSCRIBE_METHOD: &'static str = "UNSUBSCRIBE";
+const NOTIFY_METHOD: &'static str = "NOTIFY";
+
+const XML_CONTENT_TYPE: &'static str = "text/xml; charset=\"utf-8\"";
+
+/// Limits on how often changes of an evented state variable are published.
+///
+/// Corresponds to the `maximumRate` and `minimumDelta` of the `UPnP` Device
+/// Architecture; changes that are held back by either are folded into a later event.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Moderation {
+    /// Shortest time between two events holding the variable.
+    pub maximum_rate: Option<Duration>,
+    /// Smallest change of a numeric variable that is published.
+    pub minimum_delta: Option<f64>,
+}
+
+/// Store of the state variables of a service, publishing changes of evented
+/// variables to the subscribers of the service.
+///
+/// Clones share the same store, so a clone can be moved into the action
+/// handlers of the service.
+#[derive(Clone)]
+pub struct ServiceState {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    scpd: ServiceDescription,
+    variables: Mutex<Vec<Variable>>,
+    subscribers: Mutex<HashMap<String, Subscriber>>,
+}
+
+/// Current and last published value of a state variable.
+struct Variable {
+    name: String,
+    evented: bool,
+    value: Value,
+    moderation: Moderation,
+    /// Value last sent to subscribers, initially the value of the initial event.
+    published: Value,
+    published_at: Option<Instant>,
+    /// Whether a change is waiting on the maximum rate to be published.
+    pending: bool,
+}
+
+struct Subscriber {
+    expires: Instant,
+    events: Sender<Vec<(String, String)>>,
+}
+
+impl ServiceState {
+    /// Construct a store holding the default values of the state variables of the service.
+    pub fn new(scpd: &ServiceDescription) -> ServiceState {
+        let variables = scpd.state_variables
+            .iter()
+            .map(|variable| {
+                let value = match variable.default_value {
+                    Some(ref default) => {
+                        Value::parse(&variable.data_type, default)
+                            .unwrap_or_else(|_| Value::from(&default[..]))
+                    }
+                    None => initial_value(&variable.data_type),
+                };
+
+                Variable {
+                    name: variable.name.clone(),
+                    evented: variable.send_events,
+                    value: value.clone(),
+                    moderation: Moderation::default(),
+                    published: value,
+                    published_at: None,
+                    pending: false,
+                }
+            })
+            .collect();
+
+        ServiceState {
+            inner: Arc::new(Inner {
+                scpd: scpd.clone(),
+                variables: Mutex::new(variables),
+                subscribers: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Current value of the state variable.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let variables = self.inner.variables.lock().unwrap();
+
+        variables.iter().find(|variable| variable.name == name).map(|variable| variable.value.clone())
+    }
+
+    /// Set the state variable, publishing the change if the variable is evented.
+    pub fn set<V: Into<Value>>(&self, name: &str, value: V) -> SSDPResult<()> {
+        let value = value.into();
+        let described = match self.inner.scpd.state_variable(name) {
+            Some(described) => described,
+            None => {
+                let msg = "unknown state variable".to_owned();
+                return Err(SSDPErrorKind::InvalidArgument(name.to_owned(), msg).into());
+            }
+        };
+        let value = try!(Value::parse_argument(name, described, &value.to_string()));
+
+        let mut variables = self.inner.variables.lock().unwrap();
+        let variable = variables.iter_mut()
+            .find(|variable| variable.name == name)
+            .expect("Variable Missing From Store");
+
+        variable.value = value;
+        if !variable.evented || variable.pending {
+            return Ok(());
+        }
+
+        if !exceeds_delta(&variable.published, &variable.value, variable.moderation.minimum_delta) {
+            return Ok(());
+        }
+
+        if let (Some(rate), Some(published_at)) = (variable.moderation.maximum_rate, variable.published_at) {
+            let elapsed = published_at.elapsed();
+
+            if elapsed < rate {
+                variable.pending = true;
+                self.schedule_flush(name, rate - elapsed);
+                return Ok(());
+            }
+        }
+
+        variable.published = variable.value.clone();
+        variable.published_at = Some(Instant::now());
+        self.publish(vec![(variable.name.clone(), variable.value.to_string())]);
+
+        Ok(())
+    }
+
+    /// Set the limits on how often changes of the state variable are published.
+    pub fn set_moderation(&self, name: &str, moderation: Moderation) {
+        let mut variables = self.inner.variables.lock().unwrap();
+
+        if let Some(variable) = variables.iter_mut().find(|variable| variable.name == name) {
+            variable.moderation = moderation;
+        }
+    }
+
+    /// Number of subscriptions that have not expired.
+    pub fn subscriber_count(&self) -> usize {
+        let mut subscribers = self.inner.subscribers.lock().unwrap();
+        remove_expired(&mut subscribers);
+
+        subscribers.len()
+    }
+
+    /// Publish a change held back by the maximum rate once the rate allows it.
+    fn schedule_flush(&self, name: &str, delay: Duration) {
+        let state = self.clone();
+        let name = name.to_owned();
+
+        thread::spawn(move || {
+            thread::sleep(delay);
+
+            let mut variables = state.inner.variables.lock().unwrap();
+            if let Some(variable) = variables.iter_mut().find(|variable| variable.name == name) {
+                variable.pending = false;
+
+                // The variable may have changed back while it was held
+                if exceeds_delta(&variable.published, &variable.value, variable.moderation.minimum_delta) {
+                    variable.published = variable.value.clone();
+                    variable.published_at = Some(Instant::now());
+
+                    state.publish(vec![(variable.name.clone(), variable.value.to_string())]);
+                }
+            }
+        });
+    }
+
+    /// Queue the properties for delivery to every subscriber.
+    ///
+    /// Called with the variables locked, so that events are queued in the order
+    /// the variables changed.
+    fn publish(&self, properties: Vec<(String, String)>) {
+        let mut subscribers = self.inner.subscribers.lock().unwrap();
+        remove_expired(&mut subscribers);
+
+        for subscriber in subscribers.values() {
+            let _ = subscriber.events.send(properties.clone());
+        }
+    }
+
+    /// Handle a request made on the event subscription url of the service.
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let headers = &request.headers;
+        let sid = HeaderRef::get::<SID>(headers).map(|sid| sid.0.clone());
+        let has_subscription_headers = HeaderRef::get_raw(headers, "CALLBACK").is_some() ||
+                                       HeaderRef::get_raw(headers, "NT").is_some();
+
+        match (&request.method, sid) {
+            (&Method::Extension(ref method), Some(_)) if has_subscription_headers &&
+                                                         (method == SUBSCRIBE_METHOD ||
+                                                          method == UNSUBSCRIBE_METHOD) => {
+                HttpResponse::empty(StatusCode::BadRequest)
+            }
+            (&Method::Extension(ref method), Some(sid)) if method == SUBSCRIBE_METHOD => {
+                self.renew(&sid, HeaderRef::get::<Timeout>(headers).cloned())
+            }
+            (&Method::Extension(ref method), None) if method == SUBSCRIBE_METHOD => self.subscribe(headers),
+            (&Method::Extension(ref method), Some(sid)) if method == UNSUBSCRIBE_METHOD => {
+                match self.inner.subscribers.lock().unwrap().remove(&sid) {
+                    Some(_) => HttpResponse::empty(StatusCode::Ok),
+                    None => HttpResponse::empty(StatusCode::PreconditionFailed),
+                }
+            }
+            _ => HttpResponse::empty(StatusCode::MethodNotAllowed),
+        }
+    }
+
+    fn subscribe(&self, headers: &Headers) -> HttpResponse {
+        match HeaderRef::get::<NT>(headers) {
+            Some(&NT(FieldMap::UPnP(ref nt))) if nt == eventing::EVENT_NT_VALUE => (),
+            _ => return HttpResponse::empty(StatusCode::PreconditionFailed),
+        }
+        let callbacks: Vec<Url> = match HeaderRef::get::<Callback>(headers) {
+            Some(&Callback(ref urls)) => urls.iter().filter_map(|url| Url::parse(url).ok()).collect(),
+            None => Vec::new(),
+        };
+        if callbacks.is_empty() {
+            return HttpResponse::empty(StatusCode::PreconditionFailed);
+        }
+
+        let sid = format!("uuid:{}", random_uuid());
+        let timeout = granted_timeout(HeaderRef::get::<Timeout>(headers).cloned());
+        let (events, queued) = mpsc::channel();
+        let (sent, ready) = mpsc::channel();
+
+        // Queue the initial event before any change can be published to the subscriber
+        {
+            let variables = self.inner.variables.lock().unwrap();
+            let initial = variables.iter()
+                .filter(|variable| variable.evented)
+                .map(|variable| (variable.name.clone(), variable.value.to_string()))
+                .collect();
+            let _ = events.send(initial);
+
+            self.inner.subscribers.lock().unwrap().insert(sid.clone(),
+                                                          Subscriber {
+                                                              expires: expiry(timeout),
+                                                              events: events,
+                                                          });
+        }
+
+        let delivered_sid = sid.clone();
+        let client = description::default_client();
+        thread::spawn(move || deliver(client, delivered_sid, callbacks, ready, queued));
+
+        subscription_response(sid, timeout).set_after_sent(move || {
+            let _ = sent.send(());
+        })
+    }
+
+    fn renew(&self, sid: &str, requested: Option<Timeout>) -> HttpResponse {
+        let mut subscribers = self.inner.subscribers.lock().unwrap();
+        remove_expired(&mut subscribers);
+
+        match subscribers.get_mut(sid) {
+            Some(subscriber) => {
+                let timeout = granted_timeout(requested);
+                subscriber.expires = expiry(timeout);
+
+                subscription_response(sid.to_owned(), timeout)
+            }
+            None => HttpResponse::empty(StatusCode::PreconditionFailed),
+        }
+    }
+}
+
+/// Send the events queued for a subscription, in order, until it ends.
+fn deliver(client: Client,
+           sid: String,
+           callbacks: Vec<Url>,
+           ready: Receiver<()>,
+           queued: Receiver<Vec<(String, String)>>) {
+    // The initial event must not arrive before the response to the subscription
+    let _ = ready.recv_timeout(Duration::new(INITIAL_EVENT_WAIT_SECS, 0));
+
+    let mut seq = SEQ(0);
+    for properties in queued.iter() {
+        let body = encode_propertyset(&properties);
+
+        // Callbacks are tried in order until one of them accepts the event
+        let delivered = callbacks.iter().any(|callback| {
+            let result = client.request(Method::Extension(NOTIFY_METHOD.to_owned()), callback.clone())
+                .header(ContentType(XML_CONTENT_TYPE.parse().unwrap()))
+                .header(NT(FieldMap::upnp(eventing::EVENT_NT_VALUE)))
+                .header(NTS::PropChange)
+                .header(SID(sid.clone()))
+                .header(seq)
+                .body(&body[..])
+                .send();
+
+            match result {
+                Ok(ref response) if response.status == StatusCode::Ok => true,
+                Ok(response) => {
+                    debug!("Event {} of {} rejected by {}: {}", seq.0, sid, callback, response.status);
+                    false
+                }
+                Err(e) => {
+                    debug!("Failed to deliver event {} of {} to {}: {}", seq.0, sid, callback, e);
+                    false
+                }
+            }
+        });
+        if !delivered {
+            warn!("Event {} of {} could not be delivered", seq.0, sid);
+        }
+
+        seq = seq.next();
+    }
+}
+
+fn subscription_response(sid: String, timeout: u32) -> HttpResponse {
+    let mut response = HttpResponse::empty(StatusCode::Ok);
+
+    response.headers.set(SID(sid));
+    response.headers.set(Timeout::Seconds(timeout));
+    response
+}
+
+/// Number of seconds granted to a subscription that requested the timeout.
+fn granted_timeout(requested: Option<Timeout>) -> u32 {
+    match requested {
+        Some(Timeout::Seconds(secs)) if secs > 0 && secs < MAX_SUBSCRIPTION_SECS => secs,
+        _ => MAX_SUBSCRIPTION_SECS,
+    }
+}
+
+fn expiry(timeout: u32) -> Instant {
+    Instant::now() + Duration::new(timeout as u64, 0)
+}
+
+/// Drop subscriptions that were not renewed in time, ending their delivery.
+fn remove_expired(subscribers: &mut HashMap<String, Subscriber>) {
+    let now = Instant::now();
+
+    subscribers.retain(|_, subscriber| subscriber.expires > now);
+}
+
+/// Whether a change from the published value is large enough to be published.
+fn exceeds_delta(published: &Value, value: &Value, minimum_delta: Option<f64>) -> bool {
+    match (published.as_f64(), value.as_f64(), minimum_delta) {
+        (Some(published), Some(value), Some(delta)) => (value - published).abs() >= delta,
+        _ => published != value,
+    }
+}
+
+/// Value of a state variable without a default value.
+fn initial_value(data_type: &DataType) -> Value {
+    match *data_type {
+        DataType::Boolean => Value::Boolean(false),
+        _ if data_type.is_numeric() => Value::parse(data_type, "0").unwrap_or_else(|_| Value::from("")),
+        _ => Value::from(""),
+    }
+}
+
+/// Generate a random (version 4) UUID.
+fn random_uuid() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}",
+            hex[0..4].concat(),
+            hex[4..6].concat(),
+            hex[6..8].concat(),
+            hex[8..10].concat(),
+            hex[10..16].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use hyper::header::Headers;
+    use hyper::method::Method;
+    use hyper::status::StatusCode;
+
+    use description::{self, ServiceDescription, RENDERING_CONTROL_SCPD};
+    use eventing::{Event, EventListener};
+    use header::SID;
+    use net::http::HttpRequest;
+    use super::{Moderation, ServiceState};
+    use super::super::{DeviceModel, DeviceServer, ServiceModel};
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+    fn volume(event: Event) -> (u32, Vec<(String, String)>) {
+        (event.seq, event.properties)
+    }
+
+    fn media_renderer() -> (DeviceServer, ServiceState) {
+        let scpd = ServiceDescription::parse(RENDERING_CONTROL_SCPD.as_bytes()).unwrap();
+        let service = ServiceModel::new("urn:schemas-upnp-org:service:RenderingControl:1",
+                                        "urn:upnp-org:serviceId:RenderingControl",
+                                        scpd);
+        let state = service.state().clone();
+
+        let mut model = DeviceModel::new("urn:schemas-upnp-org:device:MediaRenderer:1",
+                                         "Living Room",
+                                         "Example",
+                                         "Renderer",
+                                         "uuid:renderer-1");
+        model.services.push(service);
+
+        (DeviceServer::bind(model, &[LOCALHOST]).unwrap(), state)
+    }
+
+    #[test]
+    fn positive_subscribe_and_notify() {
+        let (server, state) = media_renderer();
+        let description = description::fetch(&server.location(LOCALHOST).unwrap()).unwrap();
+        let listener = EventListener::bind((LOCALHOST, 0)).unwrap();
+        let subscription = listener.subscribe(&description.device.services[0]).unwrap();
+        let wait = Duration::new(5, 0);
+
+        assert_eq!(volume(subscription.recv_timeout(wait).unwrap()),
+                   (0, vec![("Volume".to_owned(), "20".to_owned())]));
+        assert_eq!(state.subscriber_count(), 1);
+
+        state.set("Volume", 42u16).unwrap();
+        state.set("A_ARG_TYPE_InstanceID", 7u32).unwrap();
+        assert_eq!(volume(subscription.recv_timeout(wait).unwrap()),
+                   (1, vec![("Volume".to_owned(), "42".to_owned())]));
+
+        subscription.unsubscribe().unwrap();
+        assert_eq!(state.subscriber_count(), 0);
+    }
+
+    #[test]
+    fn positive_minimum_delta() {
+        let (server, state) = media_renderer();
+        let description = description::fetch(&server.location(LOCALHOST).unwrap()).unwrap();
+        let listener = EventListener::bind((LOCALHOST, 0)).unwrap();
+        let subscription = listener.subscribe(&description.device.services[0]).unwrap();
+        subscription.recv_timeout(Duration::new(5, 0)).unwrap();
+
+        state.set_moderation("Volume",
+                             Moderation {
+                                 maximum_rate: None,
+                                 minimum_delta: Some(5.0),
+                             });
+        state.set("Volume", 22u16).unwrap();
+        assert!(subscription.recv_timeout(Duration::from_millis(300)).is_err());
+
+        state.set("Volume", 26u16).unwrap();
+        assert_eq!(volume(subscription.recv_timeout(Duration::new(5, 0)).unwrap()),
+                   (1, vec![("Volume".to_owned(), "26".to_owned())]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_renew_unknown_sid() {
+        let (_server, state) = media_renderer();
+        let mut headers = Headers::new();
+        headers.set(SID("uuid:unknown".to_owned()));
+
+        let request = HttpRequest {
+            method: Method::Extension("SUBSCRIBE".to_owned()),
+            path: "/service/0/event".to_owned(),
+            headers: headers,
+            body: Vec::new(),
+        };
+
+        assert_eq!(state.handle(&request).status, StatusCode::Ok);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_value_out_of_range() {
+        let (_server, state) = media_renderer();
+
+        state.set("Volume", 101u16).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+//! Discovery of DIAL servers and the status of their applications.
+//!
+//! DIAL servers, such as smart TVs and streaming sticks, answer searches for the
+//! DIAL service type. The response to the request for their description document
+//! carries an `Application-URL` header, and the status of an application is found
+//! by requesting the application name relative to that url.
+
+use std::io::Read;
+
+use hyper::Client;
+use hyper::Url;
+use hyper::header::{Header, Location};
+use hyper::status::StatusCode;
+
+use {FieldMap, SSDPResult, SSDPErrorKind};
+use description::{self, Description};
+use discovery::{self, DiscoverOptions};
+use dom::Element;
+use header::{ApplicationURL, HeaderRef, ST, WakeUp};
+
+/// Search target and service type of DIAL servers.
+pub const DIAL_SERVICE_TYPE: &'static str = "urn:dial-multiscreen-org:service:dial:1";
+
+/// Root element of an application status document.
+const STATUS_ROOT_ELEMENT: &'static str = "service";
+/// Prefix of the application state when it can be installed from a url.
+const INSTALLABLE_PREFIX: &'static str = "installable=";
+
+/// A DIAL server found on the network.
+#[derive(Clone, Debug)]
+pub struct DialDevice {
+    /// URL of the description document of the server.
+    pub location: String,
+    /// Base url of the applications on the server.
+    pub application_url: Url,
+    /// Hardware address and wake up time, if the server can be woken from sleep.
+    pub wakeup: Option<WakeUp>,
+    pub description: Description,
+}
+
+impl DialDevice {
+    /// Fetch the description advertised by a search response, reading the
+    /// `Application-URL` header sent along with it.
+    pub fn from_message<T>(client: &Client, message: &T) -> SSDPResult<DialDevice>
+        where T: HeaderRef
+    {
+        let location = match message.get::<Location>() {
+            Some(&Location(ref location)) => location.clone(),
+            None => return Err(SSDPErrorKind::MissingHeader(Location::header_name()).into()),
+        };
+        let url = try!(Url::parse(&location));
+
+        debug!("Fetching DIAL description at {}", url);
+        let mut response = try!(client.get(url.clone()).send());
+        if response.status != StatusCode::Ok {
+            return Err(SSDPErrorKind::ResponseCode(response.status.to_u16()).into());
+        }
+
+        let application_url = match response.headers.get::<ApplicationURL>() {
+            Some(&ApplicationURL(ref application_url)) => {
+                as_directory(try!(description::resolve_url(&url, application_url)))
+            }
+            None => return Err(SSDPErrorKind::MissingHeader(ApplicationURL::header_name()).into()),
+        };
+
+        let mut document = Vec::new();
+        try!(response.read_to_end(&mut document));
+
+        Ok(DialDevice {
+            location: location,
+            application_url: application_url,
+            wakeup: message.get::<WakeUp>().cloned(),
+            description: try!(Description::parse(&url, &document[..])),
+        })
+    }
+
+    /// URL of the application with the given name.
+    pub fn app_url(&self, name: &str) -> SSDPResult<Url> {
+        description::resolve_url(&self.application_url, name)
+    }
+
+    /// Query the status of the application, returning None if the server does
+    /// not know the application.
+    pub fn app_status(&self, name: &str) -> SSDPResult<Option<AppStatus>> {
+        self.app_status_with_client(&description::default_client(), name)
+    }
+
+    /// Query the status of the application using the client.
+    pub fn app_status_with_client(&self, client: &Client, name: &str) -> SSDPResult<Option<AppStatus>> {
+        let url = try!(self.app_url(name));
+
+        debug!("Querying DIAL application status at {}", url);
+        let mut response = try!(client.get(url.clone()).send());
+
+        let mut document = Vec::new();
+        try!(response.read_to_end(&mut document));
+
+        match response.status {
+            StatusCode::Ok => AppStatus::parse(&url, &document[..]).map(Some),
+            StatusCode::NotFound => Ok(None),
+            status => Err(SSDPErrorKind::ResponseCode(status.to_u16()).into()),
+        }
+    }
+}
+
+/// Enumerates the states an application on a DIAL server can be in.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AppState {
+    Running,
+    Stopped,
+    /// Running, but not visible to the user. Introduced in DIAL 2.1.
+    Hidden,
+    /// Not installed, but can be installed from the url.
+    Installable(Url),
+}
+
+/// Status of an application on a DIAL server.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AppStatus {
+    pub name: String,
+    pub state: AppState,
+    /// Whether a running instance of the application can be stopped.
+    pub allow_stop: bool,
+    /// URL of the running instance of the application.
+    pub instance_url: Option<Url>,
+    /// Version of the DIAL specification the server implements.
+    pub dial_version: Option<String>,
+}
+
+impl AppStatus {
+    /// Parse the status document of the application at the url.
+    pub fn parse(app_url: &Url, document: &[u8]) -> SSDPResult<AppStatus> {
+        let root = try!(Element::parse(document));
+        if root.name != STATUS_ROOT_ELEMENT {
+            let msg = format!("unexpected root element <{}>", root.name);
+            return Err(SSDPErrorKind::InvalidDocument(msg).into());
+        }
+
+        let state = match try!(root.required_text("state")) {
+            "running" => AppState::Running,
+            "stopped" => AppState::Stopped,
+            "hidden" => AppState::Hidden,
+            state if state.starts_with(INSTALLABLE_PREFIX) => {
+                AppState::Installable(try!(Url::parse(&state[INSTALLABLE_PREFIX.len()..]).map_err(|err| {
+                    SSDPErrorKind::InvalidDocument(format!("invalid installable url '{}': {}", state, err))
+                })))
+            }
+            state => return Err(SSDPErrorKind::InvalidDocument(format!("unknown state '{}'", state)).into()),
+        };
+
+        // Links are relative to the application url as a directory
+        let run_link = root.children_named("link")
+            .into_iter()
+            .find(|link| link.attribute("rel") == Some("run"));
+        let instance_url = match run_link.and_then(|link| link.attribute("href")) {
+            Some(href) => Some(try!(description::resolve_url(&as_directory(app_url.clone()), href))),
+            None => None,
+        };
+
+        let allow_stop = root.child("options").and_then(|options| options.attribute("allowStop"));
+
+        Ok(AppStatus {
+            name: try!(root.required_text("name")).to_owned(),
+            state: state,
+            allow_stop: allow_stop == Some("true"),
+            instance_url: instance_url,
+            dial_version: root.attribute("dialVer").map(str::to_owned),
+        })
+    }
+}
+
+/// Search the network for DIAL servers, fetching the description of each one.
+///
+/// Servers whose description could not be fetched are skipped.
+pub fn discover(options: DiscoverOptions) -> SSDPResult<Vec<DialDevice>> {
+    let target = ST::Target(FieldMap::new(DIAL_SERVICE_TYPE).expect("Invalid DIAL Service Type"));
+    let client = description::default_client();
+
+    let devices = try!(discovery::search(target, &options))
+        .into_iter()
+        .filter_map(|unique| match DialDevice::from_message(&client, unique.message()) {
+            Ok(device) => Some(device),
+            Err(e) => {
+                debug!("Failed to fetch DIAL description from {:?}: {}", unique.sources(), e);
+                None
+            }
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+/// Make sure relative urls are resolved beneath the url rather than beside it.
+fn as_directory(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Url;
+    use hyper::status::StatusCode;
+
+    use description::mocks::{MockResponse, MockServer};
+    use header::{ApplicationURL, HeaderMut, Location, WakeUp};
+    use message::SearchResponse;
+    use super::{AppState, AppStatus, DialDevice};
+
+    const DESCRIPTION: &'static str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <device>
+    <deviceType>urn:dial-multiscreen-org:device:dial:1</deviceType>
+    <friendlyName>Living Room TV</friendlyName>
+    <manufacturer>Example</manufacturer>
+    <modelName>TV</modelName>
+    <UDN>uuid:dial-1</UDN>
+  </device>
+</root>"#;
+
+    const YOUTUBE_STATUS: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<service xmlns="urn:dial-multiscreen-org:schemas:dial" dialVer="2.1">
+  <name>YouTube</name>
+  <options allowStop="true"/>
+  <state>running</state>
+  <link rel="run" href="run"/>
+</service>"#;
+
+    fn dial_server() -> MockServer {
+        MockServer::new(|request| match &request.path[..] {
+            "/dd.xml" => {
+                let mut response = MockResponse::ok(DESCRIPTION);
+                response.headers.set(ApplicationURL("/apps".to_owned()));
+                response
+            }
+            "/apps/YouTube" => MockResponse::ok(YOUTUBE_STATUS),
+            _ => MockResponse::with_status(StatusCode::NotFound, ""),
+        })
+    }
+
+    fn dial_device(server: &MockServer) -> DialDevice {
+        let mut message = SearchResponse::new();
+        message.set(Location(server.url("/dd.xml")));
+        message.set_raw("WAKEUP", vec![b"MAC=10:dd:b1:c9:00:e4;Timeout=10".to_vec()]);
+
+        DialDevice::from_message(&::description::default_client(), &message).unwrap()
+    }
+
+    #[test]
+    fn positive_device_from_message() {
+        let server = dial_server();
+        let device = dial_device(&server);
+
+        assert_eq!(device.application_url.as_str(), server.url("/apps/"));
+        assert_eq!(device.description.device.friendly_name, "Living Room TV");
+        assert_eq!(device.wakeup.map(|wakeup: WakeUp| wakeup.timeout), Some(10));
+    }
+
+    #[test]
+    fn positive_app_status() {
+        let server = dial_server();
+        let status = dial_device(&server).app_status("YouTube").unwrap().unwrap();
+
+        assert_eq!(status.name, "YouTube");
+        assert_eq!(status.state, AppState::Running);
+        assert!(status.allow_stop);
+        assert_eq!(status.instance_url.unwrap().as_str(), server.url("/apps/YouTube/run"));
+        assert_eq!(status.dial_version, Some("2.1".to_owned()));
+    }
+
+    #[test]
+    fn positive_unknown_app() {
+        let server = dial_server();
+
+        assert!(dial_device(&server).app_status("Netflix").unwrap().is_none());
+    }
+
+    #[test]
+    fn positive_installable() {
+        let url = Url::parse("http://192.168.1.10:8008/apps/Netflix").unwrap();
+        let status = AppStatus::parse(&url,
+                                      b"<service><name>Netflix</name>\
+                                        <state>installable=http://store.example/netflix</state></service>")
+            .unwrap();
+
+        assert_eq!(status.state,
+                   AppState::Installable(Url::parse("http://store.example/netflix").unwrap()));
+        assert!(!status.allow_stop);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_missing_application_url() {
+        let server = MockServer::new(|_| MockResponse::ok(DESCRIPTION));
+        let mut message = SearchResponse::new();
+        message.set(Location(server.url("/dd.xml")));
+
+        DialDevice::from_message(&::description::default_client(), &message).unwrap();
+    }
+}
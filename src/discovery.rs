@@ -0,0 +1,377 @@
+//! High level discovery of devices and services on the network.
+//!
+//! This module wraps the messaging primitives for the common case of searching
+//! for some target, waiting a fixed amount of time, and collecting one record
+//! for every unique device or service that answered.
+
+use std::fmt::{self, Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "description")]
+use hyper::header::Header;
+use hyper::header::{CacheControl, CacheDirective, Location, Server};
+
+use FieldMap;
+#[cfg(feature = "description")]
+use description::{self, Description};
+#[cfg(feature = "description")]
+use error::SSDPErrorKind;
+use error::SSDPResult;
+use header::{BootID, HeaderMut, HeaderRef, Man, MX, NT, ST, USN};
+use message::{Config, SearchRequest, SearchResponse, SearchSchedule};
+use receiver::{DedupKey, Deduplicated};
+
+/// Default time spent waiting for devices to respond.
+const DEFAULT_DISCOVER_TIMEOUT_SECS: u64 = 3;
+
+/// Default `MX` value sent with discovery searches.
+const DEFAULT_DISCOVER_MX: u8 = 2;
+
+/// Separator between the parts of a device or service type.
+const TYPE_SEPARATOR: char = ':';
+
+/// Type name for devices within a device or service type.
+const DEVICE_KIND: &'static str = "device";
+/// Type name for services within a device or service type.
+const SERVICE_KIND: &'static str = "service";
+
+/// Search the network for the target, returning a record for every unique
+/// device or service that responded before the timeout.
+pub fn discover(target: ST, options: DiscoverOptions) -> SSDPResult<Vec<Device>> {
+    let devices = try!(search(target, &options))
+        .into_iter()
+        .filter_map(|unique| {
+            let interfaces: Vec<IpAddr> = unique.local_addrs().iter().map(SocketAddr::ip).collect();
+
+            Device::from_message(unique.message(), unique.sources(), &interfaces[..])
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+/// Search the network for the target, returning every unique response received
+/// before the timeout.
+///
+/// Useful for protocols built on top of discovery that need headers not kept
+/// by `Device`.
+pub fn search(target: ST, options: &DiscoverOptions) -> SSDPResult<Vec<Deduplicated<SearchResponse>>> {
+    let mut request = SearchRequest::new();
+
+    request.set(Man);
+    request.set(options.mx);
+    request.set(target);
+
+    let start = Instant::now();
+    let schedule = options.schedule.set_window(options.timeout);
+    let mut recv = try!(request.multicast_with_schedule(&options.config, &schedule));
+    // Timeouts too long to add to an instant are never reached, so they set no deadline
+    if let Some(deadline) = start.checked_add(options.timeout) {
+        recv = recv.set_deadline(deadline);
+    }
+
+    Ok(recv.dedup(options.dedup).collect_all())
+}
+
+/// Options for discovering devices and services.
+#[derive(Clone)]
+pub struct DiscoverOptions {
+    config: Config,
+    mx: MX,
+    timeout: Duration,
+    schedule: SearchSchedule,
+    dedup: DedupKey,
+}
+
+impl DiscoverOptions {
+    /// Construct a new DiscoverOptions with default values.
+    pub fn new() -> DiscoverOptions {
+        DiscoverOptions {
+            config: Config::default(),
+            mx: MX(DEFAULT_DISCOVER_MX),
+            timeout: Duration::new(DEFAULT_DISCOVER_TIMEOUT_SECS, 0),
+            schedule: SearchSchedule::default(),
+            dedup: DedupKey::USN,
+        }
+    }
+
+    /// Set the multicast configuration used to send the search.
+    pub fn set_config(mut self, value: Config) -> Self {
+        self.config = value;
+        self
+    }
+
+    /// Set the `MX` value sent with the search.
+    pub fn set_mx(mut self, value: MX) -> Self {
+        self.mx = value;
+        self
+    }
+
+    /// Set the total time spent waiting for responses.
+    pub fn set_timeout(mut self, value: Duration) -> Self {
+        self.timeout = value;
+        self
+    }
+
+    /// Set the schedule used to transmit the search.
+    pub fn set_schedule(mut self, value: SearchSchedule) -> Self {
+        self.schedule = value;
+        self
+    }
+
+    /// Set the key used to decide whether two responses are from the same device.
+    pub fn set_dedup(mut self, value: DedupKey) -> Self {
+        self.dedup = value;
+        self
+    }
+}
+
+impl Default for DiscoverOptions {
+    fn default() -> Self {
+        DiscoverOptions::new()
+    }
+}
+
+/// Enumerates the kinds of types defined by the `UPnP` standard.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum URNKind {
+    /// A device type, such as `urn:schemas-upnp-org:device:MediaRenderer:1`.
+    Device,
+    /// A service type, such as `urn:schemas-upnp-org:service:AVTransport:1`.
+    Service,
+}
+
+/// A device or service type, such as `urn:schemas-upnp-org:device:MediaRenderer:1`.
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TypeURN {
+    /// Domain name of the vendor that defined the type.
+    pub domain: String,
+    /// Whether the type describes a device or a service.
+    pub kind: URNKind,
+    /// Name of the type.
+    pub name: String,
+    /// Version of the type.
+    pub version: u32,
+}
+
+impl TypeURN {
+    /// Parse a type from the value of a `FieldMap::URN`, which is everything
+    /// following the `urn:` prefix.
+    pub fn parse(value: &str) -> Option<TypeURN> {
+        let parts: Vec<&str> = value.split(TYPE_SEPARATOR).collect();
+        if parts.len() != 4 || parts[0].is_empty() || parts[2].is_empty() {
+            return None;
+        }
+
+        let kind = match parts[1] {
+            DEVICE_KIND => URNKind::Device,
+            SERVICE_KIND => URNKind::Service,
+            _ => return None,
+        };
+
+        parts[3].parse().ok().map(|version| {
+            TypeURN {
+                domain: parts[0].to_owned(),
+                kind: kind,
+                name: parts[2].to_owned(),
+                version: version,
+            }
+        })
+    }
+
+    /// Parse a type from a `FieldMap`, which must be a `FieldMap::URN`.
+    pub fn from_field(field: &FieldMap) -> Option<TypeURN> {
+        match *field {
+            FieldMap::URN(ref value) => TypeURN::parse(value),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TypeURN {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            URNKind::Device => DEVICE_KIND,
+            URNKind::Service => SERVICE_KIND,
+        };
+
+        write!(f, "urn:{}:{}:{}:{}", self.domain, kind, self.name, self.version)
+    }
+}
+
+/// A device or service found on the network.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Device {
+    /// Unique service name of the device or service.
+    pub usn: USN,
+    /// UUID of the device, without the `uuid:` prefix.
+    pub uuid: Option<String>,
+    /// Device type, if the message was about a device.
+    pub device_type: Option<TypeURN>,
+    /// Service type, if the message was about a service.
+    pub service_type: Option<TypeURN>,
+    /// URL of the description document for the root device.
+    pub location: Option<String>,
+    /// Operating system, `UPnP` version, and product of the device.
+    pub server: Option<String>,
+    /// Number of seconds the advertisement is valid for.
+    pub max_age: Option<u32>,
+    /// Boot instance of the root device.
+    pub boot_id: Option<BootID>,
+    /// Addresses the message was received from.
+    pub sources: Vec<SocketAddr>,
+    /// Addresses of the local interfaces the message was received on.
+    pub interfaces: Vec<IpAddr>,
+}
+
+impl Device {
+    /// Construct a Device from a search response or notify message, returning
+    /// None if the message has no `USN` header.
+    pub fn from_message<T>(message: &T, sources: &[SocketAddr], interfaces: &[IpAddr]) -> Option<Device>
+        where T: HeaderRef
+    {
+        let usn = match message.get::<USN>() {
+            Some(usn) => usn.clone(),
+            None => return None,
+        };
+
+        let uuid = match usn.0 {
+            FieldMap::UUID(ref uuid) => Some(uuid.clone()),
+            _ => None,
+        };
+
+        // The type can be found in the search target or notification type, with
+        // the second field of the unique service name as a fallback.
+        let opt_type = match (message.get::<ST>(), message.get::<NT>()) {
+            (Some(&ST::Target(ref field)), _) => TypeURN::from_field(field),
+            (_, Some(&NT(ref field))) => TypeURN::from_field(field),
+            _ => None,
+        };
+        let opt_type = opt_type.or_else(|| usn.1.as_ref().and_then(TypeURN::from_field));
+
+        let (device_type, service_type) = match opt_type {
+            Some(ref urn) if urn.kind == URNKind::Device => (opt_type.clone(), None),
+            Some(_) => (None, opt_type.clone()),
+            None => (None, None),
+        };
+
+        let max_age = message.get::<CacheControl>().and_then(|directives| {
+            directives.iter()
+                .filter_map(|directive| match *directive {
+                    CacheDirective::MaxAge(n) => Some(n),
+                    _ => None,
+                })
+                .next()
+        });
+
+        Some(Device {
+            usn: usn,
+            uuid: uuid,
+            device_type: device_type,
+            service_type: service_type,
+            location: message.get::<Location>().map(|location| location.0.clone()),
+            server: message.get::<Server>().map(|server| server.0.clone()),
+            max_age: max_age,
+            boot_id: message.get::<BootID>().cloned(),
+            sources: sources.to_vec(),
+            interfaces: interfaces.to_vec(),
+        })
+    }
+
+    /// Fetch and parse the description document of the root device.
+    #[cfg(feature = "description")]
+    pub fn fetch_description(&self) -> SSDPResult<Description> {
+        match self.location {
+            Some(ref location) => description::fetch(location),
+            None => Err(SSDPErrorKind::MissingHeader(Location::header_name()).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use FieldMap;
+    use header::BootID;
+    use message::{NotifyMessage, SearchResponse};
+    use receiver::FromRawSSDP;
+    use super::{Device, TypeURN, URNKind};
+
+    #[test]
+    fn positive_parse_device_type() {
+        let urn = TypeURN::parse("schemas-upnp-org:device:MediaRenderer:1").unwrap();
+
+        assert_eq!(urn.domain, "schemas-upnp-org");
+        assert_eq!(urn.kind, URNKind::Device);
+        assert_eq!(urn.name, "MediaRenderer");
+        assert_eq!(urn.version, 1);
+        assert_eq!(urn.to_string(), "urn:schemas-upnp-org:device:MediaRenderer:1");
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_parse_unknown_kind() {
+        TypeURN::parse("schemas-upnp-org:thing:MediaRenderer:1").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_parse_bad_version() {
+        TypeURN::parse("schemas-upnp-org:device:MediaRenderer:one").unwrap();
+    }
+
+    #[test]
+    fn positive_device_from_response() {
+        let raw = "HTTP/1.1 200 OK\r\n\
+                   CACHE-CONTROL: max-age=1800\r\n\
+                   LOCATION: http://192.168.1.2:8080/description.xml\r\n\
+                   SERVER: Linux/3.14 UPnP/1.1 Renderer/1.0\r\n\
+                   ST: urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+                   USN: uuid:1234::urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+                   BOOTID.UPNP.ORG: 7\r\n\r\n";
+        let response = SearchResponse::raw_ssdp(raw.as_bytes()).unwrap();
+        let src: SocketAddr = "192.168.1.2:1900".parse().unwrap();
+        let iface: IpAddr = "192.168.1.3".parse().unwrap();
+
+        let device = Device::from_message(&response, &[src], &[iface]).unwrap();
+
+        assert_eq!(device.uuid, Some("1234".to_owned()));
+        assert_eq!(device.device_type.unwrap().name, "MediaRenderer");
+        assert!(device.service_type.is_none());
+        assert_eq!(device.location, Some("http://192.168.1.2:8080/description.xml".to_owned()));
+        assert_eq!(device.server, Some("Linux/3.14 UPnP/1.1 Renderer/1.0".to_owned()));
+        assert_eq!(device.max_age, Some(1800));
+        assert_eq!(device.boot_id, Some(BootID(7)));
+        assert_eq!(device.sources, vec![src]);
+        assert_eq!(device.interfaces, vec![iface]);
+    }
+
+    #[test]
+    fn positive_service_from_notify() {
+        let raw = "NOTIFY * HTTP/1.1\r\n\
+                   HOST: 239.255.255.250:1900\r\n\
+                   NT: urn:schemas-upnp-org:service:AVTransport:1\r\n\
+                   NTS: ssdp:alive\r\n\
+                   USN: uuid:1234::urn:schemas-upnp-org:service:AVTransport:1\r\n\r\n";
+        let message = NotifyMessage::raw_ssdp(raw.as_bytes()).unwrap();
+
+        let device = Device::from_message(&message, &[], &[]).unwrap();
+
+        assert!(device.device_type.is_none());
+        assert_eq!(device.service_type.unwrap().kind, URNKind::Service);
+        assert_eq!(device.usn.0, FieldMap::uuid("1234"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_device_without_usn() {
+        let response = SearchResponse::raw_ssdp(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+
+        Device::from_message(&response, &[], &[]).unwrap();
+    }
+}
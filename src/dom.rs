@@ -0,0 +1,166 @@
+//! Minimal document tree for the XML documents exchanged with `UPnP` devices.
+//!
+//! Documents served by devices are small, so they are read into memory in full
+//! and navigated by the local names of their elements, ignoring namespaces.
+
+use xml::reader::{EventReader, XmlEvent};
+
+use {SSDPResult, SSDPErrorKind};
+
+/// An element within an XML document.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Element {
+    /// Local name of the element, without any namespace prefix.
+    pub name: String,
+    /// Attributes of the element as local name and value pairs.
+    pub attributes: Vec<(String, String)>,
+    /// Text directly contained within the element.
+    pub text: String,
+    /// Child elements, in document order.
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    /// Construct a new Element with no attributes, text or children.
+    pub fn new<S: Into<String>>(name: S) -> Element {
+        Element {
+            name: name.into(),
+            attributes: Vec::new(),
+            text: String::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Parse the root element of an XML document.
+    pub fn parse(bytes: &[u8]) -> SSDPResult<Element> {
+        let mut stack: Vec<Element> = Vec::new();
+
+        for event in EventReader::new(bytes) {
+            match try!(event.map_err(|err| SSDPErrorKind::InvalidDocument(err.to_string()))) {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    let mut element = Element::new(name.local_name);
+                    element.attributes = attributes.into_iter()
+                        .map(|attr| (attr.name.local_name, attr.value))
+                        .collect();
+
+                    stack.push(element);
+                }
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().expect("xml-rs Reported An Unbalanced End Element");
+
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                XmlEvent::Characters(text) |
+                XmlEvent::CData(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Err(SSDPErrorKind::InvalidDocument("document has no root element".to_owned()).into())
+    }
+
+    /// First child element with the given local name.
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// All child elements with the given local name.
+    pub fn children_named(&self, name: &str) -> Vec<&Element> {
+        self.children.iter().filter(|child| child.name == name).collect()
+    }
+
+    /// Trimmed text of the first child element with the given local name.
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.trim())
+    }
+
+    /// Trimmed text of the first child element with the given local name, or an
+    /// error naming the missing element.
+    pub fn required_text(&self, name: &str) -> SSDPResult<&str> {
+        match self.child_text(name) {
+            Some(text) => Ok(text),
+            None => {
+                Err(SSDPErrorKind::InvalidDocument(format!("<{}> missing <{}>", self.name, name)).into())
+            }
+        }
+    }
+
+    /// Value of the attribute with the given local name.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|&&(ref key, _)| key == name).map(|&(_, ref value)| &value[..])
+    }
+}
+
+/// Escape text for inclusion within an XML element or attribute value.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
+
+/// Append an element containing only escaped text to the buffer.
+pub fn write_text_element(buffer: &mut String, name: &str, text: &str) {
+    buffer.push_str(&format!("<{0}>{1}</{0}>", name, escape(text)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, Element};
+
+    #[test]
+    fn positive_parse_nested() {
+        let doc = b"<?xml version=\"1.0\"?>\
+                    <root xmlns=\"urn:x\"><a>one</a><b><c> two </c></b><a>three</a></root>";
+        let root = Element::parse(&doc[..]).unwrap();
+
+        assert_eq!(root.name, "root");
+        assert_eq!(root.child_text("a"), Some("one"));
+        assert_eq!(root.children_named("a").len(), 2);
+        assert_eq!(root.child("b").unwrap().child_text("c"), Some("two"));
+    }
+
+    #[test]
+    fn positive_parse_prefixed_names() {
+        let doc = b"<s:Envelope xmlns:s=\"urn:s\" s:encodingStyle=\"x\"><s:Body/></s:Envelope>";
+        let root = Element::parse(&doc[..]).unwrap();
+
+        assert_eq!(root.name, "Envelope");
+        assert_eq!(root.attribute("encodingStyle"), Some("x"));
+        assert!(root.child("Body").is_some());
+    }
+
+    #[test]
+    fn positive_escape() {
+        assert_eq!(escape("a<b & 'c'"), "a&lt;b &amp; &apos;c&apos;");
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_unbalanced() {
+        Element::parse(&b"<root><a></root>"[..]).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_missing_required() {
+        Element::parse(&b"<root></root>"[..]).unwrap().required_text("a").unwrap();
+    }
+}
@@ -54,6 +54,48 @@ error_chain! {
             description("invalid header")
             display("invalid header: '{}': {}", header, msg)
         }
+        /// Document retrieved from or sent to a device is not valid.
+        ///
+        /// Error message is supplied.
+        InvalidDocument(msg:String) {
+            description("invalid document")
+            display("invalid document: {}", msg)
+        }
+        /// Device responded to an action with a `UPnP` error.
+        ///
+        /// Error code and description are supplied.
+        UPnPError(code:u16, description:String) {
+            description("UPnP error response")
+            display("UPnP error response: {} {}", code, description)
+        }
+        /// Argument supplied for an action is not valid.
+        ///
+        /// Argument name with error message are supplied.
+        InvalidArgument(argument:String, msg:String) {
+            description("invalid argument")
+            display("invalid argument: '{}': {}", argument, msg)
+        }
+        /// No device on the network offered what was searched for.
+        ///
+        /// Search target is supplied.
+        DeviceNotFound(target:String) {
+            description("device not found")
+            display("no device found for '{}'", target)
+        }
+        /// Data exchanged with a local daemon, such as minissdpd, is malformed.
+        ///
+        /// Error message is supplied.
+        InvalidPacket(msg:String) {
+            description("invalid packet")
+            display("invalid packet: {}", msg)
+        }
+        /// Packet capture is not a valid pcap or pcapng file.
+        ///
+        /// Error message is supplied.
+        InvalidCapture(msg:String) {
+            description("invalid capture")
+            display("invalid capture: {}", msg)
+        }
     }
 
     foreign_links {
@@ -0,0 +1,523 @@
+//! Subscription to the events of services on discovered devices.
+//!
+//! Subscriptions are made with a `SUBSCRIBE` request to the event subscription
+//! url of a service, giving a callback url on a local `EventListener`. The
+//! device then delivers `NOTIFY` requests holding a property set of changed
+//! state variables to that url, numbered by the `SEQ` header so that missed
+//! events can be detected.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use hyper::Client;
+use hyper::Url;
+use hyper::header::{Header, Headers};
+use hyper::method::Method;
+use hyper::status::StatusCode;
+
+use {SSDPResult, SSDPErrorKind, FieldMap};
+use description::{self, ServiceEntry};
+use header::{Callback, HeaderRef, NT, NTS, SEQ, SID, Timeout};
+use net::http::{HttpRequest, HttpResponse, HttpServer};
+
+mod propertyset;
+
+pub use self::propertyset::{decode_propertyset, encode_propertyset};
+
+/// Value of the `NT` header for event subscriptions and messages.
+pub const EVENT_NT_VALUE: &'static str = "event";
+
+/// Timeout requested for subscriptions when none is given.
+pub const DEFAULT_SUBSCRIPTION_SECS: u32 = 1800;
+
+/// Minimum time to wait between renewals of a subscription.
+const MIN_RENEWAL_SECS: u64 = 1;
+
+/// Distance from the ends of the range of sequence numbers within which a
+/// lower sequence number than expected is taken to have wrapped around.
+const SEQ_WRAP_WINDOW: u32 = 1 << 16;
+
+const SUBSCRIBE_METHOD: &'static str = "SUBSCRIBE";
+const UNSUBSCRIBE_METHOD: &'static str = "UNSUBSCRIBE";
+const NOTIFY_METHOD: &'static str = "NOTIFY";
+
+/// Path prefix of the callback urls handed out by an `EventListener`.
+const CALLBACK_PATH_PREFIX: &'static str = "/event/";
+
+/// Changed state variables delivered for a subscription.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Event {
+    /// Identifier of the subscription the event was delivered for.
+    pub sid: String,
+    /// Sequence number of the event, zero for the initial event.
+    pub seq: u32,
+    /// Number of events skipped between the previous event and this one.
+    pub missed: u32,
+    /// Names and values of the changed state variables, in document order.
+    pub properties: Vec<(String, String)>,
+}
+
+/// Delivery state of a single subscription.
+struct Route {
+    sid: Option<String>,
+    next_seq: Option<SEQ>,
+    events: Sender<Event>,
+}
+
+struct Shared {
+    client: Client,
+    routes: Mutex<HashMap<String, Route>>,
+}
+
+/// Local HTTP listener receiving the events of subscriptions.
+pub struct EventListener {
+    shared: Arc<Shared>,
+    server: HttpServer,
+    next_id: AtomicUsize,
+}
+
+impl EventListener {
+    /// Construct a listener on an ephemeral port of every IPv4 interface.
+    pub fn new() -> SSDPResult<EventListener> {
+        EventListener::bind("0.0.0.0:0")
+    }
+
+    /// Construct a listener on the address.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> SSDPResult<EventListener> {
+        EventListener::bind_with_client(addr, description::default_client())
+    }
+
+    /// Construct a listener on the address, sending subscription requests with the client.
+    pub fn bind_with_client<A: ToSocketAddrs>(addr: A, client: Client) -> SSDPResult<EventListener> {
+        let shared = Arc::new(Shared {
+            client: client,
+            routes: Mutex::new(HashMap::new()),
+        });
+
+        let handler_shared = shared.clone();
+        let server = try!(HttpServer::bind(addr, move |request| receive_event(&handler_shared, request)));
+
+        Ok(EventListener {
+            shared: shared,
+            server: server,
+            next_id: AtomicUsize::new(0),
+        })
+    }
+
+    /// Address the listener is receiving events on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+
+    /// Subscribe to the events of the service for the default timeout.
+    pub fn subscribe(&self, service: &ServiceEntry) -> SSDPResult<Subscription> {
+        self.subscribe_url(&service.event_sub_url, Timeout::Seconds(DEFAULT_SUBSCRIPTION_SECS))
+    }
+
+    /// Subscribe to the events published at the event subscription url, requesting the timeout.
+    pub fn subscribe_url(&self, event_sub_url: &Url, timeout: Timeout) -> SSDPResult<Subscription> {
+        let path = format!("{}{}", CALLBACK_PATH_PREFIX, self.next_id.fetch_add(1, Ordering::SeqCst));
+        let local_ip = try!(local_ip_towards(event_sub_url));
+        let callback = format!("http://{}{}", SocketAddr::new(local_ip, self.local_addr().port()), path);
+
+        // Register before subscribing, the initial event may beat the response
+        let (send, recv) = mpsc::channel();
+        self.shared.routes.lock().unwrap().insert(path.clone(),
+                                                  Route {
+                                                      sid: None,
+                                                      next_seq: None,
+                                                      events: send,
+                                                  });
+
+        let mut headers = Headers::new();
+        headers.set(Callback(vec![callback]));
+        headers.set(NT(FieldMap::upnp(EVENT_NT_VALUE)));
+        headers.set(timeout);
+
+        let (sid, timeout) = match send_subscribe(&self.shared.client, event_sub_url, headers) {
+            Ok(granted) => granted,
+            Err(e) => {
+                self.shared.routes.lock().unwrap().remove(&path);
+                return Err(e);
+            }
+        };
+        if let Some(route) = self.shared.routes.lock().unwrap().get_mut(&path) {
+            route.sid = Some(sid.clone());
+        }
+
+        let renewer = Renewer {
+            shared: self.shared.clone(),
+            event_sub_url: event_sub_url.clone(),
+            sid: sid,
+            timeout: Arc::new(Mutex::new(timeout)),
+        };
+        let renewal = if let Timeout::Seconds(_) = timeout {
+            let (stop, stopped) = mpsc::channel();
+            let background = renewer.clone();
+            try!(thread::Builder::new()
+                .name(format!("renew {}", renewer.sid))
+                .spawn(move || background.run(stopped)));
+
+            Some(stop)
+        } else {
+            None
+        };
+
+        Ok(Subscription {
+            renewer: renewer,
+            path: path,
+            events: recv,
+            renewal: renewal,
+            subscribed: true,
+        })
+    }
+}
+
+/// Renews a subscription before it expires.
+#[derive(Clone)]
+struct Renewer {
+    shared: Arc<Shared>,
+    event_sub_url: Url,
+    sid: String,
+    timeout: Arc<Mutex<Timeout>>,
+}
+
+impl Renewer {
+    fn renew(&self) -> SSDPResult<Timeout> {
+        let requested = *self.timeout.lock().unwrap();
+
+        let mut headers = Headers::new();
+        headers.set(SID(self.sid.clone()));
+        headers.set(requested);
+
+        let (_, granted) = try!(send_subscribe(&self.shared.client, &self.event_sub_url, headers));
+        *self.timeout.lock().unwrap() = granted;
+
+        Ok(granted)
+    }
+
+    fn run(self, stopped: Receiver<()>) {
+        loop {
+            let wait = match *self.timeout.lock().unwrap() {
+                Timeout::Seconds(secs) => renewal_delay(secs),
+                Timeout::Infinite => return,
+            };
+
+            match stopped.recv_timeout(wait) {
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(e) = self.renew() {
+                        warn!("Failed to renew subscription {}: {}", self.sid, e);
+                        return;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+/// Subscription to the events of a service, cancelled when dropped.
+pub struct Subscription {
+    renewer: Renewer,
+    path: String,
+    events: Receiver<Event>,
+    renewal: Option<Sender<()>>,
+    subscribed: bool,
+}
+
+impl Subscription {
+    /// Identifier assigned to the subscription by the device.
+    pub fn sid(&self) -> &str {
+        &self.renewer.sid
+    }
+
+    /// Timeout most recently granted by the device.
+    pub fn timeout(&self) -> Timeout {
+        *self.renewer.timeout.lock().unwrap()
+    }
+
+    /// Renew the subscription now, returning the timeout granted.
+    ///
+    /// Subscriptions with a finite timeout are also renewed automatically
+    /// halfway through the timeout.
+    pub fn renew(&self) -> SSDPResult<Timeout> {
+        self.renewer.renew()
+    }
+
+    /// Block until the next event is received.
+    pub fn recv(&self) -> Result<Event, RecvError> {
+        self.events.recv()
+    }
+
+    /// Block until the next event is received or the timeout elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Event, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// Cancel the subscription, reporting any failure to do so.
+    pub fn unsubscribe(mut self) -> SSDPResult<()> {
+        self.subscribed = false;
+        self.cancel()
+    }
+
+    fn cancel(&mut self) -> SSDPResult<()> {
+        self.renewal.take();
+        self.renewer.shared.routes.lock().unwrap().remove(&self.path);
+
+        let mut headers = Headers::new();
+        headers.set(SID(self.renewer.sid.clone()));
+
+        let response = try!(self.renewer
+            .shared
+            .client
+            .request(Method::Extension(UNSUBSCRIBE_METHOD.to_owned()),
+                     self.renewer.event_sub_url.clone())
+            .headers(headers)
+            .send());
+
+        match response.status {
+            StatusCode::Ok => Ok(()),
+            status => Err(SSDPErrorKind::ResponseCode(status.to_u16()).into()),
+        }
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.recv().ok()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if self.subscribed {
+            if let Err(e) = self.cancel() {
+                debug!("Failed to unsubscribe {}: {}", self.renewer.sid, e);
+            }
+        }
+    }
+}
+
+/// Send a subscribe or renewal request, returning the SID and timeout granted.
+fn send_subscribe(client: &Client, event_sub_url: &Url, headers: Headers) -> SSDPResult<(String, Timeout)> {
+    debug!("Subscribing to {}", event_sub_url);
+    let response = try!(client.request(Method::Extension(SUBSCRIBE_METHOD.to_owned()),
+                 event_sub_url.clone())
+        .headers(headers)
+        .send());
+
+    if response.status != StatusCode::Ok {
+        return Err(SSDPErrorKind::ResponseCode(response.status.to_u16()).into());
+    }
+
+    let sid = match response.headers.get::<SID>() {
+        Some(&SID(ref sid)) => sid.clone(),
+        None => return Err(SSDPErrorKind::MissingHeader(SID::header_name()).into()),
+    };
+    let timeout = match response.headers.get::<Timeout>() {
+        Some(&timeout) => timeout,
+        None => return Err(SSDPErrorKind::MissingHeader(Timeout::header_name()).into()),
+    };
+
+    Ok((sid, timeout))
+}
+
+/// Handle a request to the event listener, delivering any valid event.
+fn receive_event(shared: &Shared, request: HttpRequest) -> HttpResponse {
+    if request.method != Method::Extension(NOTIFY_METHOD.to_owned()) {
+        return HttpResponse::empty(StatusCode::MethodNotAllowed);
+    }
+
+    let mut routes = shared.routes.lock().unwrap();
+    let route = match routes.get_mut(&request.path) {
+        Some(route) => route,
+        None => return HttpResponse::empty(StatusCode::PreconditionFailed),
+    };
+
+    let headers = &request.headers;
+    match (HeaderRef::get::<NT>(headers), HeaderRef::get::<NTS>(headers)) {
+        (Some(&NT(FieldMap::UPnP(ref nt))), Some(&NTS::PropChange)) if nt == EVENT_NT_VALUE => (),
+        (None, _) | (_, None) => return HttpResponse::empty(StatusCode::BadRequest),
+        _ => return HttpResponse::empty(StatusCode::PreconditionFailed),
+    }
+
+    let sid = match HeaderRef::get::<SID>(headers) {
+        Some(&SID(ref sid)) if route.sid.as_ref().map_or(true, |expected| expected == sid) => sid.clone(),
+        _ => return HttpResponse::empty(StatusCode::PreconditionFailed),
+    };
+    let seq = match HeaderRef::get::<SEQ>(headers) {
+        Some(&seq) => seq,
+        None => return HttpResponse::empty(StatusCode::BadRequest),
+    };
+    let properties = match decode_propertyset(&request.body[..]) {
+        Ok(properties) => properties,
+        Err(_) => return HttpResponse::empty(StatusCode::BadRequest),
+    };
+
+    let missed = match missed_events(route.next_seq.unwrap_or(SEQ(0)), seq) {
+        Some(missed) => missed,
+        // Events sent again or out of order were already passed on, or given up on
+        None => return HttpResponse::empty(StatusCode::Ok),
+    };
+    route.next_seq = Some(seq.next());
+
+    let _ = route.events.send(Event {
+        sid: sid,
+        seq: seq.0,
+        missed: missed,
+        properties: properties,
+    });
+
+    HttpResponse::empty(StatusCode::Ok)
+}
+
+/// Number of events skipped between the expected and received sequence
+/// numbers, or None if the event was received before, or after a later one.
+fn missed_events(expected: SEQ, received: SEQ) -> Option<u32> {
+    if received.0 >= expected.0 {
+        Some(received.0 - expected.0)
+    } else if expected.0 > u32::max_value() - SEQ_WRAP_WINDOW && received.0 < SEQ_WRAP_WINDOW {
+        // Received a sequence number after wrapping around to one
+        Some((u32::max_value() - expected.0) + received.0)
+    } else {
+        None
+    }
+}
+
+/// Time to wait before renewing a subscription with the timeout.
+fn renewal_delay(timeout_secs: u32) -> Duration {
+    Duration::new(::std::cmp::max(timeout_secs as u64 / 2, MIN_RENEWAL_SECS), 0)
+}
+
+/// Address of the local interface that traffic to the url would be sent from.
+fn local_ip_towards(url: &Url) -> SSDPResult<IpAddr> {
+    let host_port = try!(url.with_default_port(|_| Err(())));
+    let remote = try!(::net::addr_from_trait(host_port));
+
+    Ok(try!(::net::local_ip_towards(remote)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::Client;
+    use hyper::Url;
+    use hyper::header::Headers;
+    use hyper::method::Method;
+    use hyper::status::StatusCode;
+
+    use FieldMap;
+    use description::mocks::{MockResponse, MockServer};
+    use header::{Callback, NT, NTS, SEQ, SID, Timeout};
+    use super::{encode_propertyset, missed_events, EventListener};
+
+    const SUBSCRIPTION_ID: &'static str = "uuid:4b4e9d44-3f3c-11e7-a919-92ebcb67fe33";
+
+    fn mock_publisher() -> MockServer {
+        MockServer::new(|request| {
+            let mut response = MockResponse::ok("");
+            if request.method == Method::Extension("SUBSCRIBE".to_owned()) {
+                response.headers.set(SID(SUBSCRIPTION_ID.to_owned()));
+                response.headers.set(Timeout::Seconds(300));
+            }
+
+            response
+        })
+    }
+
+    fn notify(callback: &str, sid: &str, seq: u32) -> StatusCode {
+        let mut headers = Headers::new();
+        headers.set(NT(FieldMap::upnp("event")));
+        headers.set(NTS::PropChange);
+        headers.set(SID(sid.to_owned()));
+        headers.set(SEQ(seq));
+
+        let body = encode_propertyset(&[("Volume".to_owned(), seq.to_string())]);
+        Client::new()
+            .request(Method::Extension("NOTIFY".to_owned()), callback)
+            .headers(headers)
+            .body(&body[..])
+            .send()
+            .unwrap()
+            .status
+    }
+
+    fn callback_url(server: &MockServer) -> String {
+        let requests = server.requests.lock().unwrap();
+        let callback = requests[0].headers.get::<Callback>().unwrap();
+
+        callback.0[0].clone()
+    }
+
+    #[test]
+    fn positive_subscribe_and_receive() {
+        let publisher = mock_publisher();
+        let listener = EventListener::bind("127.0.0.1:0").unwrap();
+
+        let subscription = listener.subscribe_url(&Url::parse(&publisher.url("/rc/event")).unwrap(),
+                           Timeout::Seconds(300))
+            .unwrap();
+        assert_eq!(subscription.sid(), SUBSCRIPTION_ID);
+        assert_eq!(subscription.timeout(), Timeout::Seconds(300));
+
+        let callback = callback_url(&publisher);
+        assert_eq!(notify(&callback, SUBSCRIPTION_ID, 0), StatusCode::Ok);
+        assert_eq!(notify(&callback, SUBSCRIPTION_ID, 3), StatusCode::Ok);
+
+        let initial = subscription.recv_timeout(Duration::new(5, 0)).unwrap();
+        assert_eq!(initial.properties, vec![("Volume".to_owned(), "0".to_owned())]);
+        assert_eq!(initial.missed, 0);
+
+        let gap = subscription.recv_timeout(Duration::new(5, 0)).unwrap();
+        assert_eq!(gap.seq, 3);
+        assert_eq!(gap.missed, 2);
+    }
+
+    #[test]
+    fn positive_unsubscribe_on_drop() {
+        let publisher = mock_publisher();
+        let listener = EventListener::bind("127.0.0.1:0").unwrap();
+
+        listener.subscribe_url(&Url::parse(&publisher.url("/rc/event")).unwrap(),
+                           Timeout::Seconds(300))
+            .unwrap();
+
+        let requests = publisher.requests.lock().unwrap();
+        assert_eq!(requests[1].method, Method::Extension("UNSUBSCRIBE".to_owned()));
+        assert_eq!(requests[1].headers.get::<SID>(), Some(&SID(SUBSCRIPTION_ID.to_owned())));
+    }
+
+    #[test]
+    fn positive_missed_events_wrap() {
+        assert_eq!(missed_events(SEQ(5), SEQ(5)), Some(0));
+        assert_eq!(missed_events(SEQ(u32::max_value()), SEQ(2)), Some(2));
+    }
+
+    #[test]
+    fn positive_stale_events_dropped() {
+        assert_eq!(missed_events(SEQ(5), SEQ(4)), None);
+        assert_eq!(missed_events(SEQ(1 << 20), SEQ(1)), None);
+    }
+
+    #[test]
+    fn negative_unknown_sid_rejected() {
+        let publisher = mock_publisher();
+        let listener = EventListener::bind("127.0.0.1:0").unwrap();
+
+        let subscription = listener.subscribe_url(&Url::parse(&publisher.url("/rc/event")).unwrap(),
+                           Timeout::Seconds(300))
+            .unwrap();
+
+        assert_eq!(notify(&callback_url(&publisher), "uuid:someone-else", 0),
+                   StatusCode::PreconditionFailed);
+        assert!(subscription.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! Encoding and decoding of the property sets carried by event messages.
+
+use {SSDPResult, SSDPErrorKind};
+use dom::{self, Element};
+
+const PROPERTYSET_START: &'static str = "<?xml version=\"1.0\"?>\
+    <e:propertyset xmlns:e=\"urn:schemas-upnp-org:event-1-0\">";
+const PROPERTYSET_END: &'static str = "</e:propertyset>";
+
+/// Encode the state variable names and values as a property set.
+pub fn encode_propertyset(properties: &[(String, String)]) -> String {
+    let mut document = String::from(PROPERTYSET_START);
+
+    for &(ref name, ref value) in properties {
+        document.push_str("<e:property>");
+        dom::write_text_element(&mut document, name, value);
+        document.push_str("</e:property>");
+    }
+    document.push_str(PROPERTYSET_END);
+
+    document
+}
+
+/// Decode the state variable names and values, in order, from a property set.
+pub fn decode_propertyset(document: &[u8]) -> SSDPResult<Vec<(String, String)>> {
+    let root = try!(Element::parse(document));
+    if root.name != "propertyset" {
+        return Err(SSDPErrorKind::InvalidDocument(format!("unexpected root element <{}>", root.name)).into());
+    }
+
+    Ok(root.children_named("property")
+        .into_iter()
+        .flat_map(|property| property.children.iter())
+        .map(|variable: &Element| (variable.name.clone(), variable.text.clone()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_propertyset, encode_propertyset};
+
+    #[test]
+    fn positive_decode_propertyset() {
+        let document = br#"<?xml version="1.0"?>
+<e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0">
+  <e:property><Volume>42</Volume></e:property>
+  <e:property><LastChange>&lt;Event/&gt;</LastChange></e:property>
+</e:propertyset>"#;
+
+        assert_eq!(decode_propertyset(&document[..]).unwrap(),
+                   vec![("Volume".to_owned(), "42".to_owned()),
+                        ("LastChange".to_owned(), "<Event/>".to_owned())]);
+    }
+
+    #[test]
+    fn positive_round_trip() {
+        let properties = vec![("Mute".to_owned(), "0".to_owned()), ("Name".to_owned(), "a & b".to_owned())];
+        let document = encode_propertyset(&properties);
+
+        assert_eq!(decode_propertyset(document.as_bytes()).unwrap(), properties);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_wrong_root() {
+        decode_propertyset(b"<?xml version=\"1.0\"?><property><Volume>1</Volume></property>").unwrap();
+    }
+}
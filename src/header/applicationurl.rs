@@ -0,0 +1,63 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const APPLICATION_URL_HEADER_NAME: &'static str = "Application-URL";
+
+/// Represents a header used by DIAL servers to specify the base url of their
+/// applications.
+///
+/// This header is sent in the response to the request for the description
+/// document of the device, not in a discovery message.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ApplicationURL(pub String);
+
+impl Header for ApplicationURL {
+    fn header_name() -> &'static str {
+        APPLICATION_URL_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 || raw[0].is_empty() {
+            return Err(Error::Header);
+        }
+
+        match String::from_utf8(raw[0].clone()) {
+            Ok(n) => Ok(ApplicationURL(n.trim().to_owned())),
+            Err(_) => Err(Error::Header),
+        }
+    }
+}
+
+impl HeaderFormat for ApplicationURL {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        try!(fmt.write_str(&self.0));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::ApplicationURL;
+
+    #[test]
+    fn positive_application_url() {
+        let application_url_header_value = &[b"http://192.168.1.10:8008/apps/"[..].to_vec()];
+
+        assert_eq!(ApplicationURL::parse_header(application_url_header_value).unwrap(),
+                   ApplicationURL("http://192.168.1.10:8008/apps/".to_owned()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_empty() {
+        let application_url_header_value = &[b""[..].to_vec()];
+
+        ApplicationURL::parse_header(application_url_header_value).unwrap();
+    }
+}
@@ -7,6 +7,7 @@ const BOOTID_HEADER_NAME: &'static str = "BOOTID.UPNP.ORG";
 
 /// Represents a header used to denote the boot instance of a root device.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BootID(pub u32);
 
 impl Header for BootID {
@@ -0,0 +1,77 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const CALLBACK_HEADER_NAME: &'static str = "CALLBACK";
+
+/// Represents a header used to list the urls that events for a subscription
+/// should be delivered to, in order of preference.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Callback(pub Vec<String>);
+
+impl Header for Callback {
+    fn header_name() -> &'static str {
+        CALLBACK_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 {
+            return Err(Error::Header);
+        }
+
+        let cow_str = String::from_utf8_lossy(&raw[0][..]);
+        let mut urls = Vec::new();
+
+        for part in cow_str.split('>') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+
+            if !part.starts_with('<') || part.len() == 1 {
+                return Err(Error::Header);
+            }
+            urls.push(part[1..].to_owned());
+        }
+
+        if urls.is_empty() {
+            Err(Error::Header)
+        } else {
+            Ok(Callback(urls))
+        }
+    }
+}
+
+impl HeaderFormat for Callback {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        for url in &self.0 {
+            try!(fmt.write_fmt(format_args!("<{}>", url)));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::Callback;
+
+    #[test]
+    fn positive_multiple_urls() {
+        let callback_header_value = &[b"<http://192.168.1.2:4000/a><http://10.0.0.2:4000/a>"[..].to_vec()];
+
+        assert_eq!(Callback::parse_header(callback_header_value).unwrap().0.len(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_missing_brackets() {
+        let callback_header_value = &[b"http://192.168.1.2:4000/a"[..].to_vec()];
+
+        Callback::parse_header(callback_header_value).unwrap();
+    }
+}
@@ -7,6 +7,7 @@ const CONFIGID_HEADER_NAME: &'static str = "CONFIGID.UPNP.ORG";
 
 /// Represents a header used to denote the configuration of a device's DDD.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ConfigID(pub u32);
 
 impl Header for ConfigID {
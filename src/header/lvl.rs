@@ -0,0 +1,102 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const LVL_HEADER_NAME: &'static str = "LVL";
+
+const EMERGENCY_VALUE: &'static str = "upnp:emergency";
+const FAULT_VALUE: &'static str = "upnp:fault";
+const WARNING_VALUE: &'static str = "upnp:warning";
+const INFO_VALUE: &'static str = "upnp:info";
+const DEBUG_VALUE: &'static str = "upnp:debug";
+const GENERAL_VALUE: &'static str = "upnp:general";
+
+/// Represents a header used to specify the importance of a multicast event.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum LVL {
+    Emergency,
+    Fault,
+    Warning,
+    Info,
+    Debug,
+    General,
+    /// A level defined by a vendor, in the form `domain:level`.
+    Vendor(String),
+}
+
+impl Header for LVL {
+    fn header_name() -> &'static str {
+        LVL_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 {
+            return Err(Error::Header);
+        }
+
+        let value = match String::from_utf8(raw[0].clone()) {
+            Ok(n) => n,
+            Err(_) => return Err(Error::Header),
+        };
+
+        match &value[..] {
+            EMERGENCY_VALUE => Ok(LVL::Emergency),
+            FAULT_VALUE => Ok(LVL::Fault),
+            WARNING_VALUE => Ok(LVL::Warning),
+            INFO_VALUE => Ok(LVL::Info),
+            DEBUG_VALUE => Ok(LVL::Debug),
+            GENERAL_VALUE => Ok(LVL::General),
+            _ if value.contains(':') && !value.starts_with(':') && !value.ends_with(':') => {
+                Ok(LVL::Vendor(value))
+            }
+            _ => Err(Error::Header),
+        }
+    }
+}
+
+impl HeaderFormat for LVL {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        match *self {
+            LVL::Emergency => try!(fmt.write_str(EMERGENCY_VALUE)),
+            LVL::Fault => try!(fmt.write_str(FAULT_VALUE)),
+            LVL::Warning => try!(fmt.write_str(WARNING_VALUE)),
+            LVL::Info => try!(fmt.write_str(INFO_VALUE)),
+            LVL::Debug => try!(fmt.write_str(DEBUG_VALUE)),
+            LVL::General => try!(fmt.write_str(GENERAL_VALUE)),
+            LVL::Vendor(ref value) => try!(fmt.write_str(value)),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::LVL;
+
+    #[test]
+    fn positive_standard_level() {
+        let lvl_header_value = &[b"upnp:warning"[..].to_vec()];
+
+        assert_eq!(LVL::parse_header(lvl_header_value).unwrap(), LVL::Warning);
+    }
+
+    #[test]
+    fn positive_vendor_level() {
+        let lvl_header_value = &[b"example-com:verbose"[..].to_vec()];
+
+        assert_eq!(LVL::parse_header(lvl_header_value).unwrap(),
+                   LVL::Vendor("example-com:verbose".to_owned()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_no_domain() {
+        let lvl_header_value = &[b"warning"[..].to_vec()];
+
+        LVL::parse_header(lvl_header_value).unwrap();
+    }
+}
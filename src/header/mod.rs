@@ -9,27 +9,43 @@ use std::fmt::Debug;
 
 use hyper::header::{Headers, Header, HeaderFormat};
 
+mod applicationurl;
 mod bootid;
+mod callback;
 mod configid;
+mod lvl;
 mod man;
 mod mx;
 mod nt;
 mod nts;
 mod searchport;
 mod securelocation;
+mod seq;
+mod sid;
 mod st;
+mod svcid;
+mod timeout;
 mod usn;
+mod wakeup;
 
+pub use self::applicationurl::ApplicationURL;
 pub use self::bootid::BootID;
+pub use self::callback::Callback;
 pub use self::configid::ConfigID;
+pub use self::lvl::LVL;
 pub use self::man::Man;
 pub use self::mx::MX;
 pub use self::nt::NT;
 pub use self::nts::NTS;
 pub use self::searchport::SearchPort;
 pub use self::securelocation::SecureLocation;
+pub use self::seq::SEQ;
+pub use self::sid::SID;
 pub use self::st::ST;
+pub use self::svcid::SVCID;
+pub use self::timeout::Timeout;
 pub use self::usn::USN;
+pub use self::wakeup::WakeUp;
 
 // Re-exports
 pub use hyper::header::{Location, Server, CacheControl, CacheDirective};
@@ -22,6 +22,7 @@ pub const MX_HEADER_MAX: u8 = 120;
 /// (but lower than the maximum threshold) because of resources it may not want
 /// to tie up.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct MX(pub u8);
 
 impl MX {
@@ -8,6 +8,7 @@ const NTS_HEADER_NAME: &'static str = "NTS";
 const ALIVE_HEADER: &'static str = "ssdp:alive";
 const UPDATE_HEADER: &'static str = "ssdp:update";
 const BYEBYE_HEADER: &'static str = "ssdp:byebye";
+const PROPCHANGE_HEADER: &'static str = "upnp:propchange";
 
 /// Represents a header which specifies a notification sub type.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
@@ -21,6 +22,8 @@ pub enum NTS {
     Update,
     /// An entity is removing itself from the network.
     ByeBye,
+    /// An event message is reporting changed state variables of a service.
+    PropChange,
 }
 
 impl Header for NTS {
@@ -39,6 +42,8 @@ impl Header for NTS {
             Ok(NTS::Update)
         } else if &raw[0][..] == BYEBYE_HEADER.as_bytes() {
             Ok(NTS::ByeBye)
+        } else if &raw[0][..] == PROPCHANGE_HEADER.as_bytes() {
+            Ok(NTS::PropChange)
         } else {
             Err(Error::Header)
         }
@@ -51,6 +56,7 @@ impl HeaderFormat for NTS {
             NTS::Alive => try!(fmt.write_str(ALIVE_HEADER)),
             NTS::Update => try!(fmt.write_str(UPDATE_HEADER)),
             NTS::ByeBye => try!(fmt.write_str(BYEBYE_HEADER)),
+            NTS::PropChange => try!(fmt.write_str(PROPCHANGE_HEADER)),
         };
 
         Ok(())
@@ -93,6 +99,16 @@ mod tests {
         };
     }
 
+    #[test]
+    fn positive_propchange() {
+        let propchange_header = &[b"upnp:propchange"[..].to_vec()];
+
+        match NTS::parse_header(propchange_header) {
+            Ok(NTS::PropChange) => (),
+            _ => panic!("Didn't Match With NTS::PropChange"),
+        };
+    }
+
     #[test]
     #[should_panic]
     fn negative_alive_extra() {
@@ -12,6 +12,7 @@ pub const SEARCHPORT_MIN_VALUE: u16 = 49152;
 /// If a `SearchPort` header is not included in a message then the device must
 /// respond to unicast search requests on the standard port of 1900.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SearchPort(pub u16);
 
 impl Header for SearchPort {
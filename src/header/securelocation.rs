@@ -9,6 +9,7 @@ const SECURELOCATION_HEADER_NAME: &'static str = "SECURELOCATION.UPNP.ORG";
 ///
 /// Can be used instead of the `Location` header field.
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SecureLocation(pub String);
 
 impl Header for SecureLocation {
@@ -0,0 +1,79 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const SEQ_HEADER_NAME: &'static str = "SEQ";
+
+/// Represents a header used to denote the sequence number of an event message.
+///
+/// The initial event of a subscription carries a key of zero, which wraps
+/// around to one after reaching the maximum value.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SEQ(pub u32);
+
+impl SEQ {
+    /// Sequence number expected for the event following this one.
+    pub fn next(&self) -> SEQ {
+        match self.0.checked_add(1) {
+            Some(n) => SEQ(n),
+            None => SEQ(1),
+        }
+    }
+}
+
+impl Header for SEQ {
+    fn header_name() -> &'static str {
+        SEQ_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 {
+            return Err(Error::Header);
+        }
+
+        let cow_str = String::from_utf8_lossy(&raw[0][..]);
+
+        match u32::from_str_radix(cow_str.trim(), 10) {
+            Ok(n) => Ok(SEQ(n)),
+            Err(_) => Err(Error::Header),
+        }
+    }
+}
+
+impl HeaderFormat for SEQ {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        try!(fmt.write_fmt(format_args!("{}", self.0)));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::SEQ;
+
+    #[test]
+    fn positive_seq() {
+        let seq_header_value = &[b"0"[..].to_vec()];
+
+        SEQ::parse_header(seq_header_value).unwrap();
+    }
+
+    #[test]
+    fn positive_next_wraps() {
+        assert_eq!(SEQ(7).next(), SEQ(8));
+        assert_eq!(SEQ(u32::max_value()).next(), SEQ(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_negative() {
+        let seq_header_value = &[b"-1"[..].to_vec()];
+
+        SEQ::parse_header(seq_header_value).unwrap();
+    }
+}
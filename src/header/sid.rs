@@ -0,0 +1,60 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const SID_HEADER_NAME: &'static str = "SID";
+
+/// Represents a header used to identify an event subscription.
+///
+/// Subscription identifiers take the form `uuid:subscription-UUID`.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SID(pub String);
+
+impl Header for SID {
+    fn header_name() -> &'static str {
+        SID_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 || raw[0].is_empty() {
+            return Err(Error::Header);
+        }
+
+        match String::from_utf8(raw[0].clone()) {
+            Ok(n) => Ok(SID(n.trim().to_owned())),
+            Err(_) => Err(Error::Header),
+        }
+    }
+}
+
+impl HeaderFormat for SID {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        try!(fmt.write_str(&self.0));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::SID;
+
+    #[test]
+    fn positive_sid() {
+        let sid_header_value = &[b"uuid:4b4e9d44-3f3c-11e7-a919-92ebcb67fe33"[..].to_vec()];
+
+        SID::parse_header(sid_header_value).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_empty() {
+        let sid_header_value = &[b""[..].to_vec()];
+
+        SID::parse_header(sid_header_value).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const SVCID_HEADER_NAME: &'static str = "SVCID";
+
+/// Represents a header used to specify the service a multicast event originated from.
+///
+/// Holds the `serviceId` of the service as found in the device description.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SVCID(pub String);
+
+impl Header for SVCID {
+    fn header_name() -> &'static str {
+        SVCID_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 || raw[0].is_empty() {
+            return Err(Error::Header);
+        }
+
+        match String::from_utf8(raw[0].clone()) {
+            Ok(n) => Ok(SVCID(n)),
+            Err(_) => Err(Error::Header),
+        }
+    }
+}
+
+impl HeaderFormat for SVCID {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        try!(fmt.write_str(&self.0));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::SVCID;
+
+    #[test]
+    fn positive_svcid() {
+        let svcid_header_value = &[b"urn:upnp-org:serviceId:RenderingControl"[..].to_vec()];
+
+        SVCID::parse_header(svcid_header_value).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_empty() {
+        let svcid_header_value = &[b""[..].to_vec()];
+
+        SVCID::parse_header(svcid_header_value).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const TIMEOUT_HEADER_NAME: &'static str = "TIMEOUT";
+
+const SECOND_PREFIX: &'static str = "Second-";
+const INFINITE_VALUE: &'static str = "infinite";
+
+/// Represents a header used to request or report the duration of an event
+/// subscription.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Timeout {
+    /// Subscription lasts for the number of seconds.
+    Seconds(u32),
+    /// Subscription lasts until it is cancelled (deprecated in UDA 1.1).
+    Infinite,
+}
+
+impl Header for Timeout {
+    fn header_name() -> &'static str {
+        TIMEOUT_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 {
+            return Err(Error::Header);
+        }
+
+        let cow_str = String::from_utf8_lossy(&raw[0][..]);
+        let value = cow_str.trim();
+
+        if !value.starts_with(SECOND_PREFIX) {
+            return Err(Error::Header);
+        }
+
+        let seconds = &value[SECOND_PREFIX.len()..];
+        if seconds.eq_ignore_ascii_case(INFINITE_VALUE) {
+            return Ok(Timeout::Infinite);
+        }
+
+        match u32::from_str_radix(seconds, 10) {
+            Ok(n) => Ok(Timeout::Seconds(n)),
+            Err(_) => Err(Error::Header),
+        }
+    }
+}
+
+impl HeaderFormat for Timeout {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        match *self {
+            Timeout::Seconds(n) => try!(fmt.write_fmt(format_args!("{}{}", SECOND_PREFIX, n))),
+            Timeout::Infinite => try!(fmt.write_fmt(format_args!("{}{}", SECOND_PREFIX, INFINITE_VALUE))),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::Timeout;
+
+    #[test]
+    fn positive_seconds() {
+        let timeout_header_value = &[b"Second-1800"[..].to_vec()];
+
+        assert_eq!(Timeout::parse_header(timeout_header_value).unwrap(), Timeout::Seconds(1800));
+    }
+
+    #[test]
+    fn positive_infinite() {
+        let timeout_header_value = &[b"Second-infinite"[..].to_vec()];
+
+        assert_eq!(Timeout::parse_header(timeout_header_value).unwrap(), Timeout::Infinite);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_missing_prefix() {
+        let timeout_header_value = &[b"1800"[..].to_vec()];
+
+        Timeout::parse_header(timeout_header_value).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const WAKEUP_HEADER_NAME: &'static str = "WAKEUP";
+
+const MAC_KEY: &'static str = "MAC";
+const TIMEOUT_KEY: &'static str = "Timeout";
+
+/// Represents a header used by DIAL servers to advertise that they can be
+/// woken up from a sleep state.
+///
+/// The header holds the hardware address to send a Wake-on-LAN packet to and
+/// the number of seconds the device takes to become reachable after waking.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct WakeUp {
+    pub mac: [u8; 6],
+    pub timeout: u32,
+}
+
+impl Header for WakeUp {
+    fn header_name() -> &'static str {
+        WAKEUP_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 {
+            return Err(Error::Header);
+        }
+
+        let cow_str = String::from_utf8_lossy(&raw[0][..]);
+
+        let (mut mac, mut timeout) = (None, None);
+        for field in cow_str.split(';') {
+            let mut pair = field.splitn(2, '=');
+
+            match (pair.next().map(str::trim), pair.next().map(str::trim)) {
+                (Some(key), Some(value)) if key.eq_ignore_ascii_case(MAC_KEY) => mac = parse_mac(value),
+                (Some(key), Some(value)) if key.eq_ignore_ascii_case(TIMEOUT_KEY) => {
+                    timeout = u32::from_str_radix(value, 10).ok()
+                }
+                _ => (),
+            }
+        }
+
+        match (mac, timeout) {
+            (Some(mac), Some(timeout)) => {
+                Ok(WakeUp {
+                    mac: mac,
+                    timeout: timeout,
+                })
+            }
+            _ => Err(Error::Header),
+        }
+    }
+}
+
+impl HeaderFormat for WakeUp {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        let m = &self.mac;
+
+        try!(fmt.write_fmt(format_args!("{}={:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x};{}={}",
+                                        MAC_KEY,
+                                        m[0],
+                                        m[1],
+                                        m[2],
+                                        m[3],
+                                        m[4],
+                                        m[5],
+                                        TIMEOUT_KEY,
+                                        self.timeout)));
+
+        Ok(())
+    }
+}
+
+/// Parse a hardware address written as six colon separated hex octets.
+fn parse_mac(value: &str) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut octets = value.split(|c| c == ':' || c == '-');
+
+    for byte in mac.iter_mut() {
+        match octets.next().map(|octet| u8::from_str_radix(octet, 16)) {
+            Some(Ok(n)) => *byte = n,
+            _ => return None,
+        }
+    }
+
+    match octets.next() {
+        Some(_) => None,
+        None => Some(mac),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::{Header, Headers};
+
+    use super::WakeUp;
+
+    #[test]
+    fn positive_wakeup() {
+        let wakeup_header_value = &[b"MAC=10:dd:b1:c9:00:e4;Timeout=10"[..].to_vec()];
+        let wakeup = WakeUp::parse_header(wakeup_header_value).unwrap();
+
+        assert_eq!(wakeup.mac, [0x10, 0xdd, 0xb1, 0xc9, 0x00, 0xe4]);
+        assert_eq!(wakeup.timeout, 10);
+
+        let mut headers = Headers::new();
+        headers.set(wakeup);
+        assert_eq!(headers.get_raw("WAKEUP").unwrap()[0], b"MAC=10:dd:b1:c9:00:e4;Timeout=10".to_vec());
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_short_mac() {
+        let wakeup_header_value = &[b"MAC=10:dd:b1:c9:00;Timeout=10"[..].to_vec()];
+
+        WakeUp::parse_header(wakeup_header_value).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_missing_timeout() {
+        let wakeup_header_value = &[b"MAC=10:dd:b1:c9:00:e4"[..].to_vec()];
+
+        WakeUp::parse_header(wakeup_header_value).unwrap();
+    }
+}
@@ -0,0 +1,583 @@
+//! Port mapping through Internet Gateway Devices.
+//!
+//! Home routers expose a `WANIPConnection` or `WANPPPConnection` service on
+//! an embedded device of their `InternetGatewayDevice`. This module searches
+//! for those services, reads their control urls from the description of the
+//! router and invokes the actions used to manage port mappings.
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use {FieldMap, SSDPError, SSDPResult, SSDPErrorKind};
+use control::{ServiceClient, Value};
+use description::Description;
+use discovery::{self, DiscoverOptions};
+use header::ST;
+
+/// Service type of version 1 of the IP connection service.
+pub const WAN_IP_CONNECTION_1: &'static str = "urn:schemas-upnp-org:service:WANIPConnection:1";
+/// Service type of version 2 of the IP connection service.
+pub const WAN_IP_CONNECTION_2: &'static str = "urn:schemas-upnp-org:service:WANIPConnection:2";
+/// Service type of version 1 of the PPP connection service.
+pub const WAN_PPP_CONNECTION_1: &'static str = "urn:schemas-upnp-org:service:WANPPPConnection:1";
+
+/// Service types searched for, most capable first.
+const GATEWAY_SERVICE_TYPES: [&'static str; 3] = [WAN_IP_CONNECTION_2,
+                                                  WAN_IP_CONNECTION_1,
+                                                  WAN_PPP_CONNECTION_1];
+
+/// Error code returned when an index is past the end of the port mapping table.
+const SPECIFIED_ARRAY_INDEX_INVALID: u16 = 713;
+/// Error code returned by gateways that only support permanent leases.
+const ONLY_PERMANENT_LEASES_SUPPORTED: u16 = 725;
+
+/// Minimum time to wait between renewals of a lease.
+const MIN_RENEWAL_SECS: u64 = 1;
+
+/// Enumerates the transport protocols that ports can be mapped for.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Protocol {
+    TCP,
+    UDP,
+}
+
+impl Protocol {
+    fn parse(value: &str) -> SSDPResult<Protocol> {
+        match value {
+            "TCP" => Ok(Protocol::TCP),
+            "UDP" => Ok(Protocol::UDP),
+            other => Err(SSDPErrorKind::InvalidDocument(format!("unknown protocol '{}'", other)).into()),
+        }
+    }
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Protocol::TCP => f.write_str("TCP"),
+            Protocol::UDP => f.write_str("UDP"),
+        }
+    }
+}
+
+/// An entry of the port mapping table of a gateway.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PortMapping {
+    /// Remote host the mapping is restricted to, empty for any host.
+    pub remote_host: String,
+    pub external_port: u16,
+    pub protocol: Protocol,
+    pub internal_port: u16,
+    pub internal_client: String,
+    pub enabled: bool,
+    pub description: String,
+    /// Remaining duration of the mapping in seconds, zero if it is permanent.
+    pub lease_duration: u32,
+}
+
+/// Connection service of an Internet Gateway Device.
+#[derive(Clone)]
+pub struct Gateway {
+    service: Arc<ServiceClient>,
+}
+
+impl Gateway {
+    /// Construct a gateway invoking actions through the service client.
+    pub fn new(service: ServiceClient) -> Gateway {
+        Gateway { service: Arc::new(service) }
+    }
+
+    /// Find the connection services within the description of a gateway.
+    pub fn from_description(description: &Description) -> Vec<Gateway> {
+        description.device
+            .all_devices()
+            .into_iter()
+            .flat_map(|device| device.services.iter())
+            .filter(|service| GATEWAY_SERVICE_TYPES.contains(&&service.service_type[..]))
+            .map(|service| Gateway::new(ServiceClient::new(service)))
+            .collect()
+    }
+
+    pub fn service_type(&self) -> &str {
+        self.service.service_type()
+    }
+
+    /// Get the address of the gateway on the external network.
+    pub fn external_ip(&self) -> SSDPResult<IpAddr> {
+        let results = try!(self.service.invoke("GetExternalIPAddress", &[]));
+        let address = try!(output(&results, "NewExternalIPAddress"));
+
+        address.parse().map_err(|_| {
+            SSDPErrorKind::InvalidDocument(format!("invalid external address '{}'", address)).into()
+        })
+    }
+
+    /// Map the external port to the internal address for the lease duration in
+    /// seconds, zero requesting a permanent mapping.
+    pub fn add_port_mapping(&self,
+                            protocol: Protocol,
+                            external_port: u16,
+                            internal: SocketAddrV4,
+                            lease_duration: u32,
+                            description: &str)
+                            -> SSDPResult<()> {
+        let args = mapping_args(protocol, external_port, internal, lease_duration, description);
+
+        self.service.invoke("AddPortMapping", &args).map(|_| ())
+    }
+
+    /// Map any free external port to the internal address, preferring the
+    /// given port, and return the port that was mapped.
+    ///
+    /// Gateways that do not implement `WANIPConnection:2` can only map the
+    /// preferred port.
+    pub fn add_any_port_mapping(&self,
+                                protocol: Protocol,
+                                external_port: u16,
+                                internal: SocketAddrV4,
+                                lease_duration: u32,
+                                description: &str)
+                                -> SSDPResult<u16> {
+        if self.service_type() != WAN_IP_CONNECTION_2 {
+            return self.add_port_mapping(protocol, external_port, internal, lease_duration, description)
+                .map(|_| external_port);
+        }
+
+        let args = mapping_args(protocol, external_port, internal, lease_duration, description);
+        let results = try!(self.service.invoke("AddAnyPortMapping", &args));
+        let port = try!(output(&results, "NewReservedPort"));
+
+        port.parse().map_err(|_| SSDPErrorKind::InvalidDocument(format!("invalid port '{}'", port)).into())
+    }
+
+    /// Remove the mapping of the external port.
+    pub fn delete_port_mapping(&self, protocol: Protocol, external_port: u16) -> SSDPResult<()> {
+        let args = [("NewRemoteHost", Value::from("")),
+                    ("NewExternalPort", Value::from(external_port)),
+                    ("NewProtocol", Value::from(protocol.to_string()))];
+
+        self.service.invoke("DeletePortMapping", &args).map(|_| ())
+    }
+
+    /// Get the entry at the index of the port mapping table.
+    pub fn port_mapping_entry(&self, index: u16) -> SSDPResult<PortMapping> {
+        let results = try!(self.service.invoke("GetGenericPortMappingEntry",
+                                               &[("NewPortMappingIndex", Value::from(index))]));
+
+        Ok(PortMapping {
+            remote_host: try!(output(&results, "NewRemoteHost")).to_owned(),
+            external_port: try!(parse_output(&results, "NewExternalPort")),
+            protocol: try!(Protocol::parse(try!(output(&results, "NewProtocol")))),
+            internal_port: try!(parse_output(&results, "NewInternalPort")),
+            internal_client: try!(output(&results, "NewInternalClient")).to_owned(),
+            enabled: try!(output(&results, "NewEnabled")) == "1",
+            description: try!(output(&results, "NewPortMappingDescription")).to_owned(),
+            lease_duration: try!(parse_output(&results, "NewLeaseDuration")),
+        })
+    }
+
+    /// Get every entry of the port mapping table.
+    ///
+    /// The table ends at the first index the gateway answers with a fault or
+    /// an HTTP error for, gateways not all answering with the
+    /// `SpecifiedArrayIndexInvalid` fault the standard asks for.
+    pub fn port_mappings(&self) -> SSDPResult<Vec<PortMapping>> {
+        let mut mappings = Vec::new();
+
+        for index in 0..u16::max_value() {
+            match self.port_mapping_entry(index) {
+                Ok(mapping) => mappings.push(mapping),
+                Err(ref e) if is_error_response(e) => {
+                    if !is_upnp_error(e, SPECIFIED_ARRAY_INDEX_INVALID) {
+                        debug!("Port mapping table ended at index {} with: {}", index, e);
+                    }
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(mappings)
+    }
+
+    /// Map the external port to the internal address, renewing the mapping
+    /// halfway through every lease until the returned `Lease` is dropped.
+    ///
+    /// Falls back to a permanent mapping on gateways that do not support leases.
+    pub fn lease(&self,
+                 protocol: Protocol,
+                 external_port: u16,
+                 internal: SocketAddrV4,
+                 lease_duration: u32,
+                 description: &str)
+                 -> SSDPResult<Lease> {
+        let lease_duration = match self.add_port_mapping(protocol,
+                                                         external_port,
+                                                         internal,
+                                                         lease_duration,
+                                                         description) {
+            Ok(()) => lease_duration,
+            Err(ref e) if lease_duration != 0 && is_upnp_error(e, ONLY_PERMANENT_LEASES_SUPPORTED) => {
+                try!(self.add_port_mapping(protocol, external_port, internal, 0, description));
+                0
+            }
+            Err(e) => return Err(e),
+        };
+
+        let renewal = if lease_duration != 0 {
+            let (stop, stopped) = mpsc::channel::<()>();
+            let gateway = self.clone();
+            let description = description.to_owned();
+            let wait = Duration::new(::std::cmp::max(lease_duration as u64 / 2, MIN_RENEWAL_SECS), 0);
+
+            try!(thread::Builder::new()
+                .name(format!("lease {} {}", protocol, external_port))
+                .spawn(move || while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+                    let result = gateway.add_port_mapping(protocol,
+                                                          external_port,
+                                                          internal,
+                                                          lease_duration,
+                                                          &description);
+                    if let Err(e) = result {
+                        warn!("Failed to renew mapping of {} port {}: {}", protocol, external_port, e);
+                    }
+                }));
+
+            Some(stop)
+        } else {
+            None
+        };
+
+        Ok(Lease {
+            gateway: self.clone(),
+            protocol: protocol,
+            external_port: external_port,
+            lease_duration: lease_duration,
+            renewal: renewal,
+        })
+    }
+}
+
+/// Port mapping that is renewed until dropped, at which point it is deleted.
+pub struct Lease {
+    gateway: Gateway,
+    protocol: Protocol,
+    external_port: u16,
+    lease_duration: u32,
+    renewal: Option<Sender<()>>,
+}
+
+impl Lease {
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn external_port(&self) -> u16 {
+        self.external_port
+    }
+
+    /// Lease duration granted in seconds, zero if the mapping is permanent.
+    pub fn lease_duration(&self) -> u32 {
+        self.lease_duration
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.renewal.take();
+
+        if let Err(e) = self.gateway.delete_port_mapping(self.protocol, self.external_port) {
+            debug!("Failed to delete mapping of {} port {}: {}", self.protocol, self.external_port, e);
+        }
+    }
+}
+
+/// Search the network for the connection services of gateways.
+///
+/// Every service type is searched for at the same time, and the description
+/// of each gateway that responded is fetched to find its control urls.
+pub fn search_gateways(options: DiscoverOptions) -> SSDPResult<Vec<Gateway>> {
+    let searches: Vec<_> = GATEWAY_SERVICE_TYPES.iter()
+        .map(|service_type| {
+            let target = ST::Target(FieldMap::new(*service_type).expect("Invalid Gateway Service Type"));
+            let options = options.clone();
+
+            thread::spawn(move || discovery::discover(target, options))
+        })
+        .collect();
+
+    let mut locations = Vec::new();
+    for search in searches {
+        let devices = match search.join() {
+            Ok(result) => try!(result),
+            Err(_) => return Err(io::Error::new(ErrorKind::Other, "Gateway Search Thread Panicked").into()),
+        };
+
+        for location in devices.into_iter().filter_map(|device| device.location) {
+            if !locations.contains(&location) {
+                locations.push(location);
+            }
+        }
+    }
+
+    let mut gateways = Vec::new();
+    for location in locations {
+        match ::description::fetch(&location) {
+            Ok(description) => gateways.extend(Gateway::from_description(&description)),
+            Err(e) => debug!("Failed to fetch gateway description at {}: {}", location, e),
+        }
+    }
+
+    Ok(gateways)
+}
+
+/// Search the network for the first connection service of a gateway.
+pub fn search_gateway(options: DiscoverOptions) -> SSDPResult<Gateway> {
+    match try!(search_gateways(options)).into_iter().next() {
+        Some(gateway) => Ok(gateway),
+        None => Err(SSDPErrorKind::DeviceNotFound("InternetGatewayDevice".to_owned()).into()),
+    }
+}
+
+fn mapping_args(protocol: Protocol,
+                external_port: u16,
+                internal: SocketAddrV4,
+                lease_duration: u32,
+                description: &str)
+                -> Vec<(&'static str, Value)> {
+    vec![("NewRemoteHost", Value::from("")),
+         ("NewExternalPort", Value::from(external_port)),
+         ("NewProtocol", Value::from(protocol.to_string())),
+         ("NewInternalPort", Value::from(internal.port())),
+         ("NewInternalClient", Value::from(internal.ip().to_string())),
+         ("NewEnabled", Value::from(true)),
+         ("NewPortMappingDescription", Value::from(description)),
+         ("NewLeaseDuration", Value::from(lease_duration))]
+}
+
+fn output<'a>(results: &'a [(String, String)], name: &str) -> SSDPResult<&'a str> {
+    match results.iter().find(|&&(ref key, _)| key == name) {
+        Some(&(_, ref value)) => Ok(value.trim()),
+        None => Err(SSDPErrorKind::InvalidDocument(format!("response missing <{}>", name)).into()),
+    }
+}
+
+fn parse_output<T: ::std::str::FromStr>(results: &[(String, String)], name: &str) -> SSDPResult<T> {
+    let value = try!(output(results, name));
+
+    value.parse().map_err(|_| {
+        SSDPErrorKind::InvalidDocument(format!("<{}> has an invalid value: '{}'", name, value)).into()
+    })
+}
+
+fn is_upnp_error(error: &SSDPError, code: u16) -> bool {
+    match *error.kind() {
+        SSDPErrorKind::UPnPError(actual, _) => actual == code,
+        _ => false,
+    }
+}
+
+/// Returns true if the error is a fault or an HTTP error the device responded with.
+fn is_error_response(error: &SSDPError) -> bool {
+    match *error.kind() {
+        SSDPErrorKind::UPnPError(..) |
+        SSDPErrorKind::ResponseCode(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddrV4};
+    use std::time::Duration;
+    use std::thread;
+
+    use hyper::status::StatusCode;
+
+    use control::soap::{self, SoapAction};
+    use description::{self, Description};
+    use description::mocks::{MockRequest, MockResponse, MockServer};
+    use super::{Gateway, Protocol, WAN_IP_CONNECTION_1};
+
+    fn gateway_description(control_url: &str) -> String {
+        format!(r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+    <friendlyName>Router</friendlyName><manufacturer>Example</manufacturer><modelName>R1</modelName>
+    <UDN>uuid:igd-1</UDN>
+    <deviceList><device>
+      <deviceType>urn:schemas-upnp-org:device:WANDevice:1</deviceType>
+      <friendlyName>WAN</friendlyName><manufacturer>Example</manufacturer><modelName>R1</modelName>
+      <UDN>uuid:wan-1</UDN>
+      <deviceList><device>
+        <deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>
+        <friendlyName>WAN Connection</friendlyName>
+        <manufacturer>Example</manufacturer><modelName>R1</modelName>
+        <UDN>uuid:wanconn-1</UDN>
+        <serviceList><service>
+          <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+          <serviceId>urn:upnp-org:serviceId:WANIPConn1</serviceId>
+          <SCPDURL>/ipconn.xml</SCPDURL><controlURL>{}</controlURL><eventSubURL>/ipconn/event</eventSubURL>
+        </service></serviceList>
+      </device></deviceList>
+    </device></deviceList>
+  </device>
+</root>"#,
+                control_url)
+    }
+
+    fn fault(code: u16, description: &str) -> MockResponse {
+        MockResponse::with_status(StatusCode::InternalServerError,
+                                  format!("<?xml version=\"1.0\"?><s:Envelope \
+                                           xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\"><s:Body>\
+                                           <s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError\
+                                           </faultstring><detail><UPnPError><errorCode>{}</errorCode>\
+                                           <errorDescription>{}</errorDescription></UPnPError></detail>\
+                                           </s:Fault></s:Body></s:Envelope>",
+                                          code,
+                                          description))
+    }
+
+    fn respond(action: &str, args: &[(&str, &str)]) -> MockResponse {
+        let args: Vec<(String, String)> = args.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect();
+
+        MockResponse::ok(soap::encode_action(WAN_IP_CONNECTION_1, &format!("{}Response", action), &args))
+    }
+
+    /// Stand-in for a gateway with one existing port mapping.
+    fn fake_gateway(request: &MockRequest) -> MockResponse {
+        if request.path == "/description.xml" {
+            return MockResponse::ok(gateway_description("/ipconn/control"));
+        }
+
+        let action = request.headers
+            .get::<SoapAction>()
+            .and_then(|action| action.parts().map(|(_, name)| name.to_owned()))
+            .unwrap_or_default();
+        let args = soap::decode_action(&request.body[..], &action).unwrap_or_default();
+
+        match &action[..] {
+            "GetExternalIPAddress" => respond(&action, &[("NewExternalIPAddress", "203.0.113.7")]),
+            "AddPortMapping" | "DeletePortMapping" => respond(&action, &[]),
+            "GetGenericPortMappingEntry" if args[0].1 == "0" => {
+                respond(&action,
+                        &[("NewRemoteHost", ""),
+                          ("NewExternalPort", "8080"),
+                          ("NewProtocol", "TCP"),
+                          ("NewInternalPort", "80"),
+                          ("NewInternalClient", "192.168.1.20"),
+                          ("NewEnabled", "1"),
+                          ("NewPortMappingDescription", "web"),
+                          ("NewLeaseDuration", "0")])
+            }
+            "GetGenericPortMappingEntry" => fault(713, "SpecifiedArrayIndexInvalid"),
+            _ => fault(401, "Invalid Action"),
+        }
+    }
+
+    fn gateway(server: &MockServer) -> Gateway {
+        let description = description::fetch(&server.url("/description.xml")).unwrap();
+
+        Gateway::from_description(&description).remove(0)
+    }
+
+    fn actions(server: &MockServer) -> Vec<String> {
+        server.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|request| request.headers.get::<SoapAction>())
+            .filter_map(|action| action.parts().map(|(_, name)| name.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn positive_find_nested_service() {
+        let server = MockServer::new(fake_gateway);
+        let description = Description::parse(&::hyper::Url::parse(&server.url("/description.xml")).unwrap(),
+                                             gateway_description("/ipconn/control").as_bytes())
+            .unwrap();
+
+        let gateways = Gateway::from_description(&description);
+        assert_eq!(gateways.len(), 1);
+        assert_eq!(gateways[0].service_type(), WAN_IP_CONNECTION_1);
+    }
+
+    #[test]
+    fn positive_external_ip() {
+        let server = MockServer::new(fake_gateway);
+
+        assert_eq!(gateway(&server).external_ip().unwrap(),
+                   IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)));
+    }
+
+    #[test]
+    fn positive_port_mappings() {
+        let server = MockServer::new(fake_gateway);
+        let mappings = gateway(&server).port_mappings().unwrap();
+
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].external_port, 8080);
+        assert_eq!(mappings[0].protocol, Protocol::TCP);
+        assert!(mappings[0].enabled);
+    }
+
+    #[test]
+    fn positive_port_mappings_end_on_any_fault() {
+        let server = MockServer::new(|request| {
+            let response = fake_gateway(request);
+
+            if response.status == StatusCode::InternalServerError {
+                fault(402, "Invalid Args")
+            } else {
+                response
+            }
+        });
+
+        assert_eq!(gateway(&server).port_mappings().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn positive_add_any_falls_back_on_v1() {
+        let server = MockServer::new(fake_gateway);
+        let internal = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 20), 4000);
+
+        assert_eq!(gateway(&server).add_any_port_mapping(Protocol::UDP, 4000, internal, 60, "test").unwrap(),
+                   4000);
+        assert_eq!(actions(&server), vec!["AddPortMapping".to_owned()]);
+    }
+
+    #[test]
+    fn positive_lease_deleted_on_drop() {
+        let server = MockServer::new(fake_gateway);
+        let internal = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 20), 4000);
+
+        let lease = gateway(&server).lease(Protocol::TCP, 4000, internal, 2, "test").unwrap();
+        thread::sleep(Duration::from_millis(1500));
+        drop(lease);
+
+        assert_eq!(actions(&server),
+                   vec!["AddPortMapping".to_owned(),
+                        "AddPortMapping".to_owned(),
+                        "DeletePortMapping".to_owned()]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_fault_on_external_ip() {
+        let server = MockServer::new(|request| if request.path == "/description.xml" {
+            MockResponse::ok(gateway_description("/ipconn/control"))
+        } else {
+            fault(501, "Action Failed")
+        });
+
+        gateway(&server).external_ip().unwrap();
+    }
+}
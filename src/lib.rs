@@ -15,18 +15,57 @@ extern crate log;
 extern crate time;
 extern crate get_if_addrs;
 extern crate net2;
+#[cfg(unix)]
+extern crate libc;
+extern crate rand;
 #[macro_use]
 extern crate error_chain;
+#[cfg(feature = "description")]
+extern crate xml;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+#[cfg(feature = "description")]
+mod dom;
 mod error;
 mod field;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod net;
 mod receiver;
+#[cfg(feature = "serde")]
+mod serialize;
 
+pub mod cache;
+#[cfg(feature = "capture")]
+pub mod capture;
+#[cfg(feature = "description")]
+pub mod description;
+#[cfg(feature = "dial")]
+pub mod dial;
+#[cfg(feature = "control")]
+pub mod control;
+#[cfg(feature = "device")]
+pub mod device;
+pub mod discovery;
+#[cfg(feature = "eventing")]
+pub mod eventing;
 pub mod header;
+#[cfg(feature = "igd")]
+pub mod igd;
 pub mod message;
+#[cfg(unix)]
+pub mod minissdpd;
 
 pub use error::{SSDPError, SSDPErrorKind, SSDPResultExt, SSDPResult};
+pub use discovery::discover;
 pub use field::FieldMap;
-pub use receiver::{SSDPReceiver, SSDPIter};
+pub use receiver::{FromRawSSDP, SSDPReceiver, SSDPIter, SSDPRefIter, DedupKey, DedupReceiver, Deduplicated,
+                   ReceiveError, ReceiveStats};
 pub use net::IpVersionMode;
+#[cfg(feature = "metrics")]
+pub use metrics::{Exporter, Histogram, Metric, MetricFamily, MetricKind, MetricValue, Metrics,
+                  PrometheusExporter, PROMETHEUS_CONTENT_TYPE};
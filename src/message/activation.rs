@@ -0,0 +1,218 @@
+//! Sockets passed in by the service manager, as with systemd socket activation.
+//!
+//! The service manager binds the sockets, such as one on the SSDP port, and
+//! passes them to the process starting at file descriptor 3. The number of
+//! sockets is given by `LISTEN_FDS`, their names by `LISTEN_FDNAMES`, and
+//! `LISTEN_PID` names the process they are meant for.
+//!
+//! The sockets can be passed to `Listen::listen_on` and
+//! `ResponseScheduler::respond_from`.
+
+use std::env;
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::net::UdpSocket;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::process;
+
+use libc;
+
+use error::SSDPResult;
+
+/// First file descriptor passed in by the service manager.
+pub const LISTEN_FDS_START: RawFd = 3;
+
+const LISTEN_PID_VAR: &'static str = "LISTEN_PID";
+const LISTEN_FDS_VAR: &'static str = "LISTEN_FDS";
+const LISTEN_FDNAMES_VAR: &'static str = "LISTEN_FDNAMES";
+
+/// A UDP socket passed in by the service manager.
+#[derive(Debug)]
+pub struct ActivatedSocket {
+    /// Name given to the socket, if any.
+    pub name: Option<String>,
+    pub socket: UdpSocket,
+}
+
+/// Take the UDP sockets passed in by the service manager.
+///
+/// Returns no sockets if none were passed to this process. The variables
+/// describing the sockets are removed from the environment so that the
+/// sockets are only taken once, and are not passed on to child processes.
+/// Descriptors that are not IPv4 or IPv6 datagram sockets, such as Unix
+/// datagram sockets, are left open and untouched.
+pub fn listen_fds() -> SSDPResult<Vec<ActivatedSocket>> {
+    let pid = env::var(LISTEN_PID_VAR).ok();
+    let fds = env::var(LISTEN_FDS_VAR).ok();
+    let names = env::var(LISTEN_FDNAMES_VAR).ok();
+
+    env::remove_var(LISTEN_PID_VAR);
+    env::remove_var(LISTEN_FDS_VAR);
+    env::remove_var(LISTEN_FDNAMES_VAR);
+
+    let passed = try!(parse_listen_env(pid.as_ref().map(String::as_str),
+                                       fds.as_ref().map(String::as_str),
+                                       names.as_ref().map(String::as_str),
+                                       process::id()));
+
+    let mut sockets = Vec::new();
+    for (fd, name) in passed {
+        if !try!(is_udp_socket(fd)) {
+            debug!("Skipping passed descriptor {} which is not a UDP socket", fd);
+            continue;
+        }
+        try!(set_cloexec(fd));
+
+        sockets.push(ActivatedSocket {
+            name: name,
+            socket: unsafe { UdpSocket::from_raw_fd(fd) },
+        });
+    }
+
+    Ok(sockets)
+}
+
+/// Take the UDP sockets passed in by the service manager under the name,
+/// such as the one given by `FileDescriptorName=` in a systemd socket unit.
+///
+/// Sockets with other names are closed.
+pub fn listen_fds_with_name(name: &str) -> SSDPResult<Vec<UdpSocket>> {
+    let sockets = try!(listen_fds());
+
+    Ok(sockets.into_iter()
+        .filter(|activated| activated.name.as_ref().map(String::as_str) == Some(name))
+        .map(|activated| activated.socket)
+        .collect())
+}
+
+/// Descriptors passed to the process, along with their names, as described by
+/// the environment variables.
+fn parse_listen_env(pid: Option<&str>,
+                    fds: Option<&str>,
+                    names: Option<&str>,
+                    own_pid: u32)
+                    -> io::Result<Vec<(RawFd, Option<String>)>> {
+    let (pid, fds) = match (pid, fds) {
+        (Some(pid), Some(fds)) => (pid, fds),
+        _ => return Ok(Vec::new()),
+    };
+
+    let pid: u32 = try!(pid.trim().parse().map_err(|_| invalid_var(LISTEN_PID_VAR, pid)));
+    if pid != own_pid {
+        return Ok(Vec::new());
+    }
+
+    let count: RawFd = try!(fds.trim().parse().map_err(|_| invalid_var(LISTEN_FDS_VAR, fds)));
+    if count < 0 {
+        return Err(invalid_var(LISTEN_FDS_VAR, fds));
+    }
+
+    let mut names = names.map(|names| names.split(':').map(str::to_owned).collect()).unwrap_or(Vec::new());
+    names.resize(count as usize, String::new());
+
+    Ok(names.into_iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let name = if name.is_empty() { None } else { Some(name) };
+
+            (LISTEN_FDS_START + index as RawFd, name)
+        })
+        .collect())
+}
+
+fn invalid_var(name: &str, value: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput,
+                   format!("invalid value '{}' for {}", value, name))
+}
+
+fn is_udp_socket(fd: RawFd) -> io::Result<bool> {
+    let mut kind: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(fd,
+                         libc::SOL_SOCKET,
+                         libc::SO_TYPE,
+                         &mut kind as *mut libc::c_int as *mut libc::c_void,
+                         &mut len)
+    };
+
+    match result {
+        0 if kind == libc::SOCK_DGRAM => (),
+        0 => return Ok(false),
+        _ => {
+            let error = io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::ENOTSOCK) => Ok(false),
+                _ => Err(error),
+            };
+        }
+    }
+
+    // Unix datagram sockets can not be used as UDP sockets
+    let family = try!(socket_family(fd));
+    Ok(family == libc::AF_INET || family == libc::AF_INET6)
+}
+
+/// Address family of the socket, as given by the address it is bound to.
+fn socket_family(fd: RawFd) -> io::Result<libc::c_int> {
+    let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockname(fd,
+                          &mut addr as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+                          &mut len)
+    };
+
+    match result {
+        0 => Ok(addr.ss_family as libc::c_int),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixDatagram;
+
+    use super::{is_udp_socket, parse_listen_env};
+
+    #[test]
+    fn positive_parse_listen_env() {
+        let passed = parse_listen_env(Some("42"), Some("2"), Some("ssdp:"), 42).unwrap();
+
+        assert_eq!(passed, vec![(3, Some("ssdp".to_owned())), (4, None)]);
+    }
+
+    #[test]
+    fn positive_other_process() {
+        assert!(parse_listen_env(Some("41"), Some("2"), None, 42).unwrap().is_empty());
+        assert!(parse_listen_env(None, None, None, 42).unwrap().is_empty());
+    }
+
+    #[test]
+    fn positive_udp_sockets_only() {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (unix, _) = UnixDatagram::pair().unwrap();
+
+        assert!(is_udp_socket(udp.as_raw_fd()).unwrap());
+        assert!(!is_udp_socket(unix.as_raw_fd()).unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_invalid_count() {
+        parse_listen_env(Some("42"), Some("two"), None, 42).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Display, Formatter};
+
+use hyper::header::{Header, HeaderFormat, Headers};
+
+use {FieldMap, SSDPResult};
+use header::{HeaderRef, HeaderMut, NT, NTS};
+use message::{MessageType, Listen, Config};
+use message::ssdp::SSDPMessage;
+use message::multicast::{self, Multicast};
+use receiver::FromRawSSDP;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Value of the `NT` header field for event messages.
+const EVENT_NT_VALUE: &'static str = "event";
+
+/// Multicast event message reporting changed state variables of a service.
+///
+/// Introduced in `UPnP` 2.0, these messages are sent to a separate multicast
+/// group from discovery messages, see `Config::multicast_eventing()`.
+#[derive(Debug, Clone)]
+pub struct EventMessage {
+    message: SSDPMessage,
+}
+
+impl EventMessage {
+    /// Construct a new EventMessage with the `NT` and `NTS` header fields set.
+    pub fn new() -> Self {
+        let mut message = SSDPMessage::new(MessageType::Notify);
+
+        message.set(NT(FieldMap::upnp(EVENT_NT_VALUE)));
+        message.set(NTS::PropChange);
+
+        EventMessage { message: message }
+    }
+
+    /// Get the property set carried by this message.
+    pub fn body(&self) -> &[u8] {
+        self.message.body()
+    }
+
+    /// Set the property set carried by this message.
+    pub fn set_body<B: Into<Vec<u8>>>(&mut self, body: B) {
+        self.message.set_body(body)
+    }
+
+    /// Decode the names and values of the state variables carried by this message.
+    #[cfg(feature = "eventing")]
+    pub fn properties(&self) -> SSDPResult<Vec<(String, String)>> {
+        ::eventing::decode_propertyset(self.body())
+    }
+
+    /// Encode the names and values of the state variables as the body of this message.
+    #[cfg(feature = "eventing")]
+    pub fn set_properties(&mut self, properties: &[(String, String)]) {
+        self.set_body(::eventing::encode_propertyset(properties))
+    }
+}
+
+impl Multicast for EventMessage {
+    type Item = ();
+
+    /// Send the message to the multicast eventing group on all local network interfaces.
+    fn multicast(&mut self) -> SSDPResult<Self::Item> {
+        self.multicast_with_config(&Config::multicast_eventing())
+    }
+
+    fn multicast_with_config(&self, config: &Config) -> SSDPResult<Self::Item> {
+        multicast::send(&self.message, config)?;
+        Ok(())
+    }
+}
+
+impl Default for EventMessage {
+    fn default() -> Self {
+        EventMessage::new()
+    }
+}
+
+impl FromRawSSDP for EventMessage {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<EventMessage> {
+        let message = try!(SSDPMessage::raw_ssdp(bytes));
+
+        if message.message_type() != MessageType::Notify {
+            try!(Err("SSDP Message Received Is Not An EventMessage"))
+        }
+
+        match (message.get::<NT>(), message.get::<NTS>()) {
+            (Some(&NT(FieldMap::UPnP(ref nt))), Some(&NTS::PropChange)) if nt == EVENT_NT_VALUE => {
+                Ok(EventMessage { message: message })
+            }
+            _ => try!(Err("SSDP Message Received Is Not An EventMessage")),
+        }
+    }
+
+    fn message_headers(&self) -> Option<(MessageType, &Headers)> {
+        self.message.message_headers()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for EventMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.message.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for EventMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let message = try!(SSDPMessage::deserialize_as(deserializer, MessageType::Notify));
+
+        Ok(EventMessage { message: message })
+    }
+}
+
+impl Display for EventMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.message, f)
+    }
+}
+
+impl HeaderRef for EventMessage {
+    fn get<H>(&self) -> Option<&H>
+        where H: Header + HeaderFormat
+    {
+        self.message.get::<H>()
+    }
+
+    fn get_raw(&self, name: &str) -> Option<&[Vec<u8>]> {
+        self.message.get_raw(name)
+    }
+}
+
+impl HeaderMut for EventMessage {
+    fn set<H>(&mut self, value: H)
+        where H: Header + HeaderFormat
+    {
+        self.message.set(value)
+    }
+
+    fn set_raw<K>(&mut self, name: K, value: Vec<Vec<u8>>)
+        where K: Into<Cow<'static, str>> + Debug
+    {
+        self.message.set_raw(name, value)
+    }
+}
+
+/// Event listener that can listen to multicast event messages sent within the network.
+pub struct EventMessageListener;
+
+impl Listen for EventMessageListener {
+    type Message = EventMessage;
+
+    /// Listen for messages on the multicast eventing group of all local network interfaces.
+    fn listen() -> SSDPResult<::receiver::SSDPReceiver<Self::Message>> {
+        Self::listen_with_config(&Config::multicast_eventing())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use header::{HeaderRef, LVL, SEQ, SVCID};
+    use receiver::FromRawSSDP;
+    use super::EventMessage;
+
+    const RAW_EVENT: &'static str = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.246:7900\r\n\
+        CONTENT-TYPE: text/xml; charset=\"utf-8\"\r\n\
+        USN: uuid:device-1::urn:schemas-upnp-org:service:SwitchPower:1\r\n\
+        SVCID: urn:upnp-org:serviceId:SwitchPower\r\nNT: upnp:event\r\nNTS: upnp:propchange\r\nSEQ: 7\r\n\
+        LVL: upnp:info\r\nBOOTID.UPNP.ORG: 1\r\nCONTENT-LENGTH: 4\r\n\r\nbody";
+
+    #[test]
+    fn positive_event_message() {
+        let message = EventMessage::raw_ssdp(RAW_EVENT.as_bytes()).unwrap();
+
+        assert_eq!(message.get::<SVCID>(), Some(&SVCID("urn:upnp-org:serviceId:SwitchPower".to_owned())));
+        assert_eq!(message.get::<SEQ>(), Some(&SEQ(7)));
+        assert_eq!(message.get::<LVL>(), Some(&LVL::Info));
+        assert_eq!(message.body(), &b"body"[..]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_discovery_notify() {
+        let raw_message = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nNT: upnp:rootdevice\r\n\
+            NTS: ssdp:alive\r\n\r\n";
+
+        EventMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
-use std::net::{SocketAddr, IpAddr};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
 
 use error::SSDPResult;
 use message::{self, Config};
@@ -25,46 +26,46 @@ pub trait Listen {
     /// you will have to stop listening and start listening again,
     /// or we recommend using `listen_anyaddr_with_config()` instead.
     fn listen_with_config(config: &Config) -> SSDPResult<SSDPReceiver<Self::Message>> {
-        let mut ipv4_sock = None;
-        let mut ipv6_sock = None;
+        let addrs = try!(config_addrs(config));
+        let mut sockets = Vec::new();
 
         // Generate a list of reused sockets on the standard multicast address.
-        let addrs: Vec<SocketAddr> = try!(message::map_local(|&addr| Ok(Some(addr))));
-
-        for addr in addrs {
-            match addr {
-                SocketAddr::V4(_) => {
-                    let mcast_ip = config.ipv4_addr.parse().unwrap();
-
-                    if ipv4_sock.is_none() {
-                        ipv4_sock = Some(try!(net::bind_reuse(("0.0.0.0", config.port))));
-                    }
-
-                    let ref sock = ipv4_sock.as_ref().unwrap();
-
-                    debug!("Joining ipv4 multicast {} at iface: {}", mcast_ip, addr);
-                    try!(net::join_multicast(&sock, &addr, &mcast_ip));
-                }
-                SocketAddr::V6(_) => {
-                    let mcast_ip = config.ipv6_addr.parse().unwrap();
+        if addrs.iter().any(SocketAddr::is_ipv4) {
+            sockets.push(try!(net::bind_reuse(("0.0.0.0", config.port))));
+        }
+        if addrs.iter().any(SocketAddr::is_ipv6) {
+            sockets.push(try!(net::bind_reuse(("::", config.port))));
+        }
 
-                    if ipv6_sock.is_none() {
-                        ipv6_sock = Some(try!(net::bind_reuse(("::", config.port))));
-                    }
+        for sock in &sockets {
+            let ipv4 = try!(sock.local_addr()).is_ipv4();
+            try!(join_groups(sock, &addrs, config, ipv4));
+        }
 
-                    let ref sock = ipv6_sock.as_ref().unwrap();
+        Ok(try!(SSDPReceiver::new(sockets, None)))
+    }
 
-                    debug!("Joining ipv6 multicast {} at iface: {}", mcast_ip, addr);
-                    try!(net::join_multicast(&sock, &addr, &IpAddr::V6(mcast_ip)));
-                }
+    /// Listen for messages on sockets opened by the caller, such as sockets
+    /// bound to the SSDP port by the service manager.
+    ///
+    /// The sockets are only made to join the multicast group on the local
+    /// interfaces used by the config, other options are left as they were.
+    /// IPv6 sockets that also receive IPv4 traffic, such as the one opened by
+    /// systemd for `ListenDatagram=1900`, join the groups of both versions.
+    fn listen_on(sockets: Vec<UdpSocket>, config: &Config) -> SSDPResult<SSDPReceiver<Self::Message>> {
+        let addrs = try!(config_addrs(config));
+
+        for sock in &sockets {
+            // Receivers block on the sockets, which may have been passed in non-blocking
+            try!(sock.set_nonblocking(false));
+
+            let ipv4 = try!(sock.local_addr()).is_ipv4();
+            try!(join_groups(sock, &addrs, config, ipv4));
+            if try!(net::is_dual_stack(sock)) {
+                try!(join_groups(sock, &addrs, config, true));
             }
         }
 
-        let sockets = vec![ipv4_sock, ipv6_sock]
-            .into_iter()
-            .flat_map(|opt_interface| opt_interface)
-            .collect();
-
         Ok(try!(SSDPReceiver::new(sockets, None)))
     }
 
@@ -89,3 +90,28 @@ pub trait Listen {
         Ok(try!(SSDPReceiver::new(sockets, None)))
     }
 }
+
+/// Addresses of the local interfaces used by the config.
+fn config_addrs(config: &Config) -> SSDPResult<Vec<SocketAddr>> {
+    Ok(try!(message::map_local(|&addr| Ok(if config.uses_addr(&addr) { Some(addr) } else { None }))))
+}
+
+/// Join the multicast group of the config on every IPv4 interface, or every
+/// IPv6 interface.
+fn join_groups(sock: &UdpSocket, addrs: &[SocketAddr], config: &Config, ipv4: bool) -> SSDPResult<()> {
+    for addr in addrs.iter().filter(|addr| addr.is_ipv4() == ipv4) {
+        let mcast_ip = match *addr {
+            SocketAddr::V4(_) => IpAddr::V4(try!(config.ipv4_addr.parse())),
+            SocketAddr::V6(_) => IpAddr::V6(try!(config.ipv6_addr.parse())),
+        };
+
+        debug!("Joining multicast {} at iface: {}", mcast_ip, addr);
+        match net::join_multicast(sock, addr, &mcast_ip) {
+            // Sockets passed in may already be members of the group
+            Err(ref e) if e.kind() == ErrorKind::AddrInUse => (),
+            result => try!(result),
+        }
+    }
+
+    Ok(())
+}
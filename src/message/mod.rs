@@ -1,12 +1,16 @@
 //! Messaging primitives for discovering devices and services.
 
 use std::io;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use net::connector::UdpConnector;
 use net::IpVersionMode;
 
+#[cfg(unix)]
+pub mod activation;
+mod event;
 mod notify;
+mod responder;
 mod search;
 mod ssdp;
 pub mod listen;
@@ -15,20 +19,29 @@ pub mod multicast;
 use get_if_addrs;
 
 pub use message::multicast::Multicast;
-pub use message::search::{SearchRequest, SearchResponse, SearchListener};
+pub use message::search::{SearchRequest, SearchResponse, SearchListener, SearchSchedule, SearchOptions,
+                          SearchResults};
 pub use message::notify::{NotifyMessage, NotifyListener};
+pub use message::event::{EventMessage, EventMessageListener};
 pub use message::listen::Listen;
+pub use message::responder::ResponseScheduler;
 
 /// Multicast Socket Information
 pub const UPNP_MULTICAST_IPV4_ADDR: &'static str = "239.255.255.250";
 pub const UPNP_MULTICAST_IPV6_LINK_LOCAL_ADDR: &'static str = "FF02::C";
 pub const UPNP_MULTICAST_PORT: u16 = 1900;
 
+/// Multicast Socket Information For Eventing
+pub const UPNP_EVENT_MULTICAST_IPV4_ADDR: &'static str = "239.255.255.246";
+pub const UPNP_EVENT_MULTICAST_IPV6_LINK_LOCAL_ADDR: &'static str = "FF02::130";
+pub const UPNP_EVENT_MULTICAST_PORT: u16 = 7900;
+
 /// Default TTL For Multicast
 pub const UPNP_MULTICAST_TTL: u32 = 2;
 
 /// Enumerates different types of SSDP messages.
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MessageType {
     /// A notify message.
     Notify,
@@ -38,13 +51,53 @@ pub enum MessageType {
     Response,
 }
 
+/// Enumerates versions of the `UPnP` Device Architecture.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UDAVersion {
+    /// `UPnP` Device Architecture 1.0.
+    V1_0,
+    /// `UPnP` Device Architecture 1.1.
+    V1_1,
+    /// `UPnP` Device Architecture 2.0.
+    V2_0,
+}
+
+impl UDAVersion {
+    /// Largest `MX` value, in seconds, that a device should honor for this version.
+    ///
+    /// Devices treat any larger value received in a search request as this value.
+    pub fn max_mx(&self) -> u8 {
+        match *self {
+            UDAVersion::V1_0 => UDA1_0_MX_MAX,
+            UDAVersion::V1_1 | UDAVersion::V2_0 => UDA1_1_MX_MAX,
+        }
+    }
+}
+
+impl Default for UDAVersion {
+    fn default() -> Self {
+        UDAVersion::V1_0
+    }
+}
+
+/// Maximum `MX` value honored by devices implementing `UPnP` 1.0.
+const UDA1_0_MX_MAX: u8 = 120;
+
+/// Maximum `MX` value honored by devices implementing `UPnP` 1.1 and later.
+const UDA1_1_MX_MAX: u8 = 5;
+
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Config {
     pub ipv4_addr: String,
     pub ipv6_addr: String,
     pub port: u16,
     pub ttl: u32,
     pub mode: IpVersionMode,
+    /// Address of the only local interface to send and listen on, if restricted to one.
+    pub interface: Option<IpAddr>,
 }
 
 impl Config {
@@ -52,6 +105,14 @@ impl Config {
         Default::default()
     }
 
+    /// Construct a Config for the multicast group used by `UPnP` 2.0 eventing.
+    pub fn multicast_eventing() -> Self {
+        Config::new()
+            .set_ipv4_addr(UPNP_EVENT_MULTICAST_IPV4_ADDR)
+            .set_ipv6_addr(UPNP_EVENT_MULTICAST_IPV6_LINK_LOCAL_ADDR)
+            .set_port(UPNP_EVENT_MULTICAST_PORT)
+    }
+
     pub fn set_ipv4_addr<S: Into<String>>(mut self, value: S) -> Self {
         self.ipv4_addr = value.into();
         self
@@ -76,6 +137,23 @@ impl Config {
         self.mode = value;
         self
     }
+
+    /// Restrict sending and listening to the local interface with the address.
+    pub fn set_interface(mut self, value: IpAddr) -> Self {
+        self.interface = Some(value);
+        self
+    }
+
+    /// Whether the local address is used under this configuration.
+    fn uses_addr(&self, addr: &SocketAddr) -> bool {
+        let version_allowed = match (&self.mode, addr) {
+            (&IpVersionMode::V4Only, &SocketAddr::V6(_)) |
+            (&IpVersionMode::V6Only, &SocketAddr::V4(_)) => false,
+            _ => true,
+        };
+
+        version_allowed && self.interface.map_or(true, |ip| ip == addr.ip())
+    }
 }
 
 impl Default for Config {
@@ -86,10 +164,20 @@ impl Default for Config {
             port: UPNP_MULTICAST_PORT,
             ttl: UPNP_MULTICAST_TTL,
             mode: IpVersionMode::Any,
+            interface: None,
         }
     }
 }
 
+/// Generate multicast `UdpConnector` objects for the local interfaces used by the config.
+fn config_connectors(config: &Config) -> io::Result<Vec<UdpConnector>> {
+    map_local(|addr| if config.uses_addr(addr) {
+        UdpConnector::new(*addr, Some(config.ttl)).map(Some)
+    } else {
+        Ok(None)
+    })
+}
+
 /// Generate `UdpConnector` objects for all local `IPv4` interfaces.
 fn all_local_connectors(multicast_ttl: Option<u32>, filter: &IpVersionMode) -> io::Result<Vec<UdpConnector>> {
     trace!("Fetching all local connectors");
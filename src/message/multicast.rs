@@ -5,6 +5,8 @@ use error::SSDPResult;
 use net::connector::UdpConnector;
 use message::{self, Config};
 use message::ssdp::SSDPMessage;
+#[cfg(feature = "metrics")]
+use metrics;
 
 
 pub trait Multicast {
@@ -18,10 +20,25 @@ pub trait Multicast {
 }
 
 pub fn send(message: &SSDPMessage, config: &Config) -> SSDPResult<Vec<UdpConnector>> {
-    let mut connectors = try!(message::all_local_connectors(Some(config.ttl), &config.mode));
+    let mut connectors = try!(message::config_connectors(config));
 
-    for conn in &mut connectors {
-        match try!(conn.local_addr()) {
+    try!(send_on(message, &mut connectors, config));
+
+    Ok(connectors)
+}
+
+/// Send the message to the multicast group on connectors that were previously
+/// returned from `send`, so that replies to every transmission arrive on the same sockets.
+pub fn send_on(message: &SSDPMessage, connectors: &mut [UdpConnector], config: &Config) -> SSDPResult<()> {
+    #[cfg(feature = "metrics")]
+    let mut locals = Vec::with_capacity(connectors.len());
+
+    for conn in connectors.iter_mut() {
+        let local = try!(conn.local_addr());
+        #[cfg(feature = "metrics")]
+        locals.push(local);
+
+        match local {
             SocketAddr::V4(n) => {
                 let mcast_addr = (config.ipv4_addr.as_str(), config.port);
                 debug!("Sending ipv4 multicast through {} to {:?}", n, mcast_addr);
@@ -39,5 +56,8 @@ pub fn send(message: &SSDPMessage, config: &Config) -> SSDPResult<Vec<UdpConnect
         }
     }
 
-    Ok(connectors)
+    #[cfg(feature = "metrics")]
+    metrics::message_sent(message.message_type(), message, &locals);
+
+    Ok(())
 }
@@ -1,14 +1,18 @@
 use std::borrow::Cow;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::net::IpAddr;
+use std::slice;
 
-use hyper::header::{Header, HeaderFormat};
+use hyper::header::{Header, HeaderFormat, Headers};
 
 use error::SSDPResult;
 use header::{HeaderRef, HeaderMut};
-use message::{MessageType, Listen, Config};
+use message::{self, MessageType, Listen, Config};
 use message::ssdp::SSDPMessage;
 use message::multicast::{self, Multicast};
 use receiver::FromRawSSDP;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 
 /// Notify message that can be sent via multicast to devices on the network.
@@ -22,6 +26,28 @@ impl NotifyMessage {
     pub fn new() -> Self {
         NotifyMessage { message: SSDPMessage::new(MessageType::Notify) }
     }
+
+    /// Send the message via multicast on every local interface, passing a copy
+    /// of it to be adjusted for the address of each interface before it is sent.
+    ///
+    /// Useful for headers such as `LOCATION` that differ between interfaces.
+    /// Interfaces for which `prepare` returns false are skipped.
+    pub fn multicast_per_interface<F>(&self, config: &Config, mut prepare: F) -> SSDPResult<()>
+        where F: FnMut(IpAddr, &mut NotifyMessage) -> bool
+    {
+        let mut connectors = try!(message::config_connectors(config));
+
+        for connector in connectors.iter_mut() {
+            let mut message = self.clone();
+            if !prepare(try!(connector.local_addr()).ip(), &mut message) {
+                continue;
+            }
+
+            try!(multicast::send_on(&message.message, slice::from_mut(connector), config));
+        }
+
+        Ok(())
+    }
 }
 
 impl Multicast for NotifyMessage {
@@ -49,6 +75,32 @@ impl FromRawSSDP for NotifyMessage {
             Ok(NotifyMessage { message: message })
         }
     }
+
+    fn message_headers(&self) -> Option<(MessageType, &Headers)> {
+        self.message.message_headers()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for NotifyMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.message.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for NotifyMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let message = try!(SSDPMessage::deserialize_as(deserializer, MessageType::Notify));
+
+        Ok(NotifyMessage { message: message })
+    }
+}
+
+impl Display for NotifyMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.message, f)
+    }
 }
 
 impl HeaderRef for NotifyMessage {
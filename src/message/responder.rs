@@ -0,0 +1,310 @@
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use hyper::header::Host;
+use rand::{self, Rng};
+
+use error::SSDPResult;
+use header::{HeaderRef, MX, ST};
+use message::{SearchRequest, SearchResponse, UDAVersion};
+use net;
+
+
+/// Default spacing between consecutive responses sent for a single search request.
+const DEFAULT_BURST_SPACING_MS: u64 = 10;
+
+/// Schedules the responses a device sends when answering a `SearchRequest`.
+///
+/// Multicast searches are answered after a random delay between zero and the
+/// `MX` value of the request, capped according to the `UDAVersion` of the device.
+/// Unicast searches are answered right away. When more than one response is sent
+/// for a single request, such as for an `ssdp:all` search, responses are spread
+/// out so that no two are sent closer together than the burst spacing, which is
+/// shrunk when too many responses are sent to fit in the window otherwise.
+#[derive(Copy, Clone, Debug)]
+pub struct ResponseScheduler {
+    version: UDAVersion,
+    burst_spacing: Duration,
+}
+
+impl ResponseScheduler {
+    /// Construct a new ResponseScheduler for a device implementing the given version.
+    pub fn new(version: UDAVersion) -> ResponseScheduler {
+        ResponseScheduler {
+            version: version,
+            burst_spacing: Duration::from_millis(DEFAULT_BURST_SPACING_MS),
+        }
+    }
+
+    /// Set the minimum spacing between consecutive responses to a single request.
+    ///
+    /// Responses to multicast searches are never delayed past the `MX` window,
+    /// so the spacing may be shrunk to fit all of them in it.
+    pub fn set_burst_spacing(mut self, value: Duration) -> Self {
+        self.burst_spacing = value;
+        self
+    }
+
+    /// Largest delay a response to the given request may be sent after.
+    ///
+    /// Unicast requests, and requests without an `MX` header, are not delayed.
+    pub fn max_delay(&self, request: &SearchRequest) -> Duration {
+        match request.get::<MX>() {
+            Some(&MX(n)) if is_multicast_request(request) => {
+                let capped = if n > self.version.max_mx() {
+                    self.version.max_mx()
+                } else {
+                    n
+                };
+
+                Duration::new(capped as u64, 0)
+            }
+            _ => Duration::new(0, 0),
+        }
+    }
+
+    /// Generate sorted delays, relative to the arrival of the request, for sending
+    /// the given number of responses to the request.
+    ///
+    /// For `ssdp:all` searches, the responses are spread over the whole window so
+    /// that a device with many services does not answer in a single burst.
+    pub fn delays(&self, request: &SearchRequest, count: usize) -> Vec<Duration> {
+        let window = duration_to_millis(self.max_delay(request));
+        let mut spacing = duration_to_millis(self.burst_spacing);
+        let mut rng = rand::thread_rng();
+
+        let mut delays: Vec<u64> = if window == 0 {
+            vec![0; count]
+        } else {
+            // Shrink the spacing so that even the last response is sent within the window
+            spacing = spacing.min(window / count.max(1) as u64);
+            let spread = window - spacing * count.saturating_sub(1) as u64;
+
+            if let Some(&ST::All) = request.get::<ST>() {
+                // Give each response its own slot in the window so the burst is spread out
+                let slot = spread / count.max(1) as u64;
+                (0..count as u64).map(|index| index * slot + rng.gen_range(0, slot.max(1))).collect()
+            } else {
+                (0..count).map(|_| rng.gen_range(0, spread)).collect()
+            }
+        };
+        delays.sort();
+
+        // Space out consecutive responses, the offsets having left room for it
+        delays.into_iter()
+            .enumerate()
+            .map(|(index, delay)| Duration::from_millis(delay + index as u64 * spacing))
+            .collect()
+    }
+
+    /// Send the responses to the request according to the schedule.
+    ///
+    /// Responses are sent from a separate thread so that the caller can go back
+    /// to listening for requests; the returned handle finishes once the last
+    /// response has been sent.
+    pub fn respond<A: ToSocketAddrs>(&self,
+                                     request: &SearchRequest,
+                                     dst_addr: A,
+                                     responses: Vec<SearchResponse>)
+                                     -> SSDPResult<JoinHandle<()>> {
+        let dst_addr = try!(net::addr_from_trait(dst_addr));
+        let start = Instant::now();
+        let schedule = self.schedule(request, responses);
+
+        Ok(thread::spawn(move || send_scheduled(start, schedule, dst_addr, None)))
+    }
+
+    /// Send the responses to the request according to the schedule, through the
+    /// socket rather than sockets opened for every local interface.
+    ///
+    /// Useful when the socket the request was received on was opened by the
+    /// caller, such as a socket passed in by the service manager.
+    pub fn respond_from<A: ToSocketAddrs>(&self,
+                                          socket: &UdpSocket,
+                                          request: &SearchRequest,
+                                          dst_addr: A,
+                                          responses: Vec<SearchResponse>)
+                                          -> SSDPResult<JoinHandle<()>> {
+        let dst_addr = try!(net::addr_from_trait(dst_addr));
+        let socket = try!(socket.try_clone());
+        let start = Instant::now();
+        let schedule = self.schedule(request, responses);
+
+        Ok(thread::spawn(move || send_scheduled(start, schedule, dst_addr, Some(socket))))
+    }
+
+    /// Pair each response with the delay it is sent after.
+    fn schedule(&self,
+                request: &SearchRequest,
+                responses: Vec<SearchResponse>)
+                -> Vec<(Duration, SearchResponse)> {
+        self.delays(request, responses.len())
+            .into_iter()
+            .zip(responses.into_iter())
+            .collect()
+    }
+}
+
+impl Default for ResponseScheduler {
+    fn default() -> Self {
+        ResponseScheduler::new(UDAVersion::default())
+    }
+}
+
+/// Send each response once its delay, relative to the start, has elapsed.
+fn send_scheduled(start: Instant,
+                  schedule: Vec<(Duration, SearchResponse)>,
+                  dst_addr: SocketAddr,
+                  socket: Option<UdpSocket>) {
+    for (delay, mut response) in schedule {
+        let elapsed = start.elapsed();
+        if delay > elapsed {
+            thread::sleep(delay - elapsed);
+        }
+
+        let result = match socket {
+            Some(ref socket) => response.unicast_from(socket, dst_addr),
+            None => response.unicast(dst_addr),
+        };
+        if let Err(err) = result {
+            warn!("Failed to send scheduled search response to {}: {}", dst_addr, err);
+        }
+    }
+}
+
+/// Returns true if the request was sent to a multicast address.
+///
+/// Requests without a host that parses as an ip address are assumed to be multicast.
+fn is_multicast_request(request: &SearchRequest) -> bool {
+    let host = match request.get::<Host>() {
+        Some(host) => host,
+        None => return true,
+    };
+    let hostname = host.hostname.trim_matches(|c| c == '[' || c == ']');
+
+    match hostname.parse::<IpAddr>() {
+        Ok(addr) => addr.is_multicast(),
+        Err(_) => true,
+    }
+}
+
+fn duration_to_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use hyper::header::Host;
+
+    use FieldMap;
+    use header::{HeaderMut, MX, ST};
+    use message::{SearchRequest, SearchResponse, UDAVersion};
+    use super::ResponseScheduler;
+
+    fn search_request(host: &str, mx: Option<u8>, st: ST) -> SearchRequest {
+        let mut request = SearchRequest::new();
+
+        request.set(Host {
+            hostname: host.to_owned(),
+            port: Some(1900),
+        });
+        if let Some(n) = mx {
+            request.set(MX(n));
+        }
+        request.set(st);
+
+        request
+    }
+
+    #[test]
+    fn positive_multicast_max_delay() {
+        let request = search_request("239.255.255.250", Some(3), ST::All);
+
+        assert_eq!(ResponseScheduler::new(UDAVersion::V1_0).max_delay(&request),
+                   Duration::new(3, 0));
+    }
+
+    #[test]
+    fn positive_uda2_caps_max_delay() {
+        let request = search_request("239.255.255.250", Some(120), ST::All);
+
+        assert_eq!(ResponseScheduler::new(UDAVersion::V2_0).max_delay(&request),
+                   Duration::new(5, 0));
+        assert_eq!(ResponseScheduler::new(UDAVersion::V1_1).max_delay(&request),
+                   Duration::new(5, 0));
+        assert_eq!(ResponseScheduler::new(UDAVersion::V1_0).max_delay(&request),
+                   Duration::new(120, 0));
+    }
+
+    #[test]
+    fn positive_ipv6_multicast_max_delay() {
+        let request = search_request("[FF02::C]", Some(2), ST::All);
+
+        assert_eq!(ResponseScheduler::default().max_delay(&request), Duration::new(2, 0));
+    }
+
+    #[test]
+    fn positive_unicast_not_delayed() {
+        let request = search_request("192.168.1.1", Some(3), ST::All);
+        let delays = ResponseScheduler::default().set_burst_spacing(Duration::new(0, 0)).delays(&request, 3);
+
+        assert_eq!(delays, vec![Duration::new(0, 0); 3]);
+    }
+
+    #[test]
+    fn positive_delays_within_window() {
+        let request = search_request("239.255.255.250", Some(1), ST::All);
+        let spacing = Duration::from_millis(5);
+        let delays = ResponseScheduler::default().set_burst_spacing(spacing).delays(&request, 10);
+
+        assert_eq!(delays.len(), 10);
+        for pair in delays.windows(2) {
+            assert!(pair[1] >= pair[0] + spacing);
+        }
+        assert!(delays[9] < Duration::new(1, 0));
+    }
+
+    #[test]
+    fn positive_large_burst_within_window() {
+        for st in vec![ST::All, ST::Target(FieldMap::UPnP("rootdevice".to_owned()))] {
+            let request = search_request("239.255.255.250", Some(1), st);
+            let delays = ResponseScheduler::default().delays(&request, 500);
+
+            assert_eq!(delays.len(), 500);
+            for pair in delays.windows(2) {
+                assert!(pair[1] >= pair[0]);
+            }
+            assert!(delays[499] < Duration::new(1, 0));
+        }
+    }
+
+    #[test]
+    fn positive_no_mx_not_delayed() {
+        let request = search_request("239.255.255.250", None, ST::All);
+
+        assert_eq!(ResponseScheduler::default().max_delay(&request), Duration::new(0, 0));
+    }
+
+    #[test]
+    fn positive_respond_from_socket() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let control_point = UdpSocket::bind("127.0.0.1:0").unwrap();
+        control_point.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+
+        let request = search_request("127.0.0.1", Some(3), ST::All);
+        ResponseScheduler::default()
+            .respond_from(&socket, &request, control_point.local_addr().unwrap(), vec![SearchResponse::new()])
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let mut buffer = [0u8; 1024];
+        let (len, src) = control_point.recv_from(&mut buffer).unwrap();
+        assert_eq!(src, socket.local_addr().unwrap());
+        assert!(buffer[..len].starts_with(b"HTTP/1.1 200 OK"));
+    }
+}
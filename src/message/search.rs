@@ -1,10 +1,11 @@
 use std::borrow::Cow;
-use std::fmt::Debug;
-use std::net::ToSocketAddrs;
-use std::time::Duration;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use std::thread;
 use std::io;
 
-use hyper::header::{Header, HeaderFormat};
+use hyper::header::{Header, HeaderFormat, Headers};
 
 use error::SSDPResult;
 use header::{HeaderRef, HeaderMut, MX};
@@ -12,7 +13,10 @@ use message::{self, MessageType, Listen, Config};
 use message::ssdp::SSDPMessage;
 use message::multicast::{self, Multicast};
 use receiver::{SSDPReceiver, FromRawSSDP};
+use net::connector::UdpConnector;
 use net;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 
 /// Overhead to add to device response times to account for transport time.
@@ -21,6 +25,9 @@ const NETWORK_TIMEOUT_OVERHEAD: u8 = 1;
 /// Devices are required to respond within 1 second of receiving unicast message.
 const DEFAULT_UNICAST_TIMEOUT: u8 = 1 + NETWORK_TIMEOUT_OVERHEAD;
 
+/// Default spacing between transmissions of a repeated multicast search.
+const DEFAULT_SEARCH_SPACING_SECS: u64 = 1;
+
 /// Search request that can be sent via unicast or multicast to devices on the network.
 #[derive(Debug, Clone)]
 pub struct SearchRequest {
@@ -54,19 +61,257 @@ impl SearchRequest {
 
         Ok(try!(SSDPReceiver::new(raw_connectors, opt_timeout)))
     }
+
+    /// Send this search request to the multicast group according to the given schedule.
+    ///
+    /// Every transmission goes out on the same sockets, so responses to all of
+    /// them arrive on the returned receiver.
+    pub fn multicast_with_schedule(&self,
+                                   config: &Config,
+                                   schedule: &SearchSchedule)
+                                   -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        let window = try!(schedule.collection_window(self.get::<MX>()));
+
+        self.multicast_for(config, schedule, window)
+    }
+
+    /// Send this search request to the multicast group, collecting responses
+    /// according to the given options.
+    ///
+    /// If the options specify a deadline, the request does not need an `MX` header.
+    pub fn multicast_with_options(&self,
+                                  config: &Config,
+                                  options: SearchOptions)
+                                  -> SSDPResult<SearchResults> {
+        let recv = match options.deadline {
+            Some(deadline) => {
+                let start = Instant::now();
+                let recv = try!(self.multicast_for(config, &options.schedule, deadline));
+
+                // Deadlines too far away to add to an instant are never reached
+                match start.checked_add(deadline) {
+                    Some(deadline) => recv.set_deadline(deadline),
+                    None => recv,
+                }
+            }
+            None => try!(self.multicast_with_schedule(config, &options.schedule)),
+        };
+
+        Ok(SearchResults {
+            recv: recv,
+            remaining: options.max_results,
+            stop_on: options.stop_on,
+            done: false,
+        })
+    }
+
+    /// Send this search request to the multicast group, waiting at most `window`
+    /// between responses.
+    fn multicast_for(&self,
+                     config: &Config,
+                     schedule: &SearchSchedule,
+                     window: Duration)
+                     -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        let connectors = try!(multicast::send(&self.message, config));
+
+        let mut raw_connectors = Vec::with_capacity(connectors.len());
+        for conn in &connectors {
+            raw_connectors.push(try!(conn.try_clone()).deconstruct());
+        }
+
+        if schedule.transmissions > 1 {
+            let message = self.message.clone();
+            let config = config.clone();
+            let schedule = *schedule;
+
+            thread::spawn(move || retransmit(message, connectors, config, schedule));
+        }
+
+        Ok(try!(SSDPReceiver::new(raw_connectors, Some(window))))
+    }
 }
 
 impl Multicast for SearchRequest {
     type Item = SSDPReceiver<SearchResponse>;
 
     fn multicast_with_config(&self, config: &Config) -> SSDPResult<Self::Item> {
-        let connectors = multicast::send(&self.message, config)?;
+        self.multicast_with_schedule(config, &SearchSchedule::default())
+    }
+}
 
-        let mcast_timeout = try!(multicast_timeout(self.get::<MX>()));
-        let mut raw_connectors = Vec::with_capacity(connectors.len());
-        raw_connectors.extend(connectors.into_iter().map(|conn| conn.deconstruct()));
+/// Send the remaining transmissions of a repeated search on the original connectors.
+fn retransmit(message: SSDPMessage,
+              mut connectors: Vec<UdpConnector>,
+              config: Config,
+              schedule: SearchSchedule) {
+    for attempt in 1..schedule.transmissions {
+        thread::sleep(schedule.spacing);
+
+        debug!("Retransmitting search request, attempt {} of {}", attempt + 1, schedule.transmissions);
+        if let Err(err) = multicast::send_on(&message, &mut connectors, &config) {
+            warn!("Failed to retransmit search request: {}", err);
+        }
+    }
+}
+
+/// Options for collecting the responses to a multicast search request.
+pub struct SearchOptions {
+    schedule: SearchSchedule,
+    deadline: Option<Duration>,
+    max_results: Option<usize>,
+    stop_on: Option<Box<Fn(&SearchResponse) -> bool + Send>>,
+}
+
+impl SearchOptions {
+    /// Construct a new SearchOptions that collects every response until `MX`
+    /// seconds (plus some network overhead) have passed without a response.
+    pub fn new() -> SearchOptions {
+        SearchOptions {
+            schedule: SearchSchedule::new(),
+            deadline: None,
+            max_results: None,
+            stop_on: None,
+        }
+    }
+
+    /// Set the schedule used to transmit the search request.
+    pub fn set_schedule(mut self, value: SearchSchedule) -> Self {
+        self.schedule = value;
+        self
+    }
+
+    /// Set the total time responses are collected for, regardless of `MX`.
+    pub fn set_deadline(mut self, value: Duration) -> Self {
+        self.deadline = Some(value);
+        self
+    }
+
+    /// Stop collecting after the given number of responses.
+    pub fn set_max_results(mut self, value: usize) -> Self {
+        self.max_results = Some(value);
+        self
+    }
+
+    /// Stop collecting after the first response for which the predicate returns true.
+    ///
+    /// The matching response is the last one yielded.
+    pub fn set_stop_on<F>(mut self, predicate: F) -> Self
+        where F: Fn(&SearchResponse) -> bool + Send + 'static
+    {
+        self.stop_on = Some(Box::new(predicate));
+        self
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions::new()
+    }
+}
+
+/// Iterator over the responses to a multicast search request.
+pub struct SearchResults {
+    recv: SSDPReceiver<SearchResponse>,
+    remaining: Option<usize>,
+    stop_on: Option<Box<Fn(&SearchResponse) -> bool + Send>>,
+    done: bool,
+}
+
+impl Iterator for SearchResults {
+    type Item = (SearchResponse, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.remaining == Some(0) {
+            return None;
+        }
+
+        let (response, src) = match self.recv.recv() {
+            Ok(received) => received,
+            Err(_) => {
+                self.done = true;
+                return None;
+            }
+        };
+
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= 1;
+        }
+        if let Some(ref stop_on) = self.stop_on {
+            self.done = stop_on(&response);
+        }
+
+        Some((response, src))
+    }
+}
+
+/// Schedule for transmitting a multicast search request more than once.
+///
+/// UDP is lossy, so the `UPnP` standard recommends sending each multicast
+/// search several times. By default, a search is only sent once.
+#[derive(Copy, Clone, Debug)]
+pub struct SearchSchedule {
+    transmissions: u8,
+    spacing: Duration,
+    window: Option<Duration>,
+}
+
+impl SearchSchedule {
+    /// Construct a new SearchSchedule that transmits a search once.
+    pub fn new() -> SearchSchedule {
+        SearchSchedule {
+            transmissions: 1,
+            spacing: Duration::new(DEFAULT_SEARCH_SPACING_SECS, 0),
+            window: None,
+        }
+    }
+
+    /// Set the number of times the search is transmitted, at least one.
+    pub fn set_transmissions(mut self, value: u8) -> Self {
+        self.transmissions = if value == 0 { 1 } else { value };
+        self
+    }
+
+    /// Set the time between consecutive transmissions.
+    pub fn set_spacing(mut self, value: Duration) -> Self {
+        self.spacing = value;
+        self
+    }
+
+    /// Set the total time responses are collected for, starting at the first transmission.
+    ///
+    /// If not set, responses are collected until `MX` seconds (plus some network
+    /// overhead) after the last transmission.
+    pub fn set_window(mut self, value: Duration) -> Self {
+        self.window = Some(value);
+        self
+    }
+
+    /// Get the number of times the search is transmitted.
+    pub fn transmissions(&self) -> u8 {
+        self.transmissions
+    }
 
-        Ok(try!(SSDPReceiver::new(raw_connectors, Some(mcast_timeout))))
+    /// Get the time between consecutive transmissions.
+    pub fn spacing(&self) -> Duration {
+        self.spacing
+    }
+
+    /// Get the total time responses are collected for.
+    fn collection_window(&self, mx: Option<&MX>) -> SSDPResult<Duration> {
+        match self.window {
+            Some(window) => Ok(window),
+            None => {
+                let retransmit_time = self.spacing * (self.transmissions as u32 - 1);
+
+                Ok(retransmit_time + try!(multicast_timeout(mx)))
+            }
+        }
+    }
+}
+
+impl Default for SearchSchedule {
+    fn default() -> Self {
+        SearchSchedule::new()
     }
 }
 
@@ -102,6 +347,32 @@ impl FromRawSSDP for SearchRequest {
             Ok(SearchRequest { message: message })
         }
     }
+
+    fn message_headers(&self) -> Option<(MessageType, &Headers)> {
+        self.message.message_headers()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SearchRequest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.message.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for SearchRequest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let message = try!(SSDPMessage::deserialize_as(deserializer, MessageType::Search));
+
+        Ok(SearchRequest { message: message })
+    }
+}
+
+impl Display for SearchRequest {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.message, f)
+    }
 }
 
 impl HeaderRef for SearchRequest {
@@ -168,6 +439,14 @@ impl SearchResponse {
 
         Ok(())
     }
+
+    /// Send this search response to a single host through the socket, such as
+    /// the socket the search request was received on.
+    pub fn unicast_from<A: ToSocketAddrs>(&mut self, socket: &UdpSocket, dst_addr: A) -> SSDPResult<()> {
+        let mut connector = UdpConnector::from_socket(try!(socket.try_clone()));
+
+        self.message.send(&mut connector, dst_addr)
+    }
 }
 
 impl Default for SearchResponse {
@@ -193,6 +472,32 @@ impl FromRawSSDP for SearchResponse {
             Ok(SearchResponse { message: message })
         }
     }
+
+    fn message_headers(&self) -> Option<(MessageType, &Headers)> {
+        self.message.message_headers()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SearchResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.message.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for SearchResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let message = try!(SSDPMessage::deserialize_as(deserializer, MessageType::Response));
+
+        Ok(SearchResponse { message: message })
+    }
+}
+
+impl Display for SearchResponse {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.message, f)
+    }
 }
 
 impl HeaderRef for SearchResponse {
@@ -223,7 +528,81 @@ impl HeaderMut for SearchResponse {
 
 #[cfg(test)]
 mod tests {
-    use header::MX;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use header::{HeaderRef, MX, USN};
+    use receiver::SSDPReceiver;
+    use super::{SearchResponse, SearchResults, SearchSchedule};
+
+    fn search_results(usns: &[&str], max_results: Option<usize>) -> SearchResults {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        for usn in usns {
+            let raw = format!("HTTP/1.1 200 OK\r\nUSN: {}\r\n\r\n", usn);
+            send_sock.send_to(raw.as_bytes(), recv_sock.local_addr().unwrap()).unwrap();
+        }
+
+        SearchResults {
+            recv: SSDPReceiver::new(vec![recv_sock], Some(Duration::from_millis(200))).unwrap(),
+            remaining: max_results,
+            stop_on: None,
+            done: false,
+        }
+    }
+
+    #[test]
+    fn positive_max_results() {
+        let results = search_results(&["uuid:a", "uuid:b", "uuid:c"], Some(2));
+
+        assert_eq!(results.count(), 2);
+    }
+
+    #[test]
+    fn positive_stop_on_match() {
+        let mut results = search_results(&["uuid:a", "uuid:b", "uuid:c"], None);
+        results.stop_on = Some(Box::new(|response: &SearchResponse| {
+            response.get_raw("USN").map_or(false, |usn| &usn[0][..] == b"uuid:b")
+        }));
+
+        let found: Vec<USN> = results.map(|(response, _)| response.get::<USN>().unwrap().clone()).collect();
+
+        assert_eq!(found.len(), 2);
+    }
+
+    #[test]
+    fn positive_single_transmission_window() {
+        let window = SearchSchedule::new().collection_window(Some(&MX(3))).unwrap();
+
+        assert_eq!(window, Duration::new(4, 0));
+    }
+
+    #[test]
+    fn positive_repeated_transmission_window() {
+        let schedule = SearchSchedule::new().set_transmissions(3).set_spacing(Duration::from_millis(500));
+        let window = schedule.collection_window(Some(&MX(3))).unwrap();
+
+        assert_eq!(window, Duration::new(5, 0));
+    }
+
+    #[test]
+    fn positive_explicit_window_without_mx() {
+        let schedule = SearchSchedule::new().set_window(Duration::new(7, 0));
+
+        assert_eq!(schedule.collection_window(None).unwrap(), Duration::new(7, 0));
+    }
+
+    #[test]
+    fn positive_zero_transmissions_clamped() {
+        assert_eq!(SearchSchedule::new().set_transmissions(0).transmissions(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_schedule_window_without_mx() {
+        SearchSchedule::new().set_transmissions(2).collection_window(None).unwrap();
+    }
 
     #[test]
     fn positive_multicast_timeout() {
@@ -1,6 +1,6 @@
 use std::borrow::{Cow, ToOwned};
-use std::fmt::Debug;
-use std::io::Write;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{Read, Write};
 use std::net::{ToSocketAddrs, SocketAddr};
 
 use hyper::Url;
@@ -38,6 +38,7 @@ const SEARCH_METHOD: &'static str = "M-SEARCH";
 pub struct SSDPMessage {
     method: MessageType,
     headers: Headers,
+    body: Vec<u8>,
 }
 
 impl SSDPMessage {
@@ -46,6 +47,7 @@ impl SSDPMessage {
         SSDPMessage {
             method: message_type,
             headers: Headers::new(),
+            body: Vec::new(),
         }
     }
 
@@ -54,6 +56,18 @@ impl SSDPMessage {
         self.method
     }
 
+    /// Get the body of this message, empty for most SSDP messages.
+    pub fn body(&self) -> &[u8] {
+        &self.body[..]
+    }
+
+    /// Set the body of this message.
+    ///
+    /// The `Content-Length` header field will be taken care of when sending.
+    pub fn set_body<B: Into<Vec<u8>>>(&mut self, body: B) {
+        self.body = body.into();
+    }
+
     /// Send this request to the given destination address using the given connector.
     ///
     /// The host header field will be taken care of by the underlying library.
@@ -65,11 +79,11 @@ impl SSDPMessage {
         match self.method {
             MessageType::Notify => {
                 trace!("Notify to: {:?}", dst_sock_addr);
-                send_request(NOTIFY_METHOD, &self.headers, connector, dst_sock_addr)
+                send_request(NOTIFY_METHOD, &self.headers, &self.body, connector, dst_sock_addr)
             }
             MessageType::Search => {
                 trace!("Sending search request...");
-                send_request(SEARCH_METHOD, &self.headers, connector, dst_sock_addr)
+                send_request(SEARCH_METHOD, &self.headers, &self.body, connector, dst_sock_addr)
             }
             MessageType::Response => {
                 trace!("Sending response to: {:?}", dst_sock_addr);
@@ -79,16 +93,17 @@ impl SSDPMessage {
 
                 let net_stream = try!(connector.connect(&dst_ip_string[..], dst_port, "")).into();
 
-                send_response(&self.headers, net_stream)
+                send_response(&self.headers, &self.body, net_stream)
             }
         }
     }
 }
 
 #[allow(unused)]
-/// Send a request using the connector with the supplied method and headers.
+/// Send a request using the connector with the supplied method, headers and body.
 fn send_request<C, S>(method: &str,
                       headers: &Headers,
+                      body: &[u8],
                       connector: &mut C,
                       dst_addr: SocketAddr)
                       -> SSDPResult<()>
@@ -104,31 +119,35 @@ fn send_request<C, S>(method: &str,
     trace!("Copying headers...");
     copy_headers(headers, request.headers_mut());
     trace!("Setting length");
-    request.headers_mut().set(ContentLength(0));
+    request.headers_mut().set(ContentLength(body.len() as u64));
 
     // Send Will Always Fail Within The UdpConnector Which Is Intended So That
     // Hyper Does Not Block For A Response Since We Are Handling That Ourselves.
 
     trace!("actual .send ...");
-    try!(request.start()).send();
+    let mut request = try!(request.start());
+    try!(request.write_all(body));
+    request.send();
 
     Ok(())
 }
 
-/// Send an Ok response on the Writer with the supplied headers.
-fn send_response<W>(headers: &Headers, mut dst_writer: W) -> SSDPResult<()>
+/// Send an Ok response on the Writer with the supplied headers and body.
+fn send_response<W>(headers: &Headers, body: &[u8], mut dst_writer: W) -> SSDPResult<()>
     where W: Write
 {
     let mut temp_headers = Headers::new();
 
     copy_headers(headers, &mut temp_headers);
-    temp_headers.set(ContentLength(0));
+    temp_headers.set(ContentLength(body.len() as u64));
 
     let mut response = Response::new(&mut dst_writer as &mut Write, &mut temp_headers);
     *response.status_mut() = StatusCode::Ok;
 
     // Have to make sure response is destroyed here for lifetime issues with temp_headers
-    try!(try!(response.start()).end());
+    let mut response = try!(response.start());
+    try!(response.write_all(body));
+    try!(response.end());
 
     Ok(())
 }
@@ -155,6 +174,184 @@ fn copy_headers(src_headers: &Headers, dst_headers: &mut Headers) {
     }
 }
 
+impl Display for SSDPMessage {
+    /// Render the message as it would be sent, headers included.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.method {
+            MessageType::Notify => try!(write!(f, "{} * {}\r\n", NOTIFY_METHOD, HttpVersion::Http11)),
+            MessageType::Search => try!(write!(f, "{} * {}\r\n", SEARCH_METHOD, HttpVersion::Http11)),
+            MessageType::Response => try!(write!(f, "{} {}\r\n", HttpVersion::Http11, StatusCode::Ok)),
+        }
+
+        try!(Display::fmt(&self.headers, f));
+        try!(f.write_str("\r\n"));
+        f.write_str(&String::from_utf8_lossy(&self.body))
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serialize {
+    use std::borrow::Cow;
+    use std::fmt::{self, Formatter};
+    use std::str;
+
+    use hyper::header::Headers;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde::de::{Error, MapAccess, Visitor};
+    use serde::ser::{SerializeMap, SerializeStruct};
+
+    use message::MessageType;
+    use super::SSDPMessage;
+
+    /// Lines of a header field, a single line serializing as a string.
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Lines {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    impl Lines {
+        fn new(raw: &[Vec<u8>]) -> Lines {
+            let mut lines: Vec<String> = raw.iter()
+                .map(|line| String::from_utf8_lossy(line).into_owned())
+                .collect();
+
+            match lines.len() {
+                1 => Lines::One(lines.remove(0)),
+                _ => Lines::Many(lines),
+            }
+        }
+
+        fn into_raw(self) -> Vec<Vec<u8>> {
+            match self {
+                Lines::One(line) => vec![line.into_bytes()],
+                Lines::Many(lines) => lines.into_iter().map(String::into_bytes).collect(),
+            }
+        }
+    }
+
+    struct HeaderMap<'a>(&'a Headers);
+
+    impl<'a> Serialize for HeaderMap<'a> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = try!(serializer.serialize_map(Some(self.0.len())));
+
+            for view in self.0.iter() {
+                let lines = match self.0.get_raw(view.name()) {
+                    Some(raw) => Lines::new(raw),
+                    None => Lines::One(view.value_string()),
+                };
+                try!(map.serialize_entry(view.name(), &lines));
+            }
+
+            map.end()
+        }
+    }
+
+    /// Header fields in the order they appear in the message.
+    #[derive(Default)]
+    struct OrderedHeaders(Vec<(String, Lines)>);
+
+    impl<'de> Deserialize<'de> for OrderedHeaders {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_map(OrderedHeadersVisitor)
+        }
+    }
+
+    struct OrderedHeadersVisitor;
+
+    impl<'de> Visitor<'de> for OrderedHeadersVisitor {
+        type Value = OrderedHeaders;
+
+        fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+            formatter.write_str("a map of header names to their values")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<OrderedHeaders, A::Error> {
+            let mut headers = Vec::new();
+            while let Some(entry) = try!(access.next_entry()) {
+                headers.push(entry);
+            }
+
+            Ok(OrderedHeaders(headers))
+        }
+    }
+
+    /// Body of a message, serializing as a string unless it is not valid UTF-8.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Body {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+
+    #[derive(Deserialize)]
+    struct Message {
+        method: MessageType,
+        #[serde(default)]
+        headers: OrderedHeaders,
+        #[serde(default)]
+        body: Option<Body>,
+    }
+
+    impl Serialize for SSDPMessage {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = try!(serializer.serialize_struct("SSDPMessage", 3));
+
+            try!(state.serialize_field("method", &self.method));
+            try!(state.serialize_field("headers", &HeaderMap(&self.headers)));
+            if self.body.is_empty() {
+                try!(state.skip_field("body"));
+            } else if let Ok(text) = str::from_utf8(&self.body) {
+                try!(state.serialize_field("body", text));
+            } else {
+                try!(state.serialize_field("body", &self.body));
+            }
+
+            state.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SSDPMessage {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let message = try!(Message::deserialize(deserializer));
+            let mut headers = Headers::new();
+
+            for (name, lines) in message.headers.0 {
+                headers.set_raw(Cow::Owned(name), lines.into_raw());
+            }
+
+            Ok(SSDPMessage {
+                method: message.method,
+                headers: headers,
+                body: match message.body {
+                    Some(Body::Text(text)) => text.into_bytes(),
+                    Some(Body::Bytes(bytes)) => bytes,
+                    None => Vec::new(),
+                },
+            })
+        }
+    }
+
+    impl SSDPMessage {
+        /// Deserialize a message, failing if it is not of the expected type.
+        pub fn deserialize_as<'de, D>(deserializer: D, expected: MessageType) -> Result<SSDPMessage, D::Error>
+            where D: Deserializer<'de>
+        {
+            let message = try!(SSDPMessage::deserialize(deserializer));
+
+            if message.method != expected {
+                return Err(D::Error::custom(format!("expected a {:?} message, found a {:?} message",
+                                                    expected,
+                                                    message.method)));
+            }
+
+            Ok(message)
+        }
+    }
+}
+
 impl HeaderRef for SSDPMessage {
     fn get<H>(&self) -> Option<&H>
         where H: Header + HeaderFormat
@@ -186,14 +383,16 @@ impl FromRawSSDP for SSDPMessage {
         let mut buf_reader = BufReader::new(bytes);
 
         if let Ok(parts) = h1::parse_request(&mut buf_reader) {
-            let message_result = message_from_request(parts);
+            let message_result = message_from_request(parts)
+                .and_then(|message| read_body(message, buf_reader));
 
             log_message_result(&message_result, bytes);
             message_result
         } else {
             match h1::parse_response(&mut buf_reader) {
                 Ok(parts) => {
-                    let message_result = message_from_response(parts);
+                    let message_result = message_from_response(parts)
+                        .and_then(|message| read_body(message, buf_reader));
 
                     log_message_result(&message_result, bytes);
                     message_result
@@ -206,6 +405,20 @@ impl FromRawSSDP for SSDPMessage {
             }
         } 
     }
+
+    fn message_headers(&self) -> Option<(MessageType, &Headers)> {
+        Some((self.method, &self.headers))
+    }
+}
+
+/// Read the body following the headers of the message, up to its `Content-Length`.
+fn read_body<R: Read>(mut message: SSDPMessage, reader: R) -> SSDPResult<SSDPMessage> {
+    match message.headers.get::<ContentLength>().map(|length| length.0) {
+        Some(length) => try!(reader.take(length).read_to_end(&mut message.body)),
+        None => try!(reader.take(u64::max_value()).read_to_end(&mut message.body)),
+    };
+
+    Ok(message)
 }
 
 /// Logs a debug! message based on the value of the `SSDPResult`.
@@ -230,12 +443,14 @@ fn message_from_request(parts: Incoming<(Method, RequestUri)>) -> SSDPResult<SSD
                     Ok(SSDPMessage {
                         method: MessageType::Notify,
                         headers: headers,
+                        body: Vec::new(),
                     })
                 }
                 SEARCH_METHOD => {
                     Ok(SSDPMessage {
                         method: MessageType::Search,
                         headers: headers,
+                        body: Vec::new(),
                     })
                 }
                 _ => Err(SSDPErrorKind::InvalidMethod(n).into()),
@@ -259,6 +474,7 @@ fn message_from_response(parts: Incoming<RawStatus>) -> SSDPResult<SSDPMessage>
     Ok(SSDPMessage {
         method: MessageType::Response,
         headers: headers,
+        body: Vec::new(),
     })
 }
 
@@ -322,12 +538,16 @@ mod mocks {
 
             self.receivers.borrow_mut().push(recv);
 
-            Ok(MockStream { sender: send })
+            Ok(MockStream {
+                sender: send,
+                started: false,
+            })
         }
     }
 
     pub struct MockStream {
         sender: Sender<Vec<u8>>,
+        started: bool,
     }
 
     impl NetworkStream for MockStream {
@@ -353,7 +573,7 @@ mod mocks {
             // Hyper will generate a request with a /, we need to intercept that.
             let mut buffer = vec![0u8; buf.len()];
 
-            let mut found = false;
+            let mut found = self.started;
             for (src, dst) in buf.iter().zip(buffer.iter_mut()) {
                 if *src == b'/' && !found && buf[0] != b'H' {
                     *dst = b'*';
@@ -363,6 +583,7 @@ mod mocks {
                 }
             }
 
+            self.started = true;
             self.sender.send(buffer).unwrap();
 
             Ok(buf.len())
@@ -442,6 +663,20 @@ mod tests {
 
             assert!(sent_message.contains("Host: 127.0.0.1:0"));
         }
+
+        #[test]
+        fn positive_body() {
+            let mut message = SSDPMessage::new(MessageType::Notify);
+            message.set_body("<e:propertyset/>");
+            let mut connector = MockConnector::new();
+
+            message.send(&mut connector, ("127.0.0.1", 0)).unwrap();
+
+            let sent_message = String::from_utf8(join_buffers(&*connector.receivers.borrow())).unwrap();
+
+            assert!(sent_message.contains("Content-Length: 16"));
+            assert!(sent_message.ends_with("\r\n\r\n<e:propertyset/>"));
+        }
     }
 
     mod parse {
@@ -464,6 +699,23 @@ mod tests {
             assert_eq!(&message.get_raw("Host").unwrap()[0][..], &b"192.168.1.1"[..]);
         }
 
+        #[test]
+        fn positive_body() {
+            let raw_message = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.246:7900\r\nCONTENT-LENGTH: 4\r\n\r\n\
+                               bodyextra";
+            let message = SSDPMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
+
+            assert_eq!(message.body(), &b"body"[..]);
+        }
+
+        #[test]
+        fn positive_display_round_trip() {
+            let raw_message = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nST: ssdp:all\r\n\r\n";
+            let message = SSDPMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
+
+            assert_eq!(message.to_string(), raw_message);
+        }
+
         #[test]
         #[should_panic]
         fn negative_http_version() {
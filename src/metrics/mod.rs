@@ -0,0 +1,416 @@
+//! Metrics on discovery and announcement activity.
+//!
+//! Messages sent to the multicast group, messages received by an `SSDPReceiver`,
+//! including those received by listeners, and changes to a `DeviceCache` are
+//! counted in a registry shared by the whole process. `Metrics::snapshot`
+//! returns their current values, which an `Exporter`, such as the
+//! `PrometheusExporter`, renders for a monitoring system.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use {SSDPErrorKind, SSDPResult};
+use header::HeaderRef;
+use message::MessageType;
+
+mod prometheus;
+
+pub use self::prometheus::{PrometheusExporter, CONTENT_TYPE as PROMETHEUS_CONTENT_TYPE};
+
+/// Upper bounds, in seconds, of the buckets search response latencies are counted in.
+const LATENCY_BUCKETS: [f64; 9] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+/// Number of distinct values counted for a label, such as the search target,
+/// before the rest are counted together as `other`. Values come from the
+/// network, and are capped so that a misbehaving device can not grow the
+/// registry without bound.
+const MAX_LABEL_VALUES: usize = 64;
+const OTHER_LABEL_VALUE: &'static str = "other";
+/// Seconds a search is remembered for, after which responses to it are no
+/// longer timed.
+const SEARCH_WINDOW_SECS: u64 = 300;
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+/// Enumerates the kinds of metrics.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// Enumerates the values of metrics.
+#[derive(Clone, PartialEq, Debug)]
+pub enum MetricValue {
+    Counter(u64),
+    Gauge(f64),
+    Histogram(Histogram),
+}
+
+/// Distribution of observed values.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Histogram {
+    /// Upper bound of each bucket along with the number of values less than or
+    /// equal to it.
+    pub buckets: Vec<(f64, u64)>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+/// A metric along with the labels telling it apart from others in its family.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Metric {
+    pub labels: Vec<(&'static str, String)>,
+    pub value: MetricValue,
+}
+
+/// Metrics of the same name and kind, told apart by their labels.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MetricFamily {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub metrics: Vec<Metric>,
+}
+
+/// Trait for rendering metrics for a monitoring system.
+pub trait Exporter {
+    /// Export the current values of the metric families.
+    fn export(&mut self, families: &[MetricFamily]) -> SSDPResult<()>;
+}
+
+/// Metrics of the process, counted with the `metrics` feature.
+///
+/// Covers the searches and notify messages sent to the multicast group, the
+/// search responses and notify messages received by receivers and listeners,
+/// the datagrams that did not parse, the size of the device cache and the
+/// advertisements expiring in it, and the time taken for search responses.
+pub struct Metrics;
+
+impl Metrics {
+    /// Current values of the metrics.
+    pub fn snapshot() -> Vec<MetricFamily> {
+        REGISTRY.lock().unwrap().snapshot()
+    }
+
+    /// Export the current values of the metrics.
+    pub fn export<E: Exporter>(exporter: &mut E) -> SSDPResult<()> {
+        exporter.export(&Metrics::snapshot())
+    }
+}
+
+/// Count a message of the type sent to the multicast group through the
+/// sockets with the local addresses.
+///
+/// Searches are remembered per socket so that the responses received on the
+/// socket can be timed from the latest search sent through it.
+pub fn message_sent<T: HeaderRef>(message_type: MessageType, message: &T, locals: &[SocketAddr]) {
+    let mut registry = REGISTRY.lock().unwrap();
+
+    match message_type {
+        MessageType::Search => {
+            increment(&mut registry.searches_sent, target_label(&raw_header(message, "ST")));
+
+            let now = Instant::now();
+            let window = Duration::from_secs(SEARCH_WINDOW_SECS);
+            registry.searches_sent_at.retain(|_, sent| now.duration_since(*sent) < window);
+            for local in locals {
+                registry.searches_sent_at.insert(*local, now);
+            }
+        }
+        MessageType::Notify => increment(&mut registry.notifies_sent, nts_label(&raw_header(message, "NTS"))),
+        MessageType::Response => (),
+    }
+}
+
+/// Count a message, already parsed, received on the socket with the local address.
+///
+/// The latency of search responses is only recorded when a search was sent
+/// through the same socket, which is never the case for listeners.
+pub fn message_received<T: HeaderRef>(message_type: MessageType, message: &T, local: SocketAddr) {
+    let mut registry = REGISTRY.lock().unwrap();
+
+    match message_type {
+        MessageType::Response => {
+            increment(&mut registry.responses_received, target_label(&raw_header(message, "ST")));
+
+            if let Some(sent) = registry.searches_sent_at.get(&local).cloned() {
+                registry.response_latency.observe(sent.elapsed().as_secs_f64());
+            }
+        }
+        MessageType::Notify => {
+            increment(&mut registry.notifies_received, nts_label(&raw_header(message, "NTS")))
+        }
+        MessageType::Search => (),
+    }
+}
+
+/// Count a datagram that did not parse as a message.
+pub fn parse_error(kind: &SSDPErrorKind) {
+    increment(&mut REGISTRY.lock().unwrap().parse_errors, kind.description().to_owned());
+}
+
+/// Record the size of a device cache after a change, along with the number
+/// of advertisements that expired in it.
+pub fn cache_changed(devices: usize, expired: usize) {
+    let mut registry = REGISTRY.lock().unwrap();
+
+    registry.cache_devices = devices as u64;
+    registry.cache_expirations += expired as u64;
+}
+
+/// Counts of the activity of the process.
+struct Registry {
+    searches_sent: BTreeMap<String, u64>,
+    responses_received: BTreeMap<String, u64>,
+    notifies_sent: BTreeMap<String, u64>,
+    notifies_received: BTreeMap<String, u64>,
+    parse_errors: BTreeMap<String, u64>,
+    /// Size of the device cache as of its last change, processes usually keeping one.
+    cache_devices: u64,
+    cache_expirations: u64,
+    response_latency: Latency,
+    /// Time of the latest search sent through each socket, by local address.
+    searches_sent_at: BTreeMap<SocketAddr, Instant>,
+}
+
+impl Registry {
+    const fn new() -> Registry {
+        Registry {
+            searches_sent: BTreeMap::new(),
+            responses_received: BTreeMap::new(),
+            notifies_sent: BTreeMap::new(),
+            notifies_received: BTreeMap::new(),
+            parse_errors: BTreeMap::new(),
+            cache_devices: 0,
+            cache_expirations: 0,
+            response_latency: Latency {
+                counts: [0; 9],
+                sum: 0.0,
+                count: 0,
+            },
+            searches_sent_at: BTreeMap::new(),
+        }
+    }
+
+    fn snapshot(&self) -> Vec<MetricFamily> {
+        vec![labeled_counters("ssdp_searches_sent_total",
+                              "Search requests sent to the multicast group, by search target.",
+                              "st",
+                              &self.searches_sent),
+             labeled_counters("ssdp_search_responses_received_total",
+                              "Search responses received, by search target.",
+                              "st",
+                              &self.responses_received),
+             labeled_counters("ssdp_notifies_sent_total",
+                              "Notify messages sent to the multicast group, by notification sub type.",
+                              "nts",
+                              &self.notifies_sent),
+             labeled_counters("ssdp_notifies_received_total",
+                              "Notify messages received, by notification sub type.",
+                              "nts",
+                              &self.notifies_received),
+             labeled_counters("ssdp_parse_errors_total",
+                              "Datagrams received that did not parse as messages, by error.",
+                              "kind",
+                              &self.parse_errors),
+             MetricFamily {
+                 name: "ssdp_cache_devices",
+                 help: "Devices and services in the device cache.",
+                 kind: MetricKind::Gauge,
+                 metrics: vec![Metric {
+                                   labels: Vec::new(),
+                                   value: MetricValue::Gauge(self.cache_devices as f64),
+                               }],
+             },
+             MetricFamily {
+                 name: "ssdp_cache_expirations_total",
+                 help: "Advertisements that expired in the device cache.",
+                 kind: MetricKind::Counter,
+                 metrics: vec![Metric {
+                                   labels: Vec::new(),
+                                   value: MetricValue::Counter(self.cache_expirations),
+                               }],
+             },
+             MetricFamily {
+                 name: "ssdp_search_response_latency_seconds",
+                 help: "Time from the latest search sent through a socket to each response received on it.",
+                 kind: MetricKind::Histogram,
+                 metrics: vec![Metric {
+                                   labels: Vec::new(),
+                                   value: MetricValue::Histogram(self.response_latency.histogram()),
+                               }],
+             }]
+    }
+}
+
+/// Counts of observed latencies in each of the `LATENCY_BUCKETS`.
+struct Latency {
+    counts: [u64; 9],
+    sum: f64,
+    count: u64,
+}
+
+impl Latency {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.counts[index] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn histogram(&self) -> Histogram {
+        let mut cumulative = 0;
+
+        Histogram {
+            buckets: LATENCY_BUCKETS.iter()
+                .zip(self.counts.iter())
+                .map(|(&bound, &count)| {
+                    cumulative += count;
+                    (bound, cumulative)
+                })
+                .collect(),
+            sum: self.sum,
+            count: self.count,
+        }
+    }
+}
+
+fn labeled_counters(name: &'static str,
+                    help: &'static str,
+                    label: &'static str,
+                    counts: &BTreeMap<String, u64>)
+                    -> MetricFamily {
+    MetricFamily {
+        name: name,
+        help: help,
+        kind: MetricKind::Counter,
+        metrics: counts.iter()
+            .map(|(value, &count)| {
+                Metric {
+                    labels: vec![(label, value.clone())],
+                    value: MetricValue::Counter(count),
+                }
+            })
+            .collect(),
+    }
+}
+
+fn increment(counts: &mut BTreeMap<String, u64>, label: String) {
+    let label = if counts.len() >= MAX_LABEL_VALUES && !counts.contains_key(&label) {
+        OTHER_LABEL_VALUE.to_owned()
+    } else {
+        label
+    };
+
+    *counts.entry(label).or_insert(0) += 1;
+}
+
+/// Label for a search target, keeping the type of the device or service but
+/// counting every unique service name together.
+fn target_label(st: &str) -> String {
+    if st == "ssdp:all" || st == "upnp:rootdevice" || st.starts_with("urn:") {
+        st.to_owned()
+    } else if st.starts_with("uuid:") {
+        "uuid".to_owned()
+    } else {
+        OTHER_LABEL_VALUE.to_owned()
+    }
+}
+
+/// Label for a notification sub type, counting unknown ones together.
+fn nts_label(nts: &str) -> String {
+    match nts {
+        "ssdp:alive" | "ssdp:byebye" | "ssdp:update" => nts.to_owned(),
+        _ => OTHER_LABEL_VALUE.to_owned(),
+    }
+}
+
+/// First value of the header as it appears in the message, empty if missing.
+fn raw_header<T: HeaderRef>(message: &T, name: &str) -> String {
+    message.get_raw(name)
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+
+    use hyper::header::Headers;
+
+    use message::MessageType;
+    use super::{increment, nts_label, target_label, Latency, Metrics, MetricValue, MAX_LABEL_VALUES};
+
+    #[test]
+    fn positive_count_received() {
+        let mut headers = Headers::new();
+        headers.set_raw("ST", vec![b"urn:metrics-test:device:Counted:1".to_vec()]);
+        let local: SocketAddr = "127.0.0.1:1900".parse().unwrap();
+
+        let before = Metrics::snapshot();
+        super::message_received(MessageType::Response, &headers, local);
+        let after = Metrics::snapshot();
+
+        let count = |families: &[super::MetricFamily]| {
+            families.iter()
+                .find(|family| family.name == "ssdp_search_responses_received_total")
+                .and_then(|family| {
+                    family.metrics
+                        .iter()
+                        .find(|metric| metric.labels[0].1 == "urn:metrics-test:device:Counted:1")
+                })
+                .map(|metric| metric.value.clone())
+        };
+        assert_eq!(count(&before), None);
+        assert_eq!(count(&after), Some(MetricValue::Counter(1)));
+    }
+
+    #[test]
+    fn positive_latency_buckets() {
+        let mut latency = Latency {
+            counts: [0; 9],
+            sum: 0.0,
+            count: 0,
+        };
+        latency.observe(0.02);
+        latency.observe(0.3);
+        latency.observe(10.0);
+
+        let histogram = latency.histogram();
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.buckets[0], (0.01, 0));
+        assert_eq!(histogram.buckets[1], (0.025, 1));
+        assert_eq!(histogram.buckets[8], (5.0, 2));
+    }
+
+    #[test]
+    fn positive_cap_label_values() {
+        let mut counts = BTreeMap::new();
+        for index in 0..MAX_LABEL_VALUES + 2 {
+            increment(&mut counts, index.to_string());
+        }
+        increment(&mut counts, "0".to_owned());
+
+        assert_eq!(counts.len(), MAX_LABEL_VALUES + 1);
+        assert_eq!(counts["other"], 2);
+        assert_eq!(counts["0"], 2);
+    }
+
+    #[test]
+    fn positive_normalize_labels() {
+        assert_eq!(target_label("ssdp:all"), "ssdp:all");
+        assert_eq!(target_label("upnp:rootdevice"), "upnp:rootdevice");
+        assert_eq!(target_label("uuid:2f402f80-da50-11e1-9b23-001788255acc"), "uuid");
+        assert_eq!(target_label("urn:schemas-upnp-org:device:Basic:1"),
+                   "urn:schemas-upnp-org:device:Basic:1");
+        assert_eq!(target_label("random"), "other");
+        assert_eq!(nts_label("ssdp:alive"), "ssdp:alive");
+        assert_eq!(nts_label("ssdp:random"), "other");
+    }
+}
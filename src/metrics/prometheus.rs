@@ -0,0 +1,169 @@
+//! Rendering of metrics in the Prometheus text exposition format.
+
+use std::io::Write;
+
+use SSDPResult;
+use super::{Exporter, MetricFamily, MetricKind, MetricValue};
+
+/// Content type of the rendered metrics, for serving them over HTTP.
+pub const CONTENT_TYPE: &'static str = "text/plain; version=0.0.4";
+
+/// Exporter writing metrics in the Prometheus text exposition format.
+pub struct PrometheusExporter<W> {
+    writer: W,
+}
+
+impl<W: Write> PrometheusExporter<W> {
+    /// Construct an exporter writing to the writer.
+    pub fn new(writer: W) -> PrometheusExporter<W> {
+        PrometheusExporter { writer: writer }
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Exporter for PrometheusExporter<W> {
+    fn export(&mut self, families: &[MetricFamily]) -> SSDPResult<()> {
+        for family in families {
+            let kind = match family.kind {
+                MetricKind::Counter => "counter",
+                MetricKind::Gauge => "gauge",
+                MetricKind::Histogram => "histogram",
+            };
+            try!(writeln!(self.writer, "# HELP {} {}", family.name, escape(family.help, false)));
+            try!(writeln!(self.writer, "# TYPE {} {}", family.name, kind));
+
+            for metric in &family.metrics {
+                let labels = metric.labels
+                    .iter()
+                    .map(|&(name, ref value)| format!("{}=\"{}\"", name, escape(value, true)))
+                    .collect::<Vec<_>>();
+
+                match metric.value {
+                    MetricValue::Counter(value) => {
+                        try!(writeln!(self.writer, "{}{} {}", family.name, label_set(&labels), value))
+                    }
+                    MetricValue::Gauge(value) => {
+                        try!(writeln!(self.writer, "{}{} {}", family.name, label_set(&labels), value))
+                    }
+                    MetricValue::Histogram(ref histogram) => {
+                        let buckets = histogram.buckets
+                            .iter()
+                            .map(|&(bound, count)| (bound.to_string(), count))
+                            .chain(Some(("+Inf".to_owned(), histogram.count)));
+
+                        for (bound, count) in buckets {
+                            let mut bucket_labels = labels.clone();
+                            bucket_labels.push(format!("le=\"{}\"", bound));
+
+                            try!(writeln!(self.writer,
+                                          "{}_bucket{} {}",
+                                          family.name,
+                                          label_set(&bucket_labels),
+                                          count));
+                        }
+                        try!(writeln!(self.writer,
+                                      "{}_sum{} {}",
+                                      family.name,
+                                      label_set(&labels),
+                                      histogram.sum));
+                        try!(writeln!(self.writer,
+                                      "{}_count{} {}",
+                                      family.name,
+                                      label_set(&labels),
+                                      histogram.count));
+                    }
+                }
+            }
+        }
+
+        try!(self.writer.flush());
+        Ok(())
+    }
+}
+
+fn label_set(labels: &[String]) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+/// Escape backslashes and line feeds, and double quotes in label values.
+fn escape(value: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quotes => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrometheusExporter;
+    use metrics::{Exporter, Histogram, Metric, MetricFamily, MetricKind, MetricValue};
+
+    fn render(families: &[MetricFamily]) -> String {
+        let mut exporter = PrometheusExporter::new(Vec::new());
+        exporter.export(families).unwrap();
+
+        String::from_utf8(exporter.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn positive_render_counters() {
+        let family = MetricFamily {
+            name: "ssdp_searches_sent_total",
+            help: "Search requests sent.",
+            kind: MetricKind::Counter,
+            metrics: vec![Metric {
+                              labels: vec![("st", "ssdp:all".to_owned())],
+                              value: MetricValue::Counter(3),
+                          },
+                          Metric {
+                              labels: vec![("st", "say \"hi\"".to_owned())],
+                              value: MetricValue::Counter(1),
+                          }],
+        };
+
+        assert_eq!(render(&[family]),
+                   "# HELP ssdp_searches_sent_total Search requests sent.\n\
+                    # TYPE ssdp_searches_sent_total counter\n\
+                    ssdp_searches_sent_total{st=\"ssdp:all\"} 3\n\
+                    ssdp_searches_sent_total{st=\"say \\\"hi\\\"\"} 1\n");
+    }
+
+    #[test]
+    fn positive_render_histogram() {
+        let family = MetricFamily {
+            name: "latency_seconds",
+            help: "Latency.",
+            kind: MetricKind::Histogram,
+            metrics: vec![Metric {
+                              labels: Vec::new(),
+                              value: MetricValue::Histogram(Histogram {
+                                  buckets: vec![(0.5, 1), (1.0, 2)],
+                                  sum: 1.25,
+                                  count: 3,
+                              }),
+                          }],
+        };
+
+        let rendered = render(&[family]);
+        assert!(rendered.contains("latency_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"1\"} 2\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(rendered.ends_with("latency_seconds_sum 1.25\nlatency_seconds_count 3\n"));
+    }
+}
@@ -0,0 +1,232 @@
+use std::io::Read;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use {SSDPResult, SSDPErrorKind};
+use header::{NT, ST, USN};
+use super::{encode_string, read_string, usn_string, write_packet, Advertisement, DEFAULT_SOCKET_PATH,
+            REQUEST_ALL, REQUEST_TYPE, REQUEST_USN, REQUEST_VERSION, SUBMIT_SERVICE};
+
+/// Default time to wait on a response from the daemon.
+const DEFAULT_TIMEOUT_SECS: u64 = 5;
+
+/// Client querying minissdpd for the advertisements it has cached.
+#[derive(Clone, Debug)]
+pub struct MiniSSDPDClient {
+    path: PathBuf,
+    timeout: Duration,
+}
+
+impl MiniSSDPDClient {
+    /// Construct a client for the daemon listening on the default socket path.
+    pub fn new() -> MiniSSDPDClient {
+        MiniSSDPDClient::with_path(DEFAULT_SOCKET_PATH)
+    }
+
+    /// Construct a client for the daemon listening on the socket path.
+    pub fn with_path<P: AsRef<Path>>(path: P) -> MiniSSDPDClient {
+        MiniSSDPDClient {
+            path: path.as_ref().to_owned(),
+            timeout: Duration::new(DEFAULT_TIMEOUT_SECS, 0),
+        }
+    }
+
+    /// Set the time to wait on a response from the daemon.
+    pub fn set_timeout(mut self, value: Duration) -> Self {
+        self.timeout = value;
+        self
+    }
+
+    /// Version of the daemon.
+    pub fn version(&self) -> SSDPResult<String> {
+        let mut stream = try!(self.request(REQUEST_VERSION, &[b""]));
+        let version = try!(read_string(&mut stream));
+
+        String::from_utf8(version)
+            .map_err(|_| SSDPErrorKind::InvalidPacket("version is not valid UTF-8".to_owned()).into())
+    }
+
+    /// Advertisements that would answer a search for the target.
+    ///
+    /// Types are matched by prefix, so a target without a version matches every
+    /// version of the type.
+    pub fn request_type(&self, target: &ST) -> SSDPResult<Vec<Advertisement>> {
+        match *target {
+            ST::All => self.request_all(),
+            ST::Target(ref field) => self.request_advertisements(REQUEST_TYPE, field.to_string().as_bytes()),
+        }
+    }
+
+    /// Advertisements with the unique service name.
+    pub fn request_usn(&self, usn: &USN) -> SSDPResult<Vec<Advertisement>> {
+        self.request_advertisements(REQUEST_USN, usn_string(usn).as_bytes())
+    }
+
+    /// Every advertisement known to the daemon.
+    pub fn request_all(&self) -> SSDPResult<Vec<Advertisement>> {
+        self.request_advertisements(REQUEST_ALL, b"ssdp:all")
+    }
+
+    /// Submit a service for the daemon to advertise and answer searches for.
+    pub fn submit(&self, nt: &NT, usn: &USN, server: &str, location: &str) -> SSDPResult<()> {
+        let nt = nt.0.to_string();
+        let usn = usn_string(usn);
+
+        try!(self.request(SUBMIT_SERVICE,
+                          &[nt.as_bytes(), usn.as_bytes(), server.as_bytes(), location.as_bytes()]));
+        Ok(())
+    }
+
+    fn request_advertisements(&self, request: u8, value: &[u8]) -> SSDPResult<Vec<Advertisement>> {
+        let mut stream = try!(self.request(request, &[value]));
+
+        let mut count = [0u8];
+        try!(stream.read_exact(&mut count));
+
+        let mut advertisements = Vec::with_capacity(count[0] as usize);
+        for _ in 0..count[0] {
+            let location = try!(read_string(&mut stream));
+            let nt = try!(read_string(&mut stream));
+            let usn = try!(read_string(&mut stream));
+
+            // An entry the crate cannot represent does not spoil the others
+            match Advertisement::parse(location, nt, usn) {
+                Ok(advertisement) => advertisements.push(advertisement),
+                Err(e) => debug!("Skipping advertisement from minissdpd: {}", e),
+            }
+        }
+
+        Ok(advertisements)
+    }
+
+    /// Send the request, returning the stream to read its response from.
+    fn request(&self, request: u8, strings: &[&[u8]]) -> SSDPResult<UnixStream> {
+        let mut buffer = vec![request];
+        for value in strings {
+            encode_string(value, &mut buffer);
+        }
+
+        let mut stream = try!(UnixStream::connect(&self.path));
+        try!(stream.set_read_timeout(Some(self.timeout)));
+        try!(write_packet(&mut stream, &buffer));
+
+        Ok(stream)
+    }
+}
+
+impl Default for MiniSSDPDClient {
+    fn default() -> Self {
+        MiniSSDPDClient::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    use FieldMap;
+    use header::{Location, NT, ST, USN};
+    use super::MiniSSDPDClient;
+    use super::super::encode_string;
+
+    static NEXT_SOCKET: AtomicUsize = AtomicUsize::new(0);
+
+    /// Stand-in for minissdpd answering a single request with the response,
+    /// passing on the request it received.
+    fn stand_in(response: Vec<u8>) -> (MiniSSDPDClient, Receiver<Vec<u8>>) {
+        let path: PathBuf = env::temp_dir().join(format!("ssdp-minissdpd-{}-{}.sock",
+                                                         process::id(),
+                                                         NEXT_SOCKET.fetch_add(1, Ordering::SeqCst)));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let (send, recv) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![0u8; 1024];
+            let len = stream.read(&mut request).unwrap();
+
+            request.truncate(len);
+            send.send(request).unwrap();
+            stream.write_all(&response).unwrap();
+        });
+
+        (MiniSSDPDClient::with_path(&path), recv)
+    }
+
+    fn advertisements_response() -> Vec<u8> {
+        let mut response = vec![2];
+        for &(location, nt, usn) in &[("http://192.168.1.1:5000/rootDesc.xml",
+                                       "upnp:rootdevice",
+                                       "uuid:gateway-1::upnp:rootdevice"),
+                                      ("http://192.168.1.1:5000/rootDesc.xml", "invalid", "invalid")] {
+            encode_string(location.as_bytes(), &mut response);
+            encode_string(nt.as_bytes(), &mut response);
+            encode_string(usn.as_bytes(), &mut response);
+        }
+
+        response
+    }
+
+    #[test]
+    fn positive_request_type() {
+        let (client, requests) = stand_in(advertisements_response());
+        let advertisements = client.request_type(&ST::Target(FieldMap::upnp("rootdevice"))).unwrap();
+
+        assert_eq!(requests.recv().unwrap(), b"\x01\x0fupnp:rootdevice".to_vec());
+        assert_eq!(advertisements.len(), 1);
+        assert_eq!(advertisements[0].nt, NT(FieldMap::upnp("rootdevice")));
+        assert_eq!(advertisements[0].usn,
+                   USN(FieldMap::uuid("gateway-1"), Some(FieldMap::upnp("rootdevice"))));
+        assert_eq!(advertisements[0].location,
+                   Location("http://192.168.1.1:5000/rootDesc.xml".to_owned()));
+    }
+
+    #[test]
+    fn positive_request_all_and_usn() {
+        let (client, requests) = stand_in(advertisements_response());
+        client.request_all().unwrap();
+        assert_eq!(requests.recv().unwrap(), b"\x03\x08ssdp:all".to_vec());
+
+        let (client, requests) = stand_in(vec![0]);
+        let usn = USN(FieldMap::uuid("gateway-1"), None);
+        assert!(client.request_usn(&usn).unwrap().is_empty());
+        assert_eq!(requests.recv().unwrap(), b"\x02\x0euuid:gateway-1".to_vec());
+    }
+
+    #[test]
+    fn positive_version_and_submit() {
+        let (client, _requests) = stand_in(b"\x031.5".to_vec());
+        assert_eq!(client.version().unwrap(), "1.5");
+
+        let (client, requests) = stand_in(Vec::new());
+        client.submit(&NT(FieldMap::upnp("rootdevice")),
+                    &USN(FieldMap::uuid("device-1"), Some(FieldMap::upnp("rootdevice"))),
+                    "Linux/4.0 UPnP/1.1 ssdp/0.7",
+                    "http://192.168.1.2/description.xml")
+            .unwrap();
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request[0], 4);
+        assert!(request.ends_with(b"\x22http://192.168.1.2/description.xml"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_truncated_response() {
+        let mut response = advertisements_response();
+        response.truncate(20);
+        let (client, _requests) = stand_in(response);
+
+        client.request_all().unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+//! Support for the Unix domain socket protocol of minissdpd.
+//!
+//! minissdpd is a daemon found on many Linux systems that owns the SSDP port,
+//! keeps a cache of the devices and services advertised on the network, and
+//! advertises services submitted to it. Querying it avoids binding another
+//! socket to the SSDP port and waiting on searches.
+//!
+//! Every request is a single byte naming the request, followed by strings that
+//! are each prefixed with their length. Lengths are big endian groups of seven
+//! bits, where every group but the last has its high bit set.
+
+use std::io::{self, Read, Write};
+
+use hyper::header::{Header, Location};
+
+use {FieldMap, SSDPResult, SSDPErrorKind};
+use header::{NT, USN};
+
+mod client;
+mod server;
+
+pub use self::client::MiniSSDPDClient;
+pub use self::server::{MiniSSDPDServer, Submission};
+
+/// Path of the socket minissdpd listens on by default.
+pub const DEFAULT_SOCKET_PATH: &'static str = "/var/run/minissdpd.sock";
+
+/// Request for the version of the daemon.
+const REQUEST_VERSION: u8 = 0;
+/// Request for the advertisements whose type starts with the string.
+const REQUEST_TYPE: u8 = 1;
+/// Request for the advertisements with the unique service name.
+const REQUEST_USN: u8 = 2;
+/// Request for every advertisement.
+const REQUEST_ALL: u8 = 3;
+/// Submission of a service for the daemon to advertise.
+const SUBMIT_SERVICE: u8 = 4;
+
+/// Longest string accepted from the other end of the socket.
+const MAX_STRING_LEN: usize = 4096;
+
+/// A device or service advertised on the network, as known to minissdpd.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Advertisement {
+    pub nt: NT,
+    pub usn: USN,
+    pub location: Location,
+}
+
+impl Advertisement {
+    /// Parse the strings of an advertisement sent by minissdpd.
+    fn parse(location: Vec<u8>, nt: Vec<u8>, usn: Vec<u8>) -> SSDPResult<Advertisement> {
+        let nt = match FieldMap::parse_bytes(&nt) {
+            Some(field) => NT(field),
+            None => return Err(SSDPErrorKind::InvalidHeader(NT::header_name(), "invalid field").into()),
+        };
+        let usn = match USN::parse_header(&[usn]) {
+            Ok(usn) => usn,
+            Err(_) => return Err(SSDPErrorKind::InvalidHeader(USN::header_name(), "invalid field").into()),
+        };
+        let location = try!(String::from_utf8(location).map_err(|_| {
+            SSDPErrorKind::InvalidHeader(Location::header_name(), "not valid UTF-8")
+        }));
+
+        Ok(Advertisement {
+            nt: nt,
+            usn: usn,
+            location: Location(location),
+        })
+    }
+}
+
+/// Render the unique service name as it appears in a message.
+fn usn_string(usn: &USN) -> String {
+    match *usn {
+        USN(ref uuid, Some(ref kind)) => format!("{}::{}", uuid, kind),
+        USN(ref uuid, None) => uuid.to_string(),
+    }
+}
+
+fn encode_length(len: usize, buffer: &mut Vec<u8>) {
+    let mut shift = 28;
+
+    while shift > 0 {
+        if len >= 1 << shift {
+            buffer.push(((len >> shift) & 0x7f) as u8 | 0x80);
+        }
+        shift -= 7;
+    }
+    buffer.push((len & 0x7f) as u8);
+}
+
+fn decode_length<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut len = 0usize;
+
+    loop {
+        let mut byte = [0u8];
+        try!(reader.read_exact(&mut byte));
+
+        len = (len << 7) | (byte[0] & 0x7f) as usize;
+        if byte[0] & 0x80 == 0 {
+            return Ok(len);
+        }
+    }
+}
+
+/// Append the string, prefixed with its length, to the buffer.
+fn encode_string(value: &[u8], buffer: &mut Vec<u8>) {
+    encode_length(value.len(), buffer);
+    buffer.extend_from_slice(value);
+}
+
+/// Read a string prefixed with its length.
+fn read_string<R: Read>(reader: &mut R) -> SSDPResult<Vec<u8>> {
+    let len = try!(decode_length(reader));
+    if len > MAX_STRING_LEN {
+        return Err(SSDPErrorKind::InvalidPacket(format!("string of {} bytes is too long", len)).into());
+    }
+
+    let mut value = vec![0u8; len];
+    try!(reader.read_exact(&mut value));
+
+    Ok(value)
+}
+
+/// Write the buffer in a single call, the daemon reads each request at once.
+fn write_packet<W: Write>(writer: &mut W, buffer: &[u8]) -> io::Result<()> {
+    try!(writer.write_all(buffer));
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_length, encode_length};
+
+    #[test]
+    fn positive_length_round_trip() {
+        for &len in &[0, 1, 127, 128, 300, 16383, 16384, 2097152, 268435456] {
+            let mut buffer = Vec::new();
+            encode_length(len, &mut buffer);
+
+            assert_eq!(decode_length(&mut &buffer[..]).unwrap(), len);
+        }
+    }
+
+    #[test]
+    fn positive_length_encoding() {
+        let mut buffer = Vec::new();
+        encode_length(300, &mut buffer);
+
+        assert_eq!(buffer, vec![0x82, 0x2c]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_truncated_length() {
+        decode_length(&mut &[0x82u8][..]).unwrap();
+    }
+}
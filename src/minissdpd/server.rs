@@ -0,0 +1,435 @@
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use {SSDPResult, SSDPErrorKind};
+use cache::DeviceCache;
+use header::{CacheControl, CacheDirective, HeaderMut, Location, NT, NTS, ST, Server, USN};
+use message::{NotifyMessage, SearchResponse};
+use super::{encode_string, read_string, usn_string, write_packet, Advertisement, REQUEST_ALL, REQUEST_TYPE,
+            REQUEST_USN, REQUEST_VERSION, SUBMIT_SERVICE};
+
+/// Time a client may take to send a request before the connection is closed.
+const CONNECTION_TIMEOUT_SECS: u64 = 5;
+/// Largest number of advertisements a single response can carry.
+const MAX_RESPONSE_COUNT: usize = 255;
+
+/// A service submitted to a `MiniSSDPDServer` by a local process, to be
+/// advertised on its behalf.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Submission {
+    pub nt: NT,
+    pub usn: USN,
+    pub server: String,
+    pub location: Location,
+}
+
+impl Submission {
+    /// Whether the service should answer a search for the target.
+    pub fn matches(&self, target: &ST) -> bool {
+        match *target {
+            ST::All => true,
+            ST::Target(ref field) => self.nt.0 == *field,
+        }
+    }
+
+    /// Notify message announcing the service, valid for the max age in seconds.
+    pub fn notify(&self, nts: NTS, max_age: u32) -> NotifyMessage {
+        let mut message = NotifyMessage::new();
+
+        message.set(self.nt.clone());
+        message.set(self.usn.clone());
+        if nts != NTS::ByeBye {
+            message.set(CacheControl(vec![CacheDirective::MaxAge(max_age)]));
+            message.set(self.location.clone());
+            message.set(Server(self.server.clone()));
+        }
+        message.set(nts);
+
+        message
+    }
+
+    /// Response to a search for the service, valid for the max age in seconds.
+    pub fn search_response(&self, max_age: u32) -> SearchResponse {
+        let mut response = SearchResponse::new();
+
+        response.set(CacheControl(vec![CacheDirective::MaxAge(max_age)]));
+        response.set_raw("EXT", vec![Vec::new()]);
+        response.set(ST::Target(self.nt.0.clone()));
+        response.set(self.usn.clone());
+        response.set(self.location.clone());
+        response.set(Server(self.server.clone()));
+
+        response
+    }
+
+    fn advertisement(&self) -> Advertisement {
+        Advertisement {
+            nt: self.nt.clone(),
+            usn: self.usn.clone(),
+            location: self.location.clone(),
+        }
+    }
+}
+
+/// Server for the minissdpd protocol, standing in for the daemon.
+///
+/// Queries are answered from a `DeviceCache` kept up to date by the owner of the
+/// server, along with the services submitted to the server. Services submitted
+/// are passed on to the owner so that it can announce them. The socket is
+/// removed once the server is dropped.
+pub struct MiniSSDPDServer {
+    path: PathBuf,
+    services: Arc<Mutex<Vec<Submission>>>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MiniSSDPDServer {
+    /// Bind to the socket path, replacing any socket left behind at it.
+    ///
+    /// Fails if a server is still listening on the socket, or if something
+    /// other than a socket is at the path.
+    ///
+    /// The function is called with every service that is submitted for the
+    /// first time or whose advertisement changed.
+    pub fn bind<P, C, F>(path: P, cache: Arc<Mutex<C>>, on_submit: F) -> SSDPResult<MiniSSDPDServer>
+        where P: AsRef<Path>,
+              C: AsRef<DeviceCache> + Send + 'static,
+              F: Fn(&Submission) + Send + Sync + 'static
+    {
+        let path = path.as_ref().to_owned();
+        try!(remove_stale_socket(&path));
+
+        let listener = try!(UnixListener::bind(&path));
+        let services = Arc::new(Mutex::new(Vec::new()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handler = Arc::new(Handler {
+            cache: cache,
+            services: services.clone(),
+            on_submit: on_submit,
+        });
+        let stopped = shutdown.clone();
+        let thread = try!(thread::Builder::new()
+            .name(format!("minissdpd server {}", path.display()))
+            .spawn(move || serve(listener, handler, stopped)));
+
+        Ok(MiniSSDPDServer {
+            path: path,
+            services: services,
+            shutdown: shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Path of the socket the server is listening on.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Services submitted to the server so far.
+    pub fn services(&self) -> Vec<Submission> {
+        self.services.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MiniSSDPDServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        // Wake the accept loop so it notices the shutdown
+        if let (Ok(_), Some(thread)) = (UnixStream::connect(&self.path), self.thread.take()) {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+struct Handler<C, F> {
+    cache: Arc<Mutex<C>>,
+    services: Arc<Mutex<Vec<Submission>>>,
+    on_submit: F,
+}
+
+impl<C, F> Handler<C, F>
+    where C: AsRef<DeviceCache>,
+          F: Fn(&Submission)
+{
+    /// Serve the requests made on the connection until it is closed.
+    fn handle_connection(&self, mut stream: UnixStream) -> SSDPResult<()> {
+        try!(stream.set_read_timeout(Some(Duration::new(CONNECTION_TIMEOUT_SECS, 0))));
+
+        loop {
+            let mut request = [0u8];
+            match stream.read(&mut request) {
+                Ok(0) => return Ok(()),
+                Ok(_) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+
+            let value = try!(read_string(&mut stream));
+            let response = match request[0] {
+                REQUEST_VERSION => {
+                    let mut buffer = Vec::new();
+                    encode_string(env!("CARGO_PKG_VERSION").as_bytes(), &mut buffer);
+                    buffer
+                }
+                REQUEST_TYPE | REQUEST_USN | REQUEST_ALL => self.answer(request[0], &value),
+                SUBMIT_SERVICE => {
+                    let usn = try!(read_string(&mut stream));
+                    let server = try!(read_string(&mut stream));
+                    let location = try!(read_string(&mut stream));
+
+                    try!(self.submit(value, usn, server, location));
+                    continue;
+                }
+                other => {
+                    return Err(SSDPErrorKind::InvalidPacket(format!("unknown request type {}", other)).into())
+                }
+            };
+
+            try!(write_packet(&mut stream, &response));
+        }
+    }
+
+    /// Encode the advertisements matching the query.
+    fn answer(&self, request: u8, value: &[u8]) -> Vec<u8> {
+        let mut advertisements: Vec<Advertisement> = {
+            let cache = self.cache.lock().unwrap();
+
+            cache.as_ref()
+                .devices()
+                .into_iter()
+                .filter_map(|device| {
+                    let nt = NT(device.usn.1.clone().unwrap_or_else(|| device.usn.0.clone()));
+
+                    device.location.as_ref().map(|location| {
+                        Advertisement {
+                            nt: nt,
+                            usn: device.usn.clone(),
+                            location: Location(location.clone()),
+                        }
+                    })
+                })
+                .collect()
+        };
+        advertisements.extend(self.services.lock().unwrap().iter().map(Submission::advertisement));
+
+        let mut buffer = vec![0];
+        let mut count = 0;
+        for advertisement in advertisements {
+            let nt = advertisement.nt.0.to_string();
+            let usn = usn_string(&advertisement.usn);
+
+            // Types are compared by prefix, as with the original daemon
+            let matches = match request {
+                REQUEST_TYPE => nt.as_bytes().starts_with(value),
+                REQUEST_USN => usn.as_bytes() == value,
+                _ => true,
+            };
+            if !matches || count == MAX_RESPONSE_COUNT {
+                continue;
+            }
+
+            encode_string(advertisement.location.as_bytes(), &mut buffer);
+            encode_string(nt.as_bytes(), &mut buffer);
+            encode_string(usn.as_bytes(), &mut buffer);
+            count += 1;
+        }
+        buffer[0] = count as u8;
+
+        buffer
+    }
+
+    fn submit(&self, nt: Vec<u8>, usn: Vec<u8>, server: Vec<u8>, location: Vec<u8>) -> SSDPResult<()> {
+        let advertisement = try!(Advertisement::parse(location, nt, usn));
+        let server = try!(String::from_utf8(server)
+            .map_err(|_| SSDPErrorKind::InvalidPacket("server is not valid UTF-8".to_owned())));
+        let submission = Submission {
+            nt: advertisement.nt,
+            usn: advertisement.usn,
+            server: server,
+            location: advertisement.location,
+        };
+
+        {
+            let mut services = self.services.lock().unwrap();
+
+            // A service submitted again replaces the previous submission
+            match services.iter().position(|service| service.usn == submission.usn) {
+                Some(index) if services[index] == submission => return Ok(()),
+                Some(index) => services[index] = submission.clone(),
+                None => services.push(submission.clone()),
+            }
+        }
+
+        (self.on_submit)(&submission);
+        Ok(())
+    }
+}
+
+/// Remove a socket left behind at the path by a server that is gone.
+fn remove_stale_socket(path: &Path) -> SSDPResult<()> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, "a server is listening on the socket").into());
+    }
+
+    match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.file_type().is_socket() => Ok(try!(fs::remove_file(path))),
+        Ok(_) => Err(io::Error::new(io::ErrorKind::AlreadyExists, "path exists and is not a socket").into()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn serve<C, F>(listener: UnixListener, handler: Arc<Handler<C, F>>, shutdown: Arc<AtomicBool>)
+    where C: AsRef<DeviceCache> + Send + 'static,
+          F: Fn(&Submission) + Send + Sync + 'static
+{
+    for stream in listener.incoming() {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+
+        match stream {
+            Ok(stream) => {
+                let handler = handler.clone();
+
+                thread::spawn(move || if let Err(e) = handler.handle_connection(stream) {
+                    debug!("Error handling minissdpd connection: {}", e);
+                });
+            }
+            Err(e) => debug!("Error accepting minissdpd connection: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+
+    use FieldMap;
+    use cache::DeviceCache;
+    use header::{HeaderMut, HeaderRef, Location, NT, NTS, ST, USN};
+    use message::NotifyMessage;
+    use super::{MiniSSDPDServer, Submission};
+    use super::super::MiniSSDPDClient;
+
+    static NEXT_SOCKET: AtomicUsize = AtomicUsize::new(0);
+
+    fn socket_path() -> PathBuf {
+        env::temp_dir().join(format!("ssdp-minissdpd-server-{}-{}.sock",
+                                     process::id(),
+                                     NEXT_SOCKET.fetch_add(1, Ordering::SeqCst)))
+    }
+
+    fn gateway_cache() -> Arc<Mutex<DeviceCache>> {
+        let mut message = NotifyMessage::new();
+        message.set(NTS::Alive);
+        message.set(NT(FieldMap::URN("schemas-upnp-org:device:InternetGatewayDevice:1".to_owned())));
+        message.set(USN(FieldMap::uuid("gateway-1"),
+                        Some(FieldMap::URN("schemas-upnp-org:device:InternetGatewayDevice:1".to_owned()))));
+        message.set(Location("http://192.168.1.1:5000/rootDesc.xml".to_owned()));
+
+        let mut cache = DeviceCache::new();
+        cache.observe(&message, "192.168.1.1:1900".parse().unwrap());
+
+        Arc::new(Mutex::new(cache))
+    }
+
+    #[test]
+    fn positive_answer_from_cache() {
+        let path = socket_path();
+        let _server = MiniSSDPDServer::bind(&path, gateway_cache(), |_: &Submission| ()).unwrap();
+        let client = MiniSSDPDClient::with_path(&path);
+
+        assert_eq!(client.version().unwrap(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(client.request_all().unwrap().len(), 1);
+
+        let target = ST::Target(FieldMap::URN("schemas-upnp-org:device:InternetGatewayDevice".to_owned()));
+        let advertisements = client.request_type(&target).unwrap();
+        assert_eq!(advertisements.len(), 1);
+        assert_eq!(advertisements[0].location,
+                   Location("http://192.168.1.1:5000/rootDesc.xml".to_owned()));
+
+        assert!(client.request_usn(&USN(FieldMap::uuid("gateway-2"), None)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn positive_submit_service() {
+        let path = socket_path();
+        let (send, submitted) = mpsc::channel();
+        let server = MiniSSDPDServer::bind(&path, gateway_cache(), move |submission: &Submission| {
+                send.send(submission.clone()).unwrap();
+            })
+            .unwrap();
+        let client = MiniSSDPDClient::with_path(&path);
+
+        let nt = NT(FieldMap::upnp("rootdevice"));
+        let usn = USN(FieldMap::uuid("device-1"), Some(FieldMap::upnp("rootdevice")));
+        client.submit(&nt, &usn, "Linux/4.0 UPnP/1.1 test/1.0", "http://192.168.1.2/description.xml")
+            .unwrap();
+
+        let submission = submitted.recv().unwrap();
+        assert_eq!(submission.usn, usn);
+        assert_eq!(server.services(), vec![submission.clone()]);
+        assert!(submission.matches(&ST::Target(FieldMap::upnp("rootdevice"))));
+
+        let response = submission.search_response(1800);
+        assert_eq!(response.get::<USN>(), Some(&usn));
+        assert_eq!(submission.notify(NTS::ByeBye, 1800).get::<Location>(), None);
+
+        let advertisements = client.request_usn(&usn).unwrap();
+        assert_eq!(advertisements.len(), 1);
+        assert_eq!(advertisements[0].nt, nt);
+    }
+
+    #[test]
+    fn positive_keep_other_files() {
+        let path = socket_path();
+        fs::write(&path, b"not a socket").unwrap();
+
+        assert!(MiniSSDPDServer::bind(&path, gateway_cache(), |_: &Submission| ()).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"not a socket");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_bind_running_server() {
+        let path = socket_path();
+        let _server = MiniSSDPDServer::bind(&path, gateway_cache(), |_: &Submission| ()).unwrap();
+
+        MiniSSDPDServer::bind(&path, gateway_cache(), |_: &Submission| ()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_unknown_request() {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+
+        let path = socket_path();
+        let _server = MiniSSDPDServer::bind(&path, gateway_cache(), |_: &Submission| ()).unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"\x09\x00").unwrap();
+
+        let mut response = [0u8];
+        stream.read_exact(&mut response).unwrap();
+    }
+}
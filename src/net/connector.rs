@@ -29,10 +29,20 @@ impl UdpConnector {
         Ok(UdpConnector(udp))
     }
 
+    /// Create a new UdpConnector that sends through a socket opened by the caller.
+    pub fn from_socket(udp: UdpSocket) -> UdpConnector {
+        UdpConnector(udp)
+    }
+
     pub fn local_addr(&self) -> io::Result<SocketAddr> {
         self.0.local_addr()
     }
 
+    /// Create a new UdpConnector that shares the underlying UdpSocket with this one.
+    pub fn try_clone(&self) -> io::Result<UdpConnector> {
+        self.0.try_clone().map(UdpConnector)
+    }
+
     /// Destroy the UdpConnector and return the underlying UdpSocket.
     pub fn deconstruct(self) -> UdpSocket {
         self.0
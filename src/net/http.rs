@@ -0,0 +1,319 @@
+//! Implements a minimal HTTP server for the TCP side of the `UPnP` standard.
+//!
+//! Control points receive event notifications and devices serve their
+//! documents, control and event subscription urls through this server. Each
+//! connection carries a single request and is handled on its own thread, and
+//! connections past `MAX_CONNECTIONS` are answered with `503 Service Unavailable`.
+
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Read, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use hyper::buffer::BufReader;
+use hyper::header::{Connection, ContentLength, Headers};
+use hyper::method::Method;
+use hyper::net::{HttpStream, NetworkStream};
+use hyper::server::{Request, Response};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+
+/// Time to wait on a peer while reading its request or writing our response.
+const CONNECTION_TIMEOUT_SECS: u64 = 10;
+/// Largest request body read, well past any event notification or action invocation.
+const MAX_BODY_LEN: u64 = 1024 * 1024;
+/// Most connections handled at once, each holding a thread.
+const MAX_CONNECTIONS: usize = 64;
+
+/// A request received by an `HttpServer`.
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub path: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+/// A response for an `HttpServer` to send back.
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    after_sent: Option<Box<FnOnce() + Send>>,
+}
+
+impl HttpResponse {
+    pub fn with_status<B: Into<Vec<u8>>>(status: StatusCode, body: B) -> HttpResponse {
+        HttpResponse {
+            status: status,
+            headers: Headers::new(),
+            body: body.into(),
+            after_sent: None,
+        }
+    }
+
+    pub fn empty(status: StatusCode) -> HttpResponse {
+        HttpResponse::with_status(status, Vec::new())
+    }
+
+    /// Set a function to run once the response was written to the connection,
+    /// such as sending the initial event of a subscription.
+    #[cfg(feature = "device")]
+    pub fn set_after_sent<F>(mut self, after_sent: F) -> Self
+        where F: FnOnce() + Send + 'static
+    {
+        self.after_sent = Some(Box::new(after_sent));
+        self
+    }
+}
+
+impl Debug for HttpResponse {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+/// HTTP server passing every request to a handler, stopped when dropped.
+pub struct HttpServer {
+    local_addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HttpServer {
+    /// Bind to the address and serve requests by passing them to the handler.
+    pub fn bind<A, F>(addr: A, handler: F) -> io::Result<HttpServer>
+        where A: ToSocketAddrs,
+              F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+    {
+        let listener = try!(TcpListener::bind(addr));
+        let local_addr = try!(listener.local_addr());
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handler = Arc::new(handler);
+        let stopped = shutdown.clone();
+        let thread = try!(thread::Builder::new()
+            .name(format!("http server {}", local_addr))
+            .spawn(move || serve(listener, handler, stopped)));
+
+        Ok(HttpServer {
+            local_addr: local_addr,
+            shutdown: shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        // Wake the accept loop so it notices the shutdown
+        let ip = match self.local_addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)),
+            ip => ip,
+        };
+        let wake = TcpStream::connect_timeout(&SocketAddr::new(ip, self.local_addr.port()),
+                                              Duration::new(CONNECTION_TIMEOUT_SECS, 0));
+
+        if let (Ok(_), Some(thread)) = (wake, self.thread.take()) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Decrements the count of open connections when the handler is done.
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn serve<F>(listener: TcpListener, handler: Arc<F>, shutdown: Arc<AtomicBool>)
+    where F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+{
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+
+        match stream {
+            Ok(stream) => {
+                let guard = ConnectionGuard(connections.clone());
+                if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    debug!("Rejecting http connection, too many are open");
+                    if let Err(e) = reject_connection(stream) {
+                        debug!("Error rejecting http connection: {}", e);
+                    }
+                    continue;
+                }
+
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    let _guard = guard;
+                    if let Err(e) = handle_connection(stream, &*handler) {
+                        debug!("Error handling http connection: {}", e);
+                    }
+                });
+            }
+            Err(e) => debug!("Error accepting http connection: {}", e),
+        }
+    }
+}
+
+fn reject_connection(stream: TcpStream) -> io::Result<()> {
+    try!(stream.set_write_timeout(Some(Duration::new(CONNECTION_TIMEOUT_SECS, 0))));
+
+    send_response(stream, HttpResponse::empty(StatusCode::ServiceUnavailable))
+}
+
+fn handle_connection<F>(stream: TcpStream, handler: &F) -> io::Result<()>
+    where F: Fn(HttpRequest) -> HttpResponse
+{
+    try!(stream.set_read_timeout(Some(Duration::new(CONNECTION_TIMEOUT_SECS, 0))));
+    try!(stream.set_write_timeout(Some(Duration::new(CONNECTION_TIMEOUT_SECS, 0))));
+
+    let remote_addr = try!(stream.peer_addr());
+
+    let mut reader_stream = HttpStream(try!(stream.try_clone()));
+    let request = {
+        let mut reader = BufReader::new(&mut reader_stream as &mut NetworkStream);
+        let mut request = try!(Request::new(&mut reader, remote_addr)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string())));
+
+        // Bodies are read in full, so their length is capped whether given or not
+        let mut body = Vec::new();
+        let too_long = match request.headers.get::<ContentLength>() {
+            Some(&ContentLength(len)) if len > MAX_BODY_LEN => true,
+            _ => try!(request.by_ref().take(MAX_BODY_LEN + 1).read_to_end(&mut body)) as u64 > MAX_BODY_LEN,
+        };
+
+        let path = match request.uri {
+            RequestUri::AbsolutePath(ref path) => path.clone(),
+            ref other => other.to_string(),
+        };
+        if too_long {
+            None
+        } else {
+            Some(HttpRequest {
+                method: request.method.clone(),
+                path: path,
+                headers: request.headers.clone(),
+                body: body,
+            })
+        }
+    };
+
+    let response = match request {
+        Some(request) => handler(request),
+        None => HttpResponse::empty(StatusCode::PayloadTooLarge),
+    };
+
+    send_response(stream, response)
+}
+
+fn send_response(stream: TcpStream, response: HttpResponse) -> io::Result<()> {
+    let mut headers = response.headers;
+    headers.set(Connection::close());
+
+    let mut writer = stream;
+    {
+        let mut http_response = Response::new(&mut writer, &mut headers);
+        *http_response.status_mut() = response.status;
+
+        try!(http_response.send(&response.body[..]));
+    }
+
+    if let Some(after_sent) = response.after_sent {
+        after_sent();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use hyper::Client;
+    use hyper::method::Method;
+    use hyper::status::StatusCode;
+
+    use super::{HttpResponse, HttpServer, MAX_CONNECTIONS};
+
+    #[test]
+    fn positive_serve_request() {
+        let server = HttpServer::bind("127.0.0.1:0", |request| {
+                assert_eq!(request.method, Method::Extension("NOTIFY".to_owned()));
+                HttpResponse::with_status(StatusCode::Ok, request.path)
+            })
+            .unwrap();
+
+        let mut response = Client::new()
+            .request(Method::Extension("NOTIFY".to_owned()),
+                     &format!("http://{}/event/1", server.local_addr())[..])
+            .send()
+            .unwrap();
+        let mut body = String::new();
+        response.read_to_string(&mut body).unwrap();
+
+        assert_eq!(response.status, StatusCode::Ok);
+        assert_eq!(body, "/event/1");
+    }
+
+    #[test]
+    fn positive_reject_large_body() {
+        let server = HttpServer::bind("127.0.0.1:0", |_| HttpResponse::empty(StatusCode::Ok)).unwrap();
+
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.write_all(b"NOTIFY /event/1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1073741824\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 413 "));
+    }
+
+    #[test]
+    fn positive_reject_past_max_connections() {
+        let server = HttpServer::bind("127.0.0.1:0", |_| HttpResponse::empty(StatusCode::Ok)).unwrap();
+
+        // Idle connections hold their handlers until they send a request
+        let _idle: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(server.local_addr()).unwrap())
+            .collect();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 503 "));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_stopped_on_drop() {
+        let server = HttpServer::bind("127.0.0.1:0", |_| HttpResponse::empty(StatusCode::Ok)).unwrap();
+        let addr = server.local_addr();
+        drop(server);
+
+        Client::new().get(&format!("http://{}/", addr)[..]).send().unwrap();
+    }
+}
@@ -9,13 +9,16 @@ use std::net::{SocketAddr, IpAddr};
 
 #[cfg(not(windows))]
 use net2::unix::UnixUdpBuilderExt;
-use net2::UdpBuilder;
+use net2::{UdpBuilder, UdpSocketExt};
 
 pub mod connector;
+#[cfg(feature = "eventing")]
+pub mod http;
 pub mod packet;
 pub mod sender;
 
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum IpVersionMode {
     V4Only,
     V6Only,
@@ -41,6 +44,19 @@ pub fn addr_from_trait<A: ToSocketAddrs>(addr: A) -> io::Result<SocketAddr> {
     }
 }
 
+/// Address of the local interface that traffic to the remote address would be sent from.
+#[cfg(any(feature = "eventing", feature = "device"))]
+pub fn local_ip_towards(remote: SocketAddr) -> io::Result<IpAddr> {
+    let socket = match remote {
+        SocketAddr::V4(_) => try!(UdpSocket::bind("0.0.0.0:0")),
+        SocketAddr::V6(_) => try!(UdpSocket::bind("[::]:0")),
+    };
+    // Connecting a udp socket only selects a route, nothing is sent
+    try!(socket.connect(remote));
+
+    Ok(try!(socket.local_addr()).ip())
+}
+
 /// Bind to a `UdpSocket`, setting `SO_REUSEADDR` on the underlying socket before binding.
 pub fn bind_reuse<A: ToSocketAddrs>(local_addr: A) -> io::Result<UdpSocket> {
     let local_addr = try!(addr_from_trait(local_addr));
@@ -70,6 +86,16 @@ fn reuse_port(builder: &UdpBuilder) -> io::Result<()> {
     Ok(())
 }
 
+/// Returns true if the socket is an IPv6 socket that also sends and receives
+/// IPv4 traffic, as IPv4-mapped addresses.
+pub fn is_dual_stack(sock: &UdpSocket) -> io::Result<bool> {
+    if try!(sock.local_addr()).is_ipv4() {
+        return Ok(false);
+    }
+
+    Ok(!try!(UdpSocketExt::only_v6(sock)))
+}
+
 /// Join a multicast address on the current `UdpSocket`.
 pub fn join_multicast(sock: &UdpSocket, iface: &SocketAddr, mcast_addr: &IpAddr) -> io::Result<()> {
     match (iface, mcast_addr) {
@@ -97,12 +123,26 @@ pub fn leave_multicast(sock: &UdpSocket, iface_addr: &SocketAddr, mcast_addr: &S
 
 #[cfg(test)]
 mod tests {
+    use std::net::UdpSocket;
+
+    use net2::UdpBuilder;
 
     #[test]
     fn positive_addr_from_trait() {
         super::addr_from_trait("192.168.0.1:0").unwrap();
     }
 
+    #[test]
+    fn positive_dual_stack() {
+        let dual = UdpBuilder::new_v6().unwrap().only_v6(false).unwrap().bind("[::]:0").unwrap();
+        let v6 = UdpBuilder::new_v6().unwrap().only_v6(true).unwrap().bind("[::]:0").unwrap();
+        let v4 = UdpSocket::bind("0.0.0.0:0").unwrap();
+
+        assert!(super::is_dual_stack(&dual).unwrap());
+        assert!(!super::is_dual_stack(&v6).unwrap());
+        assert!(!super::is_dual_stack(&v4).unwrap());
+    }
+
     #[test]
     #[should_panic]
     fn negative_addr_from_trait() {
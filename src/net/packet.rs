@@ -14,10 +14,25 @@ pub struct PacketReceiver(UdpSocket);
 
 impl PacketReceiver {
     /// Create a new PacketReceiver from the given UdpSocket.
+    ///
+    /// Sockets bound to any address are asked to report the interface each
+    /// packet arrives on, where the platform supports it.
     pub fn new(udp: UdpSocket) -> PacketReceiver {
+        #[cfg(target_os = "linux")]
+        {
+            if let Err(err) = pktinfo::enable(&udp) {
+                debug!("Failed to enable packet info on {:?}: {}", udp.local_addr(), err);
+            }
+        }
+
         PacketReceiver(udp)
     }
 
+    /// Local address of the underlying connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
     /// Receive a packet from the underlying connection.
     pub fn recv_pckt(&self) -> io::Result<(Vec<u8>, SocketAddr)> {
         let mut pckt_buf = vec![0u8; MAX_PCKT_LEN];
@@ -34,6 +49,160 @@ impl PacketReceiver {
             Ok((pckt_buf, addr))
         }
     }
+
+    /// Receive a packet from the underlying connection, along with the local
+    /// address it arrived at.
+    ///
+    /// For sockets bound to a single interface, this is the local address of
+    /// the socket. For sockets bound to any address, it is the address of the
+    /// interface the packet arrived on, or for IPv6 packets the unspecified
+    /// address scoped to the interface, where the platform reports it.
+    #[cfg(target_os = "linux")]
+    pub fn recv_pckt_at(&self) -> io::Result<(Vec<u8>, SocketAddr, SocketAddr)> {
+        let local = try!(self.0.local_addr());
+        if !local.ip().is_unspecified() {
+            return self.recv_pckt().map(|(pckt, addr)| (pckt, addr, local));
+        }
+
+        let mut pckt_buf = vec![0u8; MAX_PCKT_LEN];
+        let (size, addr, at) = try!(pktinfo::recv_from(&self.0, &mut pckt_buf));
+        pckt_buf.truncate(size);
+
+        Ok((pckt_buf, addr, at.unwrap_or(local)))
+    }
+
+    /// Receive a packet from the underlying connection, along with the local
+    /// address of the socket it arrived at.
+    #[cfg(not(target_os = "linux"))]
+    pub fn recv_pckt_at(&self) -> io::Result<(Vec<u8>, SocketAddr, SocketAddr)> {
+        let local = try!(self.0.local_addr());
+
+        self.recv_pckt().map(|(pckt, addr)| (pckt, addr, local))
+    }
+}
+
+/// Packet information reported by Linux for sockets bound to any address.
+#[cfg(target_os = "linux")]
+mod pktinfo {
+    use std::io;
+    use std::mem;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+    use std::os::unix::io::AsRawFd;
+
+    use libc;
+
+    /// Room for one `in_pktinfo` or `in6_pktinfo` control message, with plenty to spare.
+    const CONTROL_LEN: usize = 128;
+
+    /// Ask the socket to report the interface of each packet, if it is bound to any address.
+    pub fn enable(sock: &UdpSocket) -> io::Result<()> {
+        let local = try!(sock.local_addr());
+        if !local.ip().is_unspecified() {
+            return Ok(());
+        }
+
+        if local.is_ipv6() {
+            try!(set_option(sock, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO));
+        }
+        // Also covers IPv4 packets received on dual-stack sockets
+        match set_option(sock, libc::IPPROTO_IP, libc::IP_PKTINFO) {
+            Err(_) if local.is_ipv6() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Receive a packet, along with the sender and the local address of the
+    /// interface it arrived on, if reported.
+    pub fn recv_from(sock: &UdpSocket,
+                     buf: &mut [u8])
+                     -> io::Result<(usize, SocketAddr, Option<SocketAddr>)> {
+        let port = try!(sock.local_addr()).port();
+
+        let mut src: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut control = [0u64; CONTROL_LEN / 8];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut src as *mut libc::sockaddr_storage as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = CONTROL_LEN as _;
+
+        let size = unsafe { libc::recvmsg(sock.as_raw_fd(), &mut msg, 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let src = try!(socket_addr(&src));
+        let mut at = None;
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                let data = libc::CMSG_DATA(cmsg);
+
+                match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                    (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                        let info = (data as *const libc::in_pktinfo).read_unaligned();
+                        let ip = Ipv4Addr::from(u32::from_be(info.ipi_spec_dst.s_addr));
+                        at = Some(SocketAddr::V4(SocketAddrV4::new(ip, port)));
+                    }
+                    (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                        // Multicast packets are addressed to the group, so the
+                        // interface is told apart by its index alone
+                        let info = (data as *const libc::in6_pktinfo).read_unaligned();
+                        let scope = info.ipi6_ifindex as u32;
+                        at = Some(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(0), port, 0, scope)));
+                    }
+                    _ => (),
+                }
+
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+
+        Ok((size as usize, src, at))
+    }
+
+    fn set_option(sock: &UdpSocket, level: libc::c_int, name: libc::c_int) -> io::Result<()> {
+        let value: libc::c_int = 1;
+
+        let result = unsafe {
+            libc::setsockopt(sock.as_raw_fd(),
+                             level,
+                             name,
+                             &value as *const libc::c_int as *const libc::c_void,
+                             mem::size_of::<libc::c_int>() as libc::socklen_t)
+        };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn socket_addr(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let addr = unsafe { *(storage as *const _ as *const libc::sockaddr_in) };
+                let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+
+                Ok(SocketAddr::V4(SocketAddrV4::new(ip, u16::from_be(addr.sin_port))))
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { *(storage as *const _ as *const libc::sockaddr_in6) };
+
+                Ok(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                                                    u16::from_be(addr.sin6_port),
+                                                    addr.sin6_flowinfo,
+                                                    addr.sin6_scope_id)))
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Packet From Unknown Address Family")),
+        }
+    }
 }
 
 impl fmt::Display for PacketReceiver {
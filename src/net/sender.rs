@@ -51,7 +51,8 @@ impl Write for UdpSender {
         // Hyper will generate a request with a /, we need to intercept that.
         let mut buffer = vec![0u8; buf.len()];
 
-        let mut found = false;
+        // Only the request line of a datagram holds the path
+        let mut found = !self.buf.is_empty();
         for (src, dst) in buf.iter().zip(buffer.iter_mut()) {
             if *src == b'/' && !found && buf[0] != b'H' {
                 *dst = b'*';
@@ -0,0 +1,340 @@
+//! Primitives for collapsing duplicate SSDP messages.
+//!
+//! Multicast messages are sent on every local interface and devices answer every
+//! copy they see, so a single search will usually return the same response more
+//! than once. Retransmitted searches make this worse.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::mpsc::RecvError;
+use std::time::{Duration, Instant};
+
+use hyper::header::{Header, Location};
+
+use header::{HeaderRef, USN};
+use receiver::{Received, SSDPReceiver};
+
+/// Enumerates the header fields used to decide whether two messages are duplicates.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum DedupKey {
+    /// Messages with the same `USN` are duplicates.
+    USN,
+    /// Messages with the same `USN` and `LOCATION` are duplicates.
+    USNAndLocation,
+}
+
+/// A message along with every address it was seen from.
+#[derive(Clone, Debug)]
+pub struct Deduplicated<T> {
+    message: T,
+    sources: Vec<SocketAddr>,
+    local_addrs: Vec<SocketAddr>,
+}
+
+impl<T> Deduplicated<T> {
+    fn new(received: Received<T>) -> Deduplicated<T> {
+        Deduplicated {
+            message: received.message,
+            sources: vec![received.src],
+            local_addrs: vec![received.local],
+        }
+    }
+
+    /// Record another sighting of the message, ignoring addresses already seen.
+    fn merge(&mut self, src: SocketAddr, local: SocketAddr) {
+        if !self.sources.contains(&src) {
+            self.sources.push(src);
+        }
+        if !self.local_addrs.contains(&local) {
+            self.local_addrs.push(local);
+        }
+    }
+
+    /// The first copy of the message that was received.
+    pub fn message(&self) -> &T {
+        &self.message
+    }
+
+    /// Source addresses the message was received from.
+    pub fn sources(&self) -> &[SocketAddr] {
+        &self.sources[..]
+    }
+
+    /// Local addresses the message was received at.
+    ///
+    /// For searches, every socket is bound to a single interface. Listeners
+    /// bound to the unspecified address report the interface where the
+    /// platform supports it, as described in `SSDPReceiver::interface_stats`,
+    /// and the unspecified address otherwise.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs[..]
+    }
+
+    /// Destroy the Deduplicated and return the message.
+    pub fn into_message(self) -> T {
+        self.message
+    }
+}
+
+/// Raw header values that identify a message.
+type RawKey = (Vec<u8>, Option<Vec<u8>>);
+
+/// A receiver that only yields the first copy of each message.
+///
+/// Messages without a `USN` header can not be identified and are never collapsed.
+/// The addresses later copies are seen from are merged into those of the first,
+/// and can be looked up with `sightings`.
+pub struct DedupReceiver<T> {
+    recv: SSDPReceiver<T>,
+    key: DedupKey,
+    window: Option<Duration>,
+    seen: HashMap<RawKey, (Instant, Deduplicated<()>)>,
+}
+
+impl<T> DedupReceiver<T>
+    where T: HeaderRef
+{
+    /// Construct a new DedupReceiver that collapses messages from the given receiver.
+    pub fn new(recv: SSDPReceiver<T>, key: DedupKey) -> DedupReceiver<T> {
+        DedupReceiver {
+            recv: recv,
+            key: key,
+            window: None,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Set the time after which a message that was already seen is reported again.
+    ///
+    /// By default, a message is only reported once for the lifetime of the receiver,
+    /// which has to remember every message it has seen. Listeners that see periodic
+    /// announcements will usually want to set this, so that only the messages seen
+    /// within the window are remembered.
+    pub fn set_window(mut self, value: Duration) -> Self {
+        self.window = Some(value);
+        self
+    }
+
+    /// Blocking method that reads the next message not seen within the window.
+    pub fn recv(&mut self) -> Result<Deduplicated<T>, RecvError> {
+        loop {
+            let received = try!(self.recv.recv_received());
+
+            let raw_key = match raw_key(&received.message, self.key) {
+                Some(raw_key) => raw_key,
+                None => return Ok(Deduplicated::new(received)),
+            };
+
+            let now = Instant::now();
+            let window = self.window;
+            let duplicate = self.seen.get_mut(&raw_key).and_then(|&mut (seen_at, ref mut sightings)| {
+                match window {
+                    Some(window) if now.duration_since(seen_at) >= window => None,
+                    _ => Some(sightings),
+                }
+            });
+
+            if let Some(sightings) = duplicate {
+                trace!("Dropping duplicate message from {}", received.src);
+                sightings.merge(received.src, received.local);
+            } else {
+                // Forget messages last seen outside the window, they would be reported again anyway
+                if let Some(window) = self.window {
+                    self.seen.retain(|_, &mut (seen_at, _)| now.duration_since(seen_at) < window);
+                }
+                let sightings = Deduplicated::new(Received {
+                    message: (),
+                    src: received.src,
+                    local: received.local,
+                });
+                self.seen.insert(raw_key, (now, sightings));
+
+                return Ok(Deduplicated::new(received));
+            }
+        }
+    }
+
+    /// Every address the message has been seen from since it was last reported,
+    /// including those of the duplicates dropped since.
+    ///
+    /// Returns None if the message has no `USN` header, or was not reported
+    /// within the window.
+    pub fn sightings<'a>(&self, message: &'a T) -> Option<Deduplicated<&'a T>> {
+        let raw_key = match raw_key(message, self.key) {
+            Some(raw_key) => raw_key,
+            None => return None,
+        };
+
+        self.seen.get(&raw_key).map(|&(_, ref sightings)| {
+            Deduplicated {
+                message: message,
+                sources: sightings.sources.clone(),
+                local_addrs: sightings.local_addrs.clone(),
+            }
+        })
+    }
+
+    /// Block until the underlying receiver is finished, returning each unique
+    /// message along with every address it was seen from.
+    ///
+    /// Messages are returned in the order they were first received.
+    pub fn collect_all(self) -> Vec<Deduplicated<T>> {
+        let mut unique: Vec<Deduplicated<T>> = Vec::new();
+        let mut index_of: HashMap<RawKey, usize> = HashMap::new();
+
+        while let Ok(received) = self.recv.recv_received() {
+            let raw_key = match raw_key(&received.message, self.key) {
+                Some(raw_key) => raw_key,
+                None => {
+                    unique.push(Deduplicated::new(received));
+                    continue;
+                }
+            };
+
+            if let Some(&index) = index_of.get(&raw_key) {
+                unique[index].merge(received.src, received.local);
+            } else {
+                index_of.insert(raw_key, unique.len());
+                unique.push(Deduplicated::new(received));
+            }
+        }
+
+        unique
+    }
+}
+
+impl<T> Iterator for DedupReceiver<T>
+    where T: HeaderRef
+{
+    type Item = Deduplicated<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv().ok()
+    }
+}
+
+/// Extract the raw header values identifying the message, if it has a `USN`.
+fn raw_key<T>(message: &T, key: DedupKey) -> Option<RawKey>
+    where T: HeaderRef
+{
+    let usn = match message.get_raw(USN::header_name()) {
+        Some(usn) => usn.concat(),
+        None => return None,
+    };
+
+    let location = match key {
+        DedupKey::USN => None,
+        DedupKey::USNAndLocation => message.get_raw(Location::header_name()).map(|loc| loc.concat()),
+    };
+
+    Some((usn, location))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use message::SearchResponse;
+    use receiver::{FromRawSSDP, Received, SSDPReceiver};
+    use super::DedupKey;
+
+    fn response(usn: &str, location: &str) -> SearchResponse {
+        let raw = format!("HTTP/1.1 200 OK\r\nUSN: {}\r\nLOCATION: {}\r\n\r\n", usn, location);
+
+        SearchResponse::raw_ssdp(raw.as_bytes()).unwrap()
+    }
+
+    fn receiver(messages: Vec<(SearchResponse, &str, &str)>) -> SSDPReceiver<SearchResponse> {
+        let (send, recv) = mpsc::channel();
+
+        for (message, src, local) in messages {
+            send.send(Received {
+                    message: message,
+                    src: src.parse::<SocketAddr>().unwrap(),
+                    local: local.parse::<SocketAddr>().unwrap(),
+                })
+                .unwrap();
+        }
+
+        SSDPReceiver::from_channel(recv)
+    }
+
+    #[test]
+    fn positive_collapse_by_usn() {
+        let a = response("uuid:a", "http://10.0.0.2/a.xml");
+        let b = response("uuid:b", "http://10.0.0.3/b.xml");
+        let recv = receiver(vec![(a.clone(), "10.0.0.2:1900", "10.0.0.1:5000"),
+                                 (a, "10.0.0.2:1900", "10.0.1.1:5001"),
+                                 (b, "10.0.0.3:1900", "10.0.0.1:5000")]);
+
+        let unique = recv.dedup(DedupKey::USN).collect_all();
+
+        assert_eq!(unique.len(), 2);
+        assert_eq!(unique[0].sources().len(), 1);
+        assert_eq!(unique[0].local_addrs().len(), 2);
+        assert_eq!(unique[1].local_addrs().len(), 1);
+    }
+
+    #[test]
+    fn positive_distinct_locations_kept() {
+        let first = response("uuid:a", "http://10.0.0.2/a.xml");
+        let second = response("uuid:a", "http://10.0.1.2/a.xml");
+        let recv = receiver(vec![(first, "10.0.0.2:1900", "10.0.0.1:5000"),
+                                 (second, "10.0.1.2:1900", "10.0.1.1:5001")]);
+
+        assert_eq!(recv.dedup(DedupKey::USNAndLocation).collect_all().len(), 2);
+    }
+
+    #[test]
+    fn positive_streaming_drops_duplicates() {
+        let a = response("uuid:a", "http://10.0.0.2/a.xml");
+        let recv = receiver(vec![(a.clone(), "10.0.0.2:1900", "10.0.0.1:5000"),
+                                 (a, "10.0.0.4:1900", "10.0.0.1:5000")]);
+
+        assert_eq!(recv.dedup(DedupKey::USN).count(), 1);
+    }
+
+    #[test]
+    fn positive_streaming_merges_sightings() {
+        let a = response("uuid:a", "http://10.0.0.2/a.xml");
+        let b = response("uuid:b", "http://10.0.0.3/b.xml");
+        let recv = receiver(vec![(a.clone(), "10.0.0.2:1900", "10.0.0.1:5000"),
+                                 (a, "10.0.0.2:1900", "10.0.1.1:5001"),
+                                 (b, "10.0.0.3:1900", "10.0.0.1:5000")]);
+
+        let mut dedup = recv.dedup(DedupKey::USN);
+        let first = dedup.recv().unwrap();
+        // The duplicate is dropped on the way to the next message
+        dedup.recv().unwrap();
+
+        let sightings = dedup.sightings(first.message()).unwrap();
+        assert_eq!(sightings.sources().len(), 1);
+        assert_eq!(sightings.local_addrs().len(), 2);
+    }
+
+    #[test]
+    fn positive_missing_usn_not_collapsed() {
+        let raw = "HTTP/1.1 200 OK\r\n\r\n";
+        let anonymous = SearchResponse::raw_ssdp(raw.as_bytes()).unwrap();
+        let recv = receiver(vec![(anonymous.clone(), "10.0.0.2:1900", "10.0.0.1:5000"),
+                                 (anonymous, "10.0.0.2:1900", "10.0.0.1:5000")]);
+
+        assert_eq!(recv.dedup(DedupKey::USN).collect_all().len(), 2);
+    }
+
+    #[test]
+    fn positive_forget_outside_window() {
+        let a = response("uuid:a", "http://10.0.0.2/a.xml");
+        let b = response("uuid:b", "http://10.0.0.3/b.xml");
+        let recv = receiver(vec![(a, "10.0.0.2:1900", "10.0.0.1:5000"),
+                                 (b, "10.0.0.3:1900", "10.0.0.1:5000")]);
+
+        let mut dedup = recv.dedup(DedupKey::USN).set_window(Duration::new(0, 0));
+        dedup.recv().unwrap();
+        dedup.recv().unwrap();
+
+        assert_eq!(dedup.seen.len(), 1);
+    }
+}
@@ -0,0 +1,424 @@
+//! Primitives for non-blocking SSDP message receiving.
+
+use std::io;
+use std::result::Result;
+use std::thread;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError, RecvError, RecvTimeoutError};
+use std::net::{UdpSocket, SocketAddr};
+use std::time::{Duration, Instant};
+
+use hyper::header::Headers;
+
+use SSDPResult;
+use header::HeaderRef;
+use message::MessageType;
+#[cfg(feature = "metrics")]
+use metrics;
+use net::packet::PacketReceiver;
+
+mod dedup;
+mod stats;
+
+pub use self::dedup::{DedupKey, DedupReceiver, Deduplicated};
+pub use self::stats::{ReceiveError, ReceiveStats};
+
+use self::stats::Reporter;
+
+/// Trait for constructing an object from some serialized SSDP message.
+pub trait FromRawSSDP: Sized {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<Self>;
+
+    /// Type of the message along with its headers, if it is an SSDP message.
+    ///
+    /// Lets receivers count the messages they receive in the metrics without
+    /// parsing them again.
+    fn message_headers(&self) -> Option<(MessageType, &Headers)> {
+        None
+    }
+}
+
+/// Iterator for an `SSDPReceiver`.
+pub struct SSDPIter<T> {
+    recv: SSDPReceiver<T>,
+}
+
+impl<T> SSDPIter<T> {
+    fn new(recv: SSDPReceiver<T>) -> SSDPIter<T> {
+        SSDPIter { recv: recv }
+    }
+}
+
+impl<T> Iterator for SSDPIter<T> {
+    type Item = (T, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv.recv().ok()
+    }
+}
+
+/// Iterator over a borrowed `SSDPReceiver`.
+///
+/// Borrowed receivers are iterated with this rather than `mpsc::Iter` since
+/// 0.8.0, as the channel also carries the local address each message was
+/// received at, and iteration stops at the deadline of the receiver.
+pub struct SSDPRefIter<'a, T: 'a> {
+    recv: &'a SSDPReceiver<T>,
+}
+
+impl<'a, T> Iterator for SSDPRefIter<'a, T> {
+    type Item = (T, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv.recv().ok()
+    }
+}
+
+/// A message along with the source address it was sent from and the local
+/// address it was received at, as counted in `SSDPReceiver::interface_stats`.
+pub struct Received<T> {
+    pub message: T,
+    pub src: SocketAddr,
+    pub local: SocketAddr,
+}
+
+/// A non-blocking SSDP message receiver.
+pub struct SSDPReceiver<T> {
+    recvr: Receiver<Received<T>>,
+    deadline: Option<Instant>,
+    reporter: Arc<Reporter>,
+}
+
+impl<T> SSDPReceiver<T>
+    where T: FromRawSSDP + Send + 'static
+{
+    /// Construct a receiver that receives bytes from a number of UdpSockets and
+    /// tries to construct an object T from them. If a duration is provided, the
+    /// channel will be shutdown after the specified duration.
+    ///
+    /// Due to implementation details, none of the UdpSockets should be bound to
+    /// the default route, 0.0.0.0, address.
+    pub fn new(socks: Vec<UdpSocket>, time: Option<Duration>) -> io::Result<SSDPReceiver<T>> {
+        let (send, recv) = mpsc::channel();
+
+        // Ensure `receive_packets` times out in the event the timeout packet is not received
+        for sock in socks.iter() {
+            try!(sock.set_read_timeout(time));
+        }
+
+        let receiver = SSDPReceiver::from_channel(recv);
+
+        // Spawn Receiver Threads
+        spawn_receivers(socks, send, receiver.reporter.clone());
+
+        Ok(receiver)
+    }
+}
+
+/// Construct a receiver yielding the messages sent through the returned sender,
+/// for messages that do not come from a socket, such as replayed recordings.
+#[cfg(feature = "capture")]
+pub fn channel<T>() -> (Sender<Received<T>>, SSDPReceiver<T>) {
+    let (send, recv) = mpsc::channel();
+
+    (send, SSDPReceiver::from_channel(recv))
+}
+
+/// Spawn a number of receiver threads that will receive packets, forward the
+/// bytes on to T, and send successfully constructed objects through the sender.
+fn spawn_receivers<T>(socks: Vec<UdpSocket>, sender: Sender<Received<T>>, reporter: Arc<Reporter>)
+    where T: FromRawSSDP + Send + 'static
+{
+    for sock in socks {
+        let pckt_recv = PacketReceiver::new(sock);
+        let sender = sender.clone();
+        let reporter = reporter.clone();
+
+        thread::spawn(move || {
+            receive_packets(pckt_recv, sender, reporter);
+        });
+    }
+}
+
+impl<T> SSDPReceiver<T> {
+    /// Construct a receiver that reads messages from the given channel.
+    fn from_channel(recvr: Receiver<Received<T>>) -> SSDPReceiver<T> {
+        SSDPReceiver {
+            recvr: recvr,
+            deadline: None,
+            reporter: Arc::new(Reporter::default()),
+        }
+    }
+
+    /// Set a point in time after which the receiver will no longer yield values.
+    ///
+    /// Unlike the duration given when constructing the receiver, which bounds
+    /// the time spent waiting for any single message, this bounds the total
+    /// time spent receiving.
+    pub fn set_deadline(mut self, value: Instant) -> Self {
+        self.deadline = Some(value);
+        self
+    }
+
+    /// Call the handler with every datagram received from here on that does
+    /// not parse as a message, along with the error and where it came from.
+    ///
+    /// The handler is called from the threads receiving on the sockets, so
+    /// it should return quickly.
+    pub fn set_error_handler<F>(self, handler: F) -> Self
+        where F: Fn(&ReceiveError) + Send + 'static
+    {
+        self.reporter.set_handler(Some(Box::new(handler)));
+        self
+    }
+
+    /// Counts of the datagrams received on all of the sockets of this receiver.
+    pub fn stats(&self) -> ReceiveStats {
+        self.reporter.total()
+    }
+
+    /// Counts of the datagrams received on each interface, by the local address
+    /// the datagrams arrived at.
+    ///
+    /// Sockets bound to a single interface, such as those used by searches,
+    /// count the datagrams received on that interface under the address of the
+    /// socket. On Linux, sockets bound to any address count the datagrams
+    /// under the address of the interface they arrived on, or for IPv6 under
+    /// the unspecified address scoped to the interface. Elsewhere, and for
+    /// errors reading from the socket, they are counted under the address of
+    /// the socket.
+    pub fn interface_stats(&self) -> Vec<(SocketAddr, ReceiveStats)> {
+        self.reporter.by_local()
+    }
+
+    /// Non-blocking method that attempts to read a value from the receiver.
+    pub fn try_recv(&self) -> Result<(T, SocketAddr), TryRecvError> {
+        if self.remaining().map_or(false, |remaining| remaining == Duration::new(0, 0)) {
+            return Err(TryRecvError::Disconnected);
+        }
+
+        self.recvr.try_recv().map(|received| (received.message, received.src))
+    }
+
+    /// Blocking method that reads a value from the receiver until one is available.
+    pub fn recv(&self) -> Result<(T, SocketAddr), RecvError> {
+        self.recv_received().map(|received| (received.message, received.src))
+    }
+
+    /// Blocking method that reads a value from the receiver until one is available
+    /// or the timeout has elapsed.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<(T, SocketAddr), RecvTimeoutError> {
+        let timeout = match self.remaining() {
+            Some(remaining) if remaining < timeout => remaining,
+            _ => timeout,
+        };
+
+        self.recvr.recv_timeout(timeout).map(|received| (received.message, received.src))
+    }
+
+    /// Time left until the deadline, if one was set.
+    fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| {
+            let now = Instant::now();
+
+            if deadline > now {
+                deadline - now
+            } else {
+                Duration::new(0, 0)
+            }
+        })
+    }
+
+    /// Read the next message, along with where it was received, respecting the deadline.
+    fn recv_received(&self) -> Result<Received<T>, RecvError> {
+        match self.remaining() {
+            Some(remaining) => self.recvr.recv_timeout(remaining).map_err(|_| RecvError),
+            None => self.recvr.recv(),
+        }
+    }
+}
+
+impl<T> SSDPReceiver<T>
+    where T: HeaderRef
+{
+    /// Collapse duplicate messages received on this receiver using the given key.
+    pub fn dedup(self, key: DedupKey) -> DedupReceiver<T> {
+        DedupReceiver::new(self, key)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SSDPReceiver<T> {
+    type Item = (T, SocketAddr);
+    type IntoIter = SSDPRefIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SSDPRefIter { recv: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut SSDPReceiver<T> {
+    type Item = (T, SocketAddr);
+    type IntoIter = SSDPRefIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SSDPRefIter { recv: self }
+    }
+}
+
+impl<T> IntoIterator for SSDPReceiver<T> {
+    type Item = (T, SocketAddr);
+    type IntoIter = SSDPIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SSDPIter::new(self)
+    }
+}
+
+/// Receives bytes and attempts to construct a T which will be sent through the supplied channel.
+///
+/// This should almost always be run in it's own thread.
+fn receive_packets<T>(recv: PacketReceiver, send: Sender<Received<T>>, reporter: Arc<Reporter>)
+    where T: FromRawSSDP + Send
+{
+    let local = match recv.local_addr() {
+        Ok(addr) => addr,
+        Err(_) => return,
+    };
+    reporter.register(local);
+
+    loop {
+        trace!("Waiting on packet at {}...", recv);
+        let (msg_bytes, addr, at) = match recv.recv_pckt_at() {
+            Ok((bytes, addr, at)) => (bytes, addr, at),
+            // Unix returns WouldBlock on timeout while Windows returns TimedOut
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
+                            err.kind() == io::ErrorKind::TimedOut => {
+                // We have waited for at least the desired timeout (or possibly longer)
+                trace!("Receiver at {} timed out", recv);
+                return;
+            }
+            Err(err) => {
+                debug!("Receiver at {} failed to receive: {}", recv, err);
+                reporter.dropped(local);
+                continue;
+            }
+        };
+
+        trace!("Received packet with {} bytes", msg_bytes.len());
+        reporter.received(at);
+
+        // Unwrap Will Cause A Panic If Receiver Hung Up Which Is Desired
+        match T::raw_ssdp(&msg_bytes[..]) {
+            Ok(n) => {
+                reporter.parsed(at);
+                #[cfg(feature = "metrics")]
+                {
+                    if let Some((message_type, headers)) = n.message_headers() {
+                        metrics::message_received(message_type, headers, at);
+                    }
+                }
+
+                send.send(Received {
+                        message: n,
+                        src: addr,
+                        local: at,
+                    })
+                    .unwrap()
+            }
+            Err(err) => {
+                reporter.rejected(ReceiveError {
+                    src: addr,
+                    local: at,
+                    kind: err.0,
+                    bytes: msg_bytes,
+                });
+                continue;
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, UdpSocket};
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::time::{Duration, Instant};
+
+    use message::NotifyMessage;
+    use super::SSDPReceiver;
+
+    #[test]
+    fn positive_deadline_ends_recv() {
+        let (_send, recv) = mpsc::channel();
+        let recv: SSDPReceiver<NotifyMessage> = SSDPReceiver::from_channel(recv)
+            .set_deadline(Instant::now() + Duration::from_millis(50));
+
+        assert!(recv.recv().is_err());
+    }
+
+    #[test]
+    fn positive_recv_timeout() {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv: SSDPReceiver<NotifyMessage> = SSDPReceiver::new(vec![sock], None).unwrap();
+
+        match recv.recv_timeout(Duration::from_millis(50)) {
+            Err(RecvTimeoutError::Timeout) => (),
+            _ => panic!("Failed To Time Out Waiting For A Message"),
+        }
+    }
+
+    #[test]
+    fn positive_recv_before_deadline() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv_addr = recv_sock.local_addr().unwrap();
+
+        let recv: SSDPReceiver<NotifyMessage> = SSDPReceiver::new(vec![recv_sock], None)
+            .unwrap()
+            .set_deadline(Instant::now() + Duration::new(5, 0));
+        send_sock.send_to(b"NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\r\n", recv_addr).unwrap();
+
+        assert_eq!(recv.recv().unwrap().1, send_sock.local_addr().unwrap());
+    }
+
+    #[test]
+    fn positive_report_rejected() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv_addr = recv_sock.local_addr().unwrap();
+
+        let (errors_send, errors) = mpsc::channel();
+        let recv: SSDPReceiver<NotifyMessage> = SSDPReceiver::new(vec![recv_sock], None)
+            .unwrap()
+            .set_error_handler(move |error| errors_send.send((error.src, error.bytes.clone())).unwrap());
+        send_sock.send_to(b"garbage", recv_addr).unwrap();
+        send_sock.send_to(b"NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\r\n", recv_addr).unwrap();
+
+        // Datagrams on a socket are handled in order, so the first was rejected by now
+        recv.recv().unwrap();
+        assert_eq!(errors.recv_timeout(Duration::new(5, 0)).unwrap(),
+                   (send_sock.local_addr().unwrap(), b"garbage".to_vec()));
+
+        let stats = recv.stats();
+        assert_eq!((stats.received, stats.parsed, stats.rejected_total(), stats.dropped),
+                   (2, 1, 1, 0));
+        assert_eq!(recv.interface_stats(), vec![(recv_addr, stats)]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn positive_stats_by_interface() {
+        let recv_sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = recv_sock.local_addr().unwrap().port();
+        let loopback: SocketAddr = ([127, 0, 0, 1], port).into();
+
+        let recv: SSDPReceiver<NotifyMessage> = SSDPReceiver::new(vec![recv_sock], None).unwrap();
+        send_sock.send_to(b"NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\r\n", loopback).unwrap();
+        recv.recv().unwrap();
+
+        let by_interface = recv.interface_stats();
+        let stats = by_interface.iter().find(|&&(local, _)| local == loopback).map(|&(_, ref stats)| stats);
+        assert_eq!(stats.map(|stats| (stats.received, stats.parsed)), Some((1, 1)));
+    }
+}
@@ -0,0 +1,136 @@
+//! Reporting of the datagrams passing through a receiver, including those
+//! that never make it out as messages.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use SSDPErrorKind;
+#[cfg(feature = "metrics")]
+use metrics;
+
+/// Counts of the datagrams received on an interface, or on all of the sockets
+/// of a receiver.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ReceiveStats {
+    /// Datagrams read from the socket.
+    pub received: u64,
+    /// Datagrams parsed into messages.
+    pub parsed: u64,
+    /// Datagrams that did not parse, by the description of the error.
+    pub rejected: BTreeMap<String, u64>,
+    /// Errors reading from the socket, each of which may have lost a datagram.
+    pub dropped: u64,
+}
+
+impl ReceiveStats {
+    /// Number of datagrams that did not parse, whatever the error.
+    pub fn rejected_total(&self) -> u64 {
+        self.rejected.values().sum()
+    }
+
+    fn add(&mut self, other: &ReceiveStats) {
+        self.received += other.received;
+        self.parsed += other.parsed;
+        self.dropped += other.dropped;
+
+        for (kind, count) in &other.rejected {
+            *self.rejected.entry(kind.clone()).or_insert(0) += *count;
+        }
+    }
+}
+
+/// A datagram that did not parse as a message.
+#[derive(Debug)]
+pub struct ReceiveError {
+    /// Address the datagram was sent from.
+    pub src: SocketAddr,
+    /// Local address the datagram was received at, as counted in the stats.
+    pub local: SocketAddr,
+    pub kind: SSDPErrorKind,
+    pub bytes: Vec<u8>,
+}
+
+/// Function called with every datagram that does not parse.
+pub type ErrorHandler = Box<Fn(&ReceiveError) + Send>;
+
+/// Statistics and error handler shared between a receiver and the threads
+/// receiving on its sockets.
+#[derive(Default)]
+pub struct Reporter {
+    sockets: Mutex<Vec<(SocketAddr, ReceiveStats)>>,
+    handler: Mutex<Option<ErrorHandler>>,
+}
+
+impl Reporter {
+    /// Start counting the datagrams received on the socket with the local address.
+    pub fn register(&self, local: SocketAddr) {
+        let mut sockets = self.sockets.lock().unwrap();
+
+        if !sockets.iter().any(|&(addr, _)| addr == local) {
+            sockets.push((local, ReceiveStats::default()));
+        }
+    }
+
+    pub fn set_handler(&self, handler: Option<ErrorHandler>) {
+        *self.handler.lock().unwrap() = handler;
+    }
+
+    pub fn received(&self, local: SocketAddr) {
+        self.update(local, |stats| stats.received += 1);
+    }
+
+    pub fn parsed(&self, local: SocketAddr) {
+        self.update(local, |stats| stats.parsed += 1);
+    }
+
+    pub fn dropped(&self, local: SocketAddr) {
+        self.update(local, |stats| stats.dropped += 1);
+    }
+
+    pub fn rejected(&self, error: ReceiveError) {
+        debug!("Rejected datagram from {} received at {}: {}",
+               error.src,
+               error.local,
+               error.kind);
+        #[cfg(feature = "metrics")]
+        metrics::parse_error(&error.kind);
+
+        self.update(error.local, |stats| {
+            *stats.rejected.entry(error.kind.description().to_owned()).or_insert(0) += 1
+        });
+
+        if let Some(ref handler) = *self.handler.lock().unwrap() {
+            handler(&error);
+        }
+    }
+
+    /// Counts for all of the sockets together.
+    pub fn total(&self) -> ReceiveStats {
+        let mut total = ReceiveStats::default();
+        for &(_, ref stats) in self.sockets.lock().unwrap().iter() {
+            total.add(stats);
+        }
+
+        total
+    }
+
+    /// Counts for each local address datagrams were received at.
+    pub fn by_local(&self) -> Vec<(SocketAddr, ReceiveStats)> {
+        self.sockets.lock().unwrap().clone()
+    }
+
+    fn update<F: FnOnce(&mut ReceiveStats)>(&self, local: SocketAddr, f: F) {
+        let mut sockets = self.sockets.lock().unwrap();
+
+        let index = match sockets.iter().position(|&(addr, _)| addr == local) {
+            Some(index) => index,
+            None => {
+                sockets.push((local, ReceiveStats::default()));
+                sockets.len() - 1
+            }
+        };
+
+        f(&mut sockets[index].1);
+    }
+}
@@ -0,0 +1,191 @@
+//! Serialization of header fields with serde.
+//!
+//! Headers holding a single number or string serialize as that value. Headers
+//! with a syntax of their own, such as `USN` or `ST`, serialize as the string
+//! they appear as in a message, so that they read the same in a log as on the
+//! wire and parse back into the same value. Headers with a range of valid
+//! values, such as `MX`, refuse values outside of it.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use hyper::header::{Header, HeaderFormat};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, Visitor};
+
+use FieldMap;
+use header::{LVL, Man, MX, NT, NTS, ST, Timeout, USN, WakeUp};
+
+macro_rules! serialize_as_header {
+    ($($header:ty),*) => {
+        $(
+            impl Serialize for $header {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(&HeaderValue(self))
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $header {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_str(HeaderVisitor::<$header>(PhantomData))
+                }
+            }
+        )*
+    }
+}
+
+serialize_as_header!(LVL, Man, NT, NTS, ST, Timeout, USN, WakeUp);
+
+/// Displays the value of a header as it appears in a message.
+struct HeaderValue<'a, H: 'a>(&'a H);
+
+impl<'a, H: HeaderFormat> fmt::Display for HeaderValue<'a, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_header(f)
+    }
+}
+
+/// Visitor parsing a header from the string it appears as in a message.
+struct HeaderVisitor<H>(PhantomData<H>);
+
+impl<'de, H> Visitor<'de> for HeaderVisitor<H>
+    where H: Header + HeaderFormat
+{
+    type Value = H;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a value of the {} header", H::header_name())
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<H, E> {
+        H::parse_header(&[value.as_bytes().to_vec()])
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for MX {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = try!(u8::deserialize(deserializer));
+
+        MX::new(value).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for FieldMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(FieldMapVisitor)
+    }
+}
+
+struct FieldMapVisitor;
+
+impl<'de> Visitor<'de> for FieldMapVisitor {
+    type Value = FieldMap;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a field in the form key:value")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<FieldMap, E> {
+        FieldMap::new(value).ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use FieldMap;
+    use header::{BootID, HeaderMut, HeaderRef, Location, MX, NT, NTS, ST, USN};
+    use message::{Config, EventMessage, NotifyMessage, SearchResponse};
+
+    #[test]
+    fn positive_header_as_string() {
+        let usn = USN(FieldMap::uuid("device-1"), Some(FieldMap::upnp("rootdevice")));
+
+        assert_eq!(serde_json::to_string(&usn).unwrap(),
+                   "\"uuid:device-1::upnp:rootdevice\"");
+        assert_eq!(serde_json::from_str::<USN>("\"uuid:device-1::upnp:rootdevice\"").unwrap(),
+                   usn);
+        assert_eq!(serde_json::to_string(&NTS::ByeBye).unwrap(), "\"ssdp:byebye\"");
+        assert_eq!(serde_json::from_str::<ST>("\"ssdp:all\"").unwrap(), ST::All);
+    }
+
+    #[test]
+    fn positive_value_headers() {
+        assert_eq!(serde_json::to_string(&BootID(7)).unwrap(), "7");
+        assert_eq!(serde_json::from_str::<NT>("\"upnp:rootdevice\"").unwrap(),
+                   NT(FieldMap::upnp("rootdevice")));
+        assert_eq!(serde_json::from_str::<FieldMap>("\"urn:schemas-upnp-org:device:Basic:1\"").unwrap(),
+                   FieldMap::URN("schemas-upnp-org:device:Basic:1".to_owned()));
+    }
+
+    #[test]
+    fn positive_message_round_trip() {
+        let mut message = NotifyMessage::new();
+        message.set(NTS::Alive);
+        message.set(NT(FieldMap::upnp("rootdevice")));
+        message.set(Location("http://192.168.1.10/description.xml".to_owned()));
+
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.starts_with("{\"method\":\"Notify\",\"headers\":{"));
+        assert!(json.contains("\"NT\":\"upnp:rootdevice\""));
+
+        let parsed: NotifyMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.get::<NTS>(), Some(&NTS::Alive));
+        assert_eq!(parsed.get::<Location>(), message.get::<Location>());
+        assert_eq!(parsed.to_string(), message.to_string());
+    }
+
+    #[test]
+    fn positive_body_round_trip() {
+        let mut message = EventMessage::new();
+        message.set_body("<e:propertyset/>");
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.contains("\"body\":\"<e:propertyset/>\""));
+
+        message.set_body(vec![0xff, 0xfe, b'x']);
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.contains("\"body\":[255,254,120]"));
+        assert_eq!(serde_json::from_str::<EventMessage>(&json).unwrap().body(), message.body());
+    }
+
+    #[test]
+    fn positive_mx_in_range() {
+        assert_eq!(serde_json::from_str::<MX>("5").unwrap(), MX(5));
+    }
+
+    #[test]
+    fn positive_partial_config() {
+        let config: Config = serde_json::from_str("{\"port\":1901}").unwrap();
+
+        assert_eq!(config.port, 1901);
+        assert_eq!(config.ipv4_addr, Config::new().ipv4_addr);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_message_type_mismatch() {
+        let json = serde_json::to_string(&NotifyMessage::new()).unwrap();
+
+        serde_json::from_str::<SearchResponse>(&json).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_invalid_field() {
+        serde_json::from_str::<FieldMap>("\"rootdevice\"").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_mx_out_of_range() {
+        serde_json::from_str::<MX>("0").unwrap();
+    }
+}